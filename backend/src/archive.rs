@@ -0,0 +1,99 @@
+use ring::digest::{Context, SHA256};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+/*
+Offload archive of the RedEdge captures on the Pi.
+The archive keeps the layout of the SD card under one directory per camera, so the capture logged in Influx as
+/files/SET0001/000/IMG_0001_1.tif of cam1 is archived as <archive.dir>/cam1/SET0001/000/IMG_0001_1.tif.
+A capture counts as archived only when the files of all its bands (cameras.<id>.bands) are there, each non-empty and with its checksum.
+Offloads (offload.rs) write each file under a .part name and rename it once verified, after writing a <file>.sha256 next to it in the
+format of sha256sum, so a file without its checksum is not a finished copy.
+*/
+
+// file of a band of a capture, the Influx path is the one of band 1, e.g. IMG_0001_1.tif -> IMG_0001_3.tif
+pub fn band_file(capture: &str, band: u8) -> String {
+    match capture.strip_suffix("_1.tif") {
+        Some(stem) => format!("{}_{}.tif", stem, band),
+        None => capture.to_string(),
+    }
+}
+
+// where a capture path of the camera is archived, None if the path could escape the archive
+pub fn archive_path(archive_dir: &Path, camera: &str, capture: &str) -> Option<PathBuf> {
+    let relative = Path::new(capture.trim_start_matches('/'));
+    let relative = relative.strip_prefix("files").unwrap_or(relative);
+    if relative.as_os_str().is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        return None;
+    }
+    Some(archive_dir.join(camera).join(relative))
+}
+
+// captures with at least one of their bands missing from the archive, in the order given
+pub fn missing_captures(archive_dir: &Path, camera: &str, bands: u8, captures: &[String]) -> Vec<String> {
+    captures
+        .iter()
+        .filter(|capture| {
+            !(1..=bands).all(|band| {
+                archive_path(archive_dir, camera, &band_file(capture, band)).is_some_and(|path| is_archived(&path))
+            })
+        })
+        .cloned()
+        .collect()
+}
+
+// a non-empty file with a well-formed checksum next to it, without hashing the file again
+pub fn is_archived(path: &Path) -> bool {
+    path.metadata().is_ok_and(|meta| meta.is_file() && meta.len() > 0) && stored_checksum(path).is_some()
+}
+
+// whether the file still hashes to the checksum stored next to it
+pub fn verify(path: &Path) -> bool {
+    match stored_checksum(path) {
+        Some(stored) => file_checksum(path).is_ok_and(|checksum| checksum == stored),
+        None => false,
+    }
+}
+
+// hex SHA256 read from the <file>.sha256 of an archived file
+fn stored_checksum(path: &Path) -> Option<String> {
+    let content = std::fs::read_to_string(checksum_path(path)).ok()?;
+    let checksum = content.split_whitespace().next()?;
+    (checksum.len() == 64 && checksum.bytes().all(|b| b.is_ascii_hexdigit())).then(|| checksum.to_ascii_lowercase())
+}
+
+// hex SHA256 of a file, read in chunks
+fn file_checksum(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut context = Context::new(&SHA256);
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        context.update(&buffer[..read]);
+    }
+    Ok(hex(context.finish().as_ref()))
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// checksum next to an archived file, e.g. IMG_0001_1.tif.sha256
+pub fn checksum_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".sha256")
+}
+
+// name a file is downloaded under until it is verified
+pub fn part_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".part")
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
@@ -0,0 +1,95 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+/*
+Audit log of destructive operations (camera reformats), one JSON object per line in the file set by reformat.audit_file.
+The file is append-only from the backend's point of view and is also read back to find the last reformat of a camera.
+*/
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    // RFC3339
+    pub time: String,
+    pub user: String,
+    // e.g. "reformat_prepare", "reformat_attempt", "reformat"
+    pub action: String,
+    pub camera: String,
+    // "ok", "started" for an attempt, or the error that stopped the operation
+    pub outcome: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn new(user: &str, action: &str, camera: &str, outcome: &str) -> Self {
+        Self {
+            time: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            user: user.to_string(),
+            action: action.to_string(),
+            camera: camera.to_string(),
+            outcome: outcome.to_string(),
+            detail: None,
+        }
+    }
+
+    pub fn with_detail(mut self, detail: String) -> Self {
+        self.detail = Some(detail);
+        self
+    }
+}
+
+pub struct AuditLog {
+    path: PathBuf,
+    // serializes writers so lines never interleave
+    lock: Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, lock: Mutex::new(()) }
+    }
+
+    // appends an entry; failures are logged as errors and returned, so an operation that must not run
+    // unaudited can refuse to
+    pub async fn record(&self, entry: AuditEntry) -> std::io::Result<()> {
+        info!("Audit: {:?}", entry);
+        let _guard = self.lock.lock().await;
+        let written = match serde_json::to_string(&entry) {
+            Ok(line) => append(&self.path, line + "\n").await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = &written {
+            error!("Could not write {:?} to the audit log {}: {}", entry, self.path.display(), e);
+        }
+        written
+    }
+
+    // every entry in the log, skipping lines that cannot be parsed
+    pub async fn entries(&self) -> Vec<AuditEntry> {
+        let _guard = self.lock.lock().await;
+        match tokio::fs::read_to_string(&self.path).await {
+            Ok(text) => text.lines().filter_map(|l| serde_json::from_str(l).ok()).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    // time of the last successful reformat of a camera
+    pub async fn last_reformat(&self, camera: &str) -> Option<DateTime<Utc>> {
+        self.entries()
+            .await
+            .iter()
+            .filter(|e| e.action == "reformat" && e.camera == camera && e.outcome == "ok")
+            .filter_map(|e| e.time.parse::<DateTime<Utc>>().ok())
+            .max()
+    }
+}
+
+async fn append(path: &std::path::Path, line: String) -> std::io::Result<()> {
+    let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?;
+    file.write_all(line.as_bytes()).await?;
+    file.sync_data().await
+}
@@ -0,0 +1,277 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use brig_types::{LoginRequest, Role, SessionInfo};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::pbkdf2;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+use crate::config::{AuthConfig, ConfigError};
+use crate::error::AppError;
+use crate::SharedState;
+
+/*
+Login and roles.
+Users are read at startup from a TOML file with PBKDF2 password hashes (see users.example.toml and the
+hash-password subcommand). A successful login creates an in-memory session, identified by a random token sent back
+as an HttpOnly cookie; scripts can pass the same token as "Authorization: Bearer <token>".
+Sessions do not survive a restart of the backend.
+Routes are guarded by require_viewer and require_operator, which put the Session in the request extensions.
+*/
+
+pub const SESSION_COOKIE: &str = "brig_session";
+
+const HASH_SCHEME: &str = "pbkdf2-sha256";
+const HASH_ITERATIONS: u32 = 100_000;
+// slows down password guessing, every failed login waits this long
+const LOGIN_FAILURE_DELAY: Duration = Duration::from_millis(500);
+
+static PBKDF2_ALG: pbkdf2::Algorithm = pbkdf2::PBKDF2_HMAC_SHA256;
+
+// checked for unknown usernames, so their logins take as long as the ones of known users
+const DUMMY_HASH: &str = "pbkdf2-sha256$100000$AAAAAAAAAAAAAAAAAAAAAA$AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UsersFile {
+    #[serde(default)]
+    users: Vec<User>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct User {
+    name: String,
+    role: Role,
+    // output of hash-password, e.g. pbkdf2-sha256$100000$<salt>$<hash>
+    password: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub username: String,
+    pub role: Role,
+    expires: Instant,
+}
+
+impl Session {
+    fn info(&self) -> SessionInfo {
+        SessionInfo {
+            username: self.username.clone(),
+            role: self.role,
+            expires_in: self.expires.saturating_duration_since(Instant::now()).as_secs(),
+        }
+    }
+}
+
+pub struct Auth {
+    users: Vec<User>,
+    sessions: Mutex<HashMap<String, Session>>,
+    ttl: Duration,
+    anonymous_read: bool,
+}
+
+impl Auth {
+    // reads the users file, if configured, and checks every entry
+    pub fn load(config: &AuthConfig) -> Result<Self, ConfigError> {
+        let users = match &config.users_file {
+            Some(path) => load_users(path)?,
+            None => Vec::new(),
+        };
+        if users.is_empty() {
+            info!("No users configured, operator endpoints are unavailable");
+        }
+        Ok(Self {
+            users,
+            sessions: Mutex::new(HashMap::new()),
+            ttl: Duration::from_secs(config.session_ttl_secs),
+            anonymous_read: config.anonymous_read,
+        })
+    }
+
+    // runs PBKDF2 whether the user exists or not, call it off the async runtime
+    fn login(&self, username: &str, password: &str) -> Option<(String, Session)> {
+        let user = self.users.iter().find(|u| u.name == username);
+        let verified = verify_password(user.map_or(DUMMY_HASH, |u| u.password.as_str()), password);
+        let user = user.filter(|_| verified)?;
+        let token = random_token();
+        let session = Session {
+            username: user.name.clone(),
+            role: user.role,
+            expires: Instant::now() + self.ttl,
+        };
+        let mut sessions = self.sessions.lock().unwrap();
+        // forget expired sessions while we are at it
+        let now = Instant::now();
+        sessions.retain(|_, s| s.expires > now);
+        sessions.insert(token.clone(), session.clone());
+        Some((token, session))
+    }
+
+    fn session(&self, token: &str) -> Option<Session> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions.get(token).filter(|s| s.expires > Instant::now()).cloned()
+    }
+
+    fn logout(&self, token: &str) {
+        self.sessions.lock().unwrap().remove(token);
+    }
+}
+
+fn load_users(path: &Path) -> Result<Vec<User>, ConfigError> {
+    let name = path.display().to_string();
+    let text = std::fs::read_to_string(path).map_err(|e| ConfigError::Io(name.clone(), e))?;
+    let file = toml::from_str::<UsersFile>(&text).map_err(|e| ConfigError::Parse(name.clone(), e))?;
+    let mut problems = Vec::new();
+    for (i, user) in file.users.iter().enumerate() {
+        if user.name.trim().is_empty() {
+            problems.push(format!("{}: user {} has an empty name", name, i + 1));
+        }
+        if file.users.iter().filter(|u| u.name == user.name).count() > 1 {
+            problems.push(format!("{}: user \"{}\" is defined more than once", name, user.name));
+        }
+        if parse_hash(&user.password).is_none() {
+            problems.push(format!("{}: password of \"{}\" is not a {} hash, create one with hash-password", name, user.name, HASH_SCHEME));
+        }
+    }
+    problems.dedup();
+    if problems.is_empty() {
+        Ok(file.users)
+    } else {
+        Err(ConfigError::Invalid(problems))
+    }
+}
+
+// 256 random bits, url safe, for session and confirmation tokens
+pub fn random_token() -> String {
+    let mut token = [0u8; 32];
+    SystemRandom::new().fill(&mut token).expect("no system random source");
+    URL_SAFE_NO_PAD.encode(token)
+}
+
+// hashes a password in the format expected in the users file
+pub fn hash_password(password: &str) -> String {
+    let mut salt = [0u8; 16];
+    SystemRandom::new().fill(&mut salt).expect("no system random source");
+    let mut hash = [0u8; 32];
+    let iterations = NonZeroU32::new(HASH_ITERATIONS).unwrap();
+    pbkdf2::derive(PBKDF2_ALG, iterations, &salt, password.as_bytes(), &mut hash);
+    format!("{}${}${}${}", HASH_SCHEME, HASH_ITERATIONS, URL_SAFE_NO_PAD.encode(salt), URL_SAFE_NO_PAD.encode(hash))
+}
+
+// splits scheme$iterations$salt$hash
+fn parse_hash(stored: &str) -> Option<(NonZeroU32, Vec<u8>, Vec<u8>)> {
+    let mut parts = stored.split('$');
+    if parts.next()? != HASH_SCHEME {
+        return None;
+    }
+    let iterations = NonZeroU32::new(parts.next()?.parse().ok()?)?;
+    let salt = URL_SAFE_NO_PAD.decode(parts.next()?).ok()?;
+    let hash = URL_SAFE_NO_PAD.decode(parts.next()?).ok()?;
+    if parts.next().is_some() || salt.is_empty() || hash.is_empty() {
+        return None;
+    }
+    Some((iterations, salt, hash))
+}
+
+// constant time comparison against the stored hash
+fn verify_password(stored: &str, password: &str) -> bool {
+    match parse_hash(stored) {
+        Some((iterations, salt, hash)) => pbkdf2::verify(PBKDF2_ALG, iterations, &salt, password.as_bytes(), &hash).is_ok(),
+        None => false,
+    }
+}
+
+// session token from the cookie or the Authorization header
+fn request_token(headers: &HeaderMap) -> Option<String> {
+    if let Some(bearer) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    {
+        return Some(bearer.trim().to_string());
+    }
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|c| c.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value.to_string())
+}
+
+fn request_session(state: &SharedState, headers: &HeaderMap) -> Option<Session> {
+    request_token(headers).and_then(|token| state.auth.session(&token))
+}
+
+fn session_cookie(token: &str, max_age: u64) -> HeaderValue {
+    let cookie = format!("{}={}; Path=/; HttpOnly; SameSite=Strict; Max-Age={}", SESSION_COOKIE, token, max_age);
+    HeaderValue::from_str(&cookie).expect("session token is url safe base64")
+}
+
+// lets the request through if the caller may read data, as a logged in user or anonymously if allowed
+pub async fn require_viewer(State(state): State<SharedState>, mut request: Request, next: Next) -> Response {
+    match request_session(&state, request.headers()) {
+        Some(session) => {
+            request.extensions_mut().insert(session);
+            next.run(request).await
+        }
+        None if state.auth.anonymous_read => next.run(request).await,
+        None => AppError::Unauthorized("log in to read data".to_string()).into_response(),
+    }
+}
+
+// lets the request through only for logged in operators
+pub async fn require_operator(State(state): State<SharedState>, mut request: Request, next: Next) -> Response {
+    match request_session(&state, request.headers()) {
+        Some(session) if session.role >= Role::Operator => {
+            info!("{} {} by {}", request.method(), request.uri().path(), session.username);
+            request.extensions_mut().insert(session);
+            next.run(request).await
+        }
+        Some(session) => AppError::Forbidden(format!("{} is not an operator", session.username)).into_response(),
+        None => AppError::Unauthorized("log in as an operator to do this".to_string()).into_response(),
+    }
+}
+
+// checks the credentials and starts a session
+pub async fn login(State(state): State<SharedState>, Json(request): Json<LoginRequest>) -> Result<Response, AppError> {
+    let auth = state.clone();
+    let (username, password) = (request.username.clone(), request.password);
+    let login = tokio::task::spawn_blocking(move || auth.auth.login(&username, &password))
+        .await
+        .map_err(|e| AppError::Parse("the login".to_string(), e.to_string()))?;
+    let Some((token, session)) = login else {
+        info!("Failed login for {}", request.username);
+        tokio::time::sleep(LOGIN_FAILURE_DELAY).await;
+        return Err(AppError::Unauthorized("wrong username or password".to_string()));
+    };
+    info!("{} logged in as {:?}", session.username, session.role);
+    let cookie = session_cookie(&token, state.auth.ttl.as_secs());
+    Ok(([(header::SET_COOKIE, cookie)], Json(session.info())).into_response())
+}
+
+// ends the current session, if any
+pub async fn logout(State(state): State<SharedState>, headers: HeaderMap) -> Response {
+    if let Some(token) = request_token(&headers) {
+        state.auth.logout(&token);
+    }
+    [(header::SET_COOKIE, session_cookie("", 0))].into_response()
+}
+
+// current user, null when not logged in
+pub async fn session(State(state): State<SharedState>, headers: HeaderMap) -> Json<Option<SessionInfo>> {
+    Json(request_session(&state, &headers).map(|s| s.info()))
+}
@@ -1,193 +1,143 @@
-use reqwest::Client;
 use axum::{
-    extract::Query, http::StatusCode, Json, body::Bytes};
+    extract::{Path, Query, State}, http::HeaderMap, response::Response, Extension, Json};
 use serde::{Serialize, Deserialize};
-use csv::ReaderBuilder;
-use std::io::Cursor;
-use tracing::info;
-use std::collections::HashSet;
-use image::codecs::jpeg::JpegEncoder;
-use image::{load_from_memory_with_format, ImageFormat};
-
-
-use crate::util;
-
-#[derive(Debug, Deserialize)]
-struct SvInfo {
-    azimuth: Option<f64>,
-    channel: Option<i32>,
-    cno: Option<i32>,
-    diff_flag: Option<bool>,
-    elevation: Option<f64>,
-    orbit_info: Option<bool>,
-    orbit_is_eph: Option<bool>,
-    quality: Option<i32>,
-    sv_healthy: Option<bool>,
-    svid: Option<i32>,
-    used_flag: Option<bool>,
-}
+use brig_types::{
+    BandInfo, CameraFoldersParams, CameraInfo, CaptureId, CaptureInfo, CaptureListParams, CapturePage, CameraReach, CameraSpace, CaptureParams, RedEdgeStatus, ReformatConfirm, ReformatPlan, ReformatResponse,
+};
+use chrono::{DateTime, NaiveTime, SecondsFormat, TimeDelta, Utc};
+use tracing::{debug, info, warn};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration as StdDuration, Instant};
+use tokio::task;
 
-#[derive(Debug, Deserialize)]
-struct RedEdgeStatus {
-    sd_gb_free: Option<f64>,
-    sd_gb_total: Option<f64>,
-    sd_gb_type: Option<String>,
-    sd_warn: Option<bool>,
-    sd_status: Option<String>,
-    bus_volts: Option<f64>,
-    gps_used_sats: Option<i32>,
-    gps_vis_sats: Option<i32>,
-    gps_warn: Option<bool>,
-    gps_lat: Option<f64>,
-    gps_lon: Option<f64>,
-    gps_type: Option<String>,
-    course: Option<f64>,
-    alt_agl: Option<f64>,
-    alt_msl: Option<f64>,
-    p_acc: Option<f64>,
-    utc_time: Option<String>,
-    vel_2d: Option<f64>,
-    sv_info: Option<Vec<SvInfo>>,
-    auto_cap_active: Option<bool>,
-    dls_status: Option<String>,
-    gps_time: Option<String>,
-    utc_time_valid: Option<bool>,
-    time_source: Option<String>,
-}
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct CameraSpace {
-    cam1_free: f64,
-    cam1_total: f64,
-    cam2_free: f64,
-    cam2_total: f64,
-}
+use crate::archive;
+use crate::audit::AuditEntry;
+use crate::auth::{random_token, Session};
+use crate::config::CameraConfig;
+use crate::error::AppError;
+use crate::influx::Duration;
+use crate::thumbnails;
+use crate::{parse_date, parse_time_param, AppState, SharedState};
 
-#[derive(Deserialize)]
-pub struct CameraFoldersParams {
-    camera: String,
-    date: String,
+#[derive(Serialize, Deserialize, Debug)]
+struct ReformatRequest{
+    erase_all_data: bool,
 }
 
-#[derive(Deserialize)]
-pub struct FormatParams {
-    camera: String,
+// registry entry of a camera, UnknownCamera if it is not configured
+pub fn camera_config<'a>(state: &'a AppState, camera: &str) -> Result<&'a CameraConfig, AppError> {
+    state.config.camera(camera).ok_or_else(|| AppError::UnknownCamera(camera.to_string()))
 }
 
-#[derive(Deserialize)]
-pub struct CaptureParams {
-    cam: String,
-    band: String,
+// the configured cameras, with their bands
+pub async fn cameras_call(State(state): State<SharedState>) -> Json<Vec<CameraInfo>> {
+    let cameras = state.config.cameras.iter().map(|(id, camera)| CameraInfo {
+        id: id.clone(),
+        name: camera.name.clone(),
+        model: camera.model.clone(),
+        bands: camera.bands.iter().zip(1..).map(|(band, index)| BandInfo {
+            band: index,
+            name: band.name.clone(),
+            wavelength_nm: band.wavelength_nm,
+            bandwidth_nm: band.bandwidth_nm,
+        }).collect(),
+    });
+    Json(cameras.collect())
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct ReformatRequest{
-    erase_all_data: bool,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ReformatResponse{
-    message: String,
-    reformat_status: String,
+// queries RedEdge HTTP APIs for the SD space of every camera, all cameras at once so one that is off
+// only costs its own timeout; a camera that fails is reported in its entry instead of failing the request
+pub async fn camera_status_call(State(state): State<SharedState>) -> Json<Vec<CameraSpace>> {
+    let polls: Vec<_> = state.config.cameras.keys().map(|camera| {
+        let (state, camera) = (state.clone(), camera.clone());
+        (camera.clone(), task::spawn(async move { rededge_status(&state, &camera).await }))
+    }).collect();
+    let mut spaces = Vec::new();
+    for (camera, poll) in polls {
+        let result = poll.await.unwrap_or_else(|e| Err(AppError::Parse(format!("the status of {}", camera), e.to_string())));
+        spaces.push(camera_space(camera, result));
+    }
+    Json(spaces)
 }
 
-// queries RedEdge HTTP APIs for camera status
-pub async fn camera_status_call() -> Result<Json<CameraSpace>, StatusCode> {
-    let client: Client = Client::new();
-    
-    let mut cam1_fr = 0.0;
-    let mut cam1_tot = 0.0;
-    let mut cam2_fr = 0.0;
-    let mut cam2_tot = 0.0;
-
-    // camera 1 (red)
-    match client.get("http://192.168.1.83:80/status").send().await {
-        Ok(response) => match response.json::<RedEdgeStatus>().await {
-            Ok(data) => {
-                cam1_fr = data.sd_gb_free.unwrap();
-                cam1_tot = data.sd_gb_total.unwrap();
-            },
-            Err(_) => {
-                cam1_fr = -1.0;
-                cam1_tot =-1.0;
-            },
+fn camera_space(camera: String, result: Result<RedEdgeStatus, AppError>) -> CameraSpace {
+    match result {
+        Ok(status) => CameraSpace {
+            camera,
+            state: CameraReach::Ok,
+            free_gb: status.sd_gb_free,
+            total_gb: status.sd_gb_total,
+            message: None,
         },
-        Err(_) => {
-            cam1_fr = -1.0;
-            cam1_tot =-1.0;
+        Err(e) => CameraSpace {
+            camera,
+            state: match e {
+                AppError::CameraTimeout(..) => CameraReach::Timeout,
+                AppError::CameraUnreachable(..) => CameraReach::Unreachable,
+                _ => CameraReach::Error,
+            },
+            free_gb: None,
+            total_gb: None,
+            message: Some(e.to_string()),
         },
     }
+}
 
-    // camera 2 (blue)
-    match client.get("http://192.168.3.83:80/status").send().await {
-        Ok(response) => match response.json::<RedEdgeStatus>().await {
-            Ok(data) => {
-                cam2_fr = data.sd_gb_free.unwrap();
-                cam2_tot = data.sd_gb_total.unwrap();
-            },
-            Err(_) => {
-                cam2_fr = -1.0;
-                cam2_tot =-1.0;
-            },
-        },
-        Err(_) => {
-            cam2_fr = -1.0;
-            cam2_tot =-1.0;
-        },
+// full status of one camera: power, GPS fix and satellites, DLS, SD card and capture state
+pub async fn camera_health(State(state): State<SharedState>, Path(camera): Path<String>) -> Result<Json<RedEdgeStatus>, AppError> {
+    rededge_status(&state, &camera).await.map(Json)
+}
+
+// status page of a RedEdge camera
+async fn rededge_status(state: &AppState, camera: &str) -> Result<RedEdgeStatus, AppError> {
+    let config = camera_config(state, camera)?;
+    let response = state.client
+        .get(format!("{}/status", config.url))
+        .timeout(config.timeout())
+        .send()
+        .await
+        .map_err(|e| AppError::camera(camera, e))?;
+    if !response.status().is_success() {
+        return Err(AppError::CameraResponse(camera.to_string(), response.status().to_string()));
     }
-    let status = CameraSpace {
-        cam1_free: cam1_fr,
-        cam1_total: cam1_tot,
-        cam2_free: cam2_fr,
-        cam2_total: cam2_tot,
-    };
-    
-    Ok(Json(status))
+    response.json::<RedEdgeStatus>().await
+        .map_err(|e| AppError::Parse(format!("the status of {}", camera), e.to_string()))
 }
 
 // queries Influx for captures of given camera on a certain date, returns the folder generated on said date
-pub async fn camera_folders_call(Query(params): Query<CameraFoldersParams>) -> Result<Json<Vec<String>>, StatusCode> {
-    let camera = params.camera;
-    let req_date = params.date;
-    
-    // Format the date strings for the Flux query
-    let start_time = format!("{}T00:00:00Z", req_date);
-    let end_time = format!("{}T23:59:59Z", req_date);
-
-    // Build the Flux query
-    let flux_query = format!(
-        r#"from(bucket: "asv_data")
-          |> range(start: {}, stop: {})
-          |> filter(fn: (r) => r._measurement == "micasense_data")
-          |> filter(fn: (r) => r._field == "capture")
-          |> filter(fn: (r) => r.camera == "{}")"#,
-        start_time, end_time, camera
-    );
-
-    let response_text: String = util::post_influx_query(flux_query).await?;
-    if response_text.is_empty() {
-        return Err(StatusCode::NOT_FOUND);
+pub async fn camera_folders_call(
+    State(state): State<SharedState>,
+    Path(camera): Path<String>,
+    Query(params): Query<CameraFoldersParams>,
+) -> Result<Json<Vec<String>>, AppError> {
+    camera_config(&state, &camera)?;
+    let day = parse_date(&params.date)?;
+    let day_start = day.and_time(NaiveTime::MIN).and_utc();
+
+    let flux_query = state.influx.flux()
+        .range(day_start, day_start + TimeDelta::days(1))
+        .measurement("micasense_data")
+        .field("capture")
+        .tag("camera", &camera)
+        .build();
+
+    let records = state.influx.query(&flux_query).await?;
+    if records.is_empty() {
+        return Err(AppError::NoData(format!("no captures by {} on {}", camera, params.date)));
     }
 
-    println!("Raw CSV Response:\n{}", response_text);
     let mut data_points = Vec::new();
-    let mut reader = ReaderBuilder::new()
-        .has_headers(true)
-        .from_reader(Cursor::new(response_text));
-
-        for result in reader.records() {
-            if let Ok(record) = result {
-                if let Some(set) = record.get(6) {
-                    if let Some(info) = extract_set_info(set) {
-                        data_points.push(info);
-                    } else {
-                        println!("No set info could be extracted from: {}", set);
-                    }
-                }
-            }
+    for set in records.iter().filter_map(|r| r.value()) {
+        if let Some(info) = extract_set_info(set) {
+            data_points.push(info);
+        } else {
+            warn!("No set info could be extracted from: {}", set);
         }
+    }
     let unique_data_points: Vec<String> = data_points.into_iter().collect::<HashSet<_>>().into_iter().collect();
-    println!("Sent data:\n{:?}", unique_data_points);
+    debug!("Sent sets: {:?}", unique_data_points);
     Ok(Json(unique_data_points))
 
 }
@@ -195,170 +145,326 @@ pub async fn camera_folders_call(Query(params): Query<CameraFoldersParams>) -> R
 fn extract_set_info(tag: &str) -> Option<String> {
     // Extract /SETXXXX/YYY from files/SETXXXX/YYY/IMG_ZZZZ.tif
     let parts: Vec<&str> = tag.split('/').collect();
-    if parts.len() >= 3 {
+    if parts.len() >= 4 {
         // Return the SET and directory part (/SETXXXX/YYY)
         return Some(format!("{}/{}", parts[2], parts[3]));
     }
     None
 }
 
-// Given a camera and a band, returns (if it was taken in the last hour) the JPEG bytes of the last capture in the given band by the camera
-pub async fn get_last_capture(Query(params): Query<CaptureParams>) -> Result<Bytes, StatusCode>{
-    if (params.cam != "cam1") && (params.cam != "cam2"){
-        return Err(StatusCode::NOT_FOUND)
+// Given a camera and a band, returns (if it was taken in the last hour) the JPEG thumbnail of the last capture in the
+// given band by the camera; the url shows a new capture every time, so browsers must revalidate with the ETag
+pub async fn get_last_capture(
+    State(state): State<SharedState>,
+    Path(camera): Path<String>,
+    Query(params): Query<CaptureParams>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let bands = camera_config(&state, &camera)?.bands.len();
+    let band = match params.band.parse::<usize>() {
+        Ok(num) if (1..=bands).contains(&num) => num as u8,
+        _ => return Err(AppError::BadRequest(format!("band must be 1 to {}, got {}", bands, params.band))),
+    };
+    let Some((_, filename)) = newest_capture(&state, &camera, Duration::from_static("-1h")).await? else {
+        return Err(AppError::NoData(format!("{} has no capture in the last hour", camera)))
+    };
+    let id = CaptureId::from_path(&camera, &filename)
+        .ok_or_else(|| AppError::Parse(format!("the capture path of {}", camera), filename.clone()))?;
+    let thumbnail = thumbnails::thumbnail(&state, &id, band).await?;
+    Ok(thumbnails::respond(thumbnail, &headers, "no-cache"))
+}
+
+// a band file of a capture, from the offload archive if it is there, otherwise from the camera
+pub async fn load_band_file(state: &AppState, camera: &str, file: &str) -> Result<Vec<u8>, AppError> {
+    let config = camera_config(state, camera)?;
+    if let Some(path) = archive::archive_path(&state.config.archive.dir, camera, file) {
+        if let Ok(bytes) = tokio::fs::read(&path).await {
+            return Ok(bytes);
+        }
     }
-    let mut filename = get_last_capture_filename(&params.cam).await?;
-    let mut cam_url = "192.168.1.83";
-    if params.cam == "cam2" {
-        cam_url = "192.168.3.83";
+    let response = state.client
+        .get(format!("{}{}", config.url, file))
+        .timeout(config.timeout())
+        .send()
+        .await
+        .map_err(|e| AppError::camera(camera, e))?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(AppError::NoData(format!("{} is neither on {} nor in the archive", file, camera)));
     }
-    if filename == "" {
-        return Err(StatusCode::NOT_FOUND)
+    if !response.status().is_success() {
+        return Err(AppError::CameraResponse(camera.to_string(), format!("{} for {}", response.status(), file)));
     }
-    
-    match params.band.parse::<i32>(){
-        Ok(num) => {
-            if num < 1 || num > 5 {
-                return Err(StatusCode::NOT_FOUND);
-            } else if num > 1 && num <= 5 {
-                filename.truncate(filename.len() - 5);
-                filename = format!("{}{}.tif", filename, num);
-            }
-        },
-        Err(_) => return Err(StatusCode::NOT_FOUND)
+    let bytes = response.bytes().await.map_err(|e| AppError::camera(camera, e))?;
+    Ok(bytes.to_vec())
+}
+
+// Queries InfluxDB for the time and filename of the last capture of a given camera since lookback
+pub async fn newest_capture(state: &AppState, camera: &str, lookback: Duration) -> Result<Option<(DateTime<Utc>, String)>, AppError> {
+    let flux_query = state.influx.flux()
+        .range_since(lookback)
+        .measurement("micasense_data")
+        .field("capture")
+        .tag("camera", camera)
+        .last()
+        .yield_as("last")
+        .build();
+    let records = state.influx.query(&flux_query).await?;
+    Ok(records
+        .iter()
+        .filter_map(|r| Some((r.time()?, r.value()?.to_string())))
+        .next_back())
+}
+
+// newest capture of a camera in the last week, for the pages working on one capture
+pub async fn latest_capture(State(state): State<SharedState>, Path(camera): Path<String>) -> Result<Json<CaptureInfo>, AppError> {
+    camera_config(&state, &camera)?;
+    let Some((time, path)) = newest_capture(&state, &camera, Duration::from_static("-7d")).await? else {
+        return Err(AppError::NoData(format!("{} has no capture in the last week", camera)));
+    };
+    let id = CaptureId::from_path(&camera, &path)
+        .ok_or_else(|| AppError::Parse(format!("the capture path of {}", camera), path.clone()))?;
+    Ok(Json(CaptureInfo {
+        id: id.to_string(),
+        camera,
+        time: time.to_rfc3339_opts(SecondsFormat::Secs, true),
+        path,
+    }))
+}
+
+// paths of captures missing from the archive listed in a plan, the count covers the rest
+const NOT_ARCHIVED_EXAMPLES: usize = 10;
+
+// reformats waiting for confirmation, at most one per camera
+#[derive(Default)]
+pub struct PendingReformats(Mutex<HashMap<String, PendingReformat>>);
+
+struct PendingReformat {
+    token: String,
+    user: String,
+    expires: Instant,
+    // captures the plan found missing from the offload archive
+    not_archived: u64,
+}
+
+impl PendingReformats {
+    fn insert(&self, camera: &str, pending: PendingReformat) {
+        self.0.lock().unwrap().insert(camera.to_string(), pending);
     }
-    let micasense_url = format!("http://{}{}", cam_url, filename);
-    let client = Client::new();
-    println!("url: {}", micasense_url);
-    
-    match client.get(&micasense_url).send().await {
-        Ok(response) => {
-            // Check if the response was successful
-            if response.status().is_success() {
-                match response.bytes().await {
-                    Ok(bytes) => {
-                        // Convert TIF to JPEG
-                        match convert_tif_to_jpeg(&bytes) {
-                            Ok(jpeg_bytes) => {
-                                return Ok(jpeg_bytes);
-                            }
-                            Err(_) => {
-                                // Error during conversion
-                                return Err(StatusCode::INTERNAL_SERVER_ERROR);
-                            }
-                        }
-                    }
-                    Err(_) => {
-                        return Err(StatusCode::NOT_FOUND);
-                    }
-                }
-            } else {
-                return Err(StatusCode::NOT_FOUND);
-            }
-        }
-        Err(_) => {
-            return Err(StatusCode::NOT_FOUND);
+
+    // consumes the pending reformat of a camera if the token is the one given to that user and has not expired,
+    // a token works once but a wrong one leaves the reformat waiting for its operator
+    fn take(&self, camera: &str, token: &str, user: &str) -> Option<PendingReformat> {
+        let mut pending = self.0.lock().unwrap();
+        match pending.get(camera) {
+            Some(p) if p.token == token && p.user == user && p.expires > Instant::now() => pending.remove(camera),
+            _ => None,
         }
     }
-    //Ok(filename_1)
 }
 
-// Queries InfluxDB for the last capture's filename in the last hour of a given camera
-async fn get_last_capture_filename(camera: &str) -> Result<String, StatusCode> {
-    let flux_query = format!(
-        r#"from(bucket: "asv_data")
-            |> range(start: -1h)
-            |> filter(fn: (r) => r._measurement == "micasense_data")
-            |> filter(fn: (r) => r._field == "capture")
-            |> filter(fn: (r) => r.camera == "{}")
-            |> last()
-            |> yield(name: "last")"#,
-        camera
-    );
-    let response: String = util::post_influx_query(flux_query).await?;
-    println!("Raw CSV Response:\n{}", response);
-    if response.is_empty() {
-        return Err(StatusCode::NOT_FOUND);
+// paths of the captures recorded in Influx for a camera in a time range, oldest first
+pub async fn capture_paths(state: &AppState, camera: &str, start: DateTime<Utc>, stop: DateTime<Utc>) -> Result<Vec<String>, AppError> {
+    Ok(capture_records(state, camera, start, stop).await?.into_iter().map(|(_, path)| path).collect())
+}
+
+// time and band 1 path of the captures of a camera in a range, oldest first
+pub async fn capture_records(state: &AppState, camera: &str, start: DateTime<Utc>, stop: DateTime<Utc>) -> Result<Vec<(DateTime<Utc>, String)>, AppError> {
+    let query = state.influx.flux()
+        .range(start, stop)
+        .measurement("micasense_data")
+        .field("capture")
+        .tag("camera", camera)
+        .keep(&["_time", "_value"])
+        .build();
+    let records = state.influx.query(&query).await?;
+    let mut captures: Vec<_> = records.iter().filter_map(|r| Some((r.time()?, r.value()?.to_string()))).collect();
+    captures.sort_by_key(|(time, _)| *time);
+    Ok(captures)
+}
+
+// captures per page of /api/cameras/:camera_id/captures unless asked otherwise, and the most that can be asked for
+const CAPTURES_PER_PAGE: u32 = 24;
+const MAX_CAPTURES_PER_PAGE: u32 = 200;
+// the capture paths CaptureId::from_path understands, so a page is never shortened by a malformed one
+pub const CAPTURE_PATH: &str = r"(^|/)SET[0-9]+/[0-9]+/IMG_[0-9]+(_[^/]*)?$";
+
+// one page of the captures of a camera in a range, for browsing them
+pub async fn camera_captures(
+    State(state): State<SharedState>,
+    Path(camera): Path<String>,
+    Query(params): Query<CaptureListParams>,
+) -> Result<Json<CapturePage>, AppError> {
+    camera_config(&state, &camera)?;
+    let now = Utc::now();
+    let start = parse_time_param(params.start.as_deref().unwrap_or("-24h"), now)?;
+    let stop = match params.stop.as_deref() {
+        Some(stop) => parse_time_param(stop, now)?,
+        None => now,
+    };
+    if start >= stop {
+        return Err(AppError::BadRequest("start must be before stop".to_string()));
     }
-    let mut res = "".to_string();
-    let mut reader = ReaderBuilder::new()
-    .has_headers(true)
-    .from_reader(Cursor::new(response));
-    for result in reader.records() {
-        if let Ok(record) = result {
-            if let Some(str) = record.get(6){
-                res = str.to_string();
-            }
-        }
+    let per_page = params.per_page.unwrap_or(CAPTURES_PER_PAGE);
+    if !(1..=MAX_CAPTURES_PER_PAGE).contains(&per_page) {
+        return Err(AppError::BadRequest(format!("per_page must be 1 to {}", MAX_CAPTURES_PER_PAGE)));
     }
-    Ok(res)
+    let page = params.page.unwrap_or(0);
+    let query = state.influx.flux()
+        .range(start, stop)
+        .measurement("micasense_data")
+        .field("capture")
+        .tag("camera", &camera)
+        .value_matches(CAPTURE_PATH)
+        .keep(&["_time", "_value"]);
+    let offset = u64::from(page).saturating_mul(u64::from(per_page));
+    let page_query = query.clone().sort_by_time().limit(u64::from(per_page), offset).build();
+    let count_query = query.count().build();
+    let (records, counts) = tokio::try_join!(state.influx.query(&page_query), state.influx.query(&count_query))?;
+    let captures = records
+        .iter()
+        .filter_map(|r| {
+            let path = r.value()?;
+            Some(CaptureInfo {
+                id: CaptureId::from_path(&camera, path)?.to_string(),
+                camera: camera.clone(),
+                time: r.time()?.to_rfc3339_opts(SecondsFormat::Secs, true),
+                path: path.to_string(),
+            })
+        })
+        .collect();
+    let total = counts.iter().filter_map(|r| r.value()?.parse::<u64>().ok()).sum();
+    Ok(Json(CapturePage { captures, total, page, per_page }))
 }
 
+// first step of a reformat: reports what would be erased and hands out a short-lived confirmation token
+pub async fn reformat_prepare(
+    State(state): State<SharedState>,
+    Extension(session): Extension<Session>,
+    Path(camera): Path<String>,
+) -> Result<Json<ReformatPlan>, AppError> {
+    let result = plan_reformat(&state, &session, &camera).await;
+    let entry = AuditEntry::new(&session.username, "reformat_prepare", &camera, "ok");
+    let entry = match &result {
+        Ok(plan) => entry.with_detail(format!(
+            "{} captures since {} ({} not archived), {} of {} GB free",
+            plan.captures,
+            plan.since.as_deref().unwrap_or("the first record"),
+            plan.not_archived,
+            gb(plan.sd_gb_free),
+            gb(plan.sd_gb_total)
+        )),
+        Err(e) => AuditEntry { outcome: e.to_string(), ..entry },
+    };
+    let _ = state.audit.record(entry).await;
+    result.map(Json)
+}
 
-// Converts TIFF image bytes to JPEG bytes
-fn convert_tif_to_jpeg(tif_bytes: &[u8]) -> Result<axum::body::Bytes, image::ImageError> {
-    //println!("loading bytes...");
-    // load the TIF image from bytes
-    let img_res = load_from_memory_with_format(tif_bytes, ImageFormat::Tiff);
-    //let mut jpeg_buffer = Vec::new();
-    match img_res {
-        Ok(img) => {
-            println!("converting...");
-            // write the image to the buffer in JPEG format
-            let mut default = vec![];
-            let encoder = JpegEncoder::new(&mut default);
-            match img.to_rgb8().write_with_encoder(encoder) {
-                Ok(_) => 
-                println!("all ok"),
-                Err(e) => println!("error: {:?}", e)
-            }
-            // match img.write_to(&mut Cursor::new(&mut jpeg_buffer), ImageFormat::Jpeg) {
-            //     Ok(_) => println!("all ok"),
-            //     Err(e) => println!("error: {:?}", e)
-            // }
-            
-            // convert the buffer to bytes
-            Ok(axum::body::Bytes::from(default))
-        }
-        Err (e) => {
-            println!("loading error: {:?}", e);
-            return Err(e)
-        }
-    }
-    
-    // Create a buffer to store the JPEG image
-    
+fn gb(value: Option<f64>) -> String {
+    value.map(|v| format!("{:.2}", v)).unwrap_or_else(|| "?".to_string())
 }
 
-pub async fn format_sd(Query(params): Query<FormatParams>) -> Result<Json<ReformatResponse>, StatusCode> {
-    
-    let mut url = "";
-    if params.camera == "cam1" {
-        url = "http://192.168.1.83/reformatsdcard";
-    } else if params.camera == "cam2" {
-        url = "http://192.168.3.83/reformatsdcard";
-    } else {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
+async fn plan_reformat(state: &AppState, session: &Session, camera: &str) -> Result<ReformatPlan, AppError> {
+    // the camera has to answer now, or the confirmation would fail anyway
+    let status = rededge_status(state, camera).await?;
+    let since = state.audit.last_reformat(camera).await;
+    let captures = capture_paths(state, camera, since.unwrap_or(DateTime::UNIX_EPOCH), Utc::now()).await?;
+    let captures_count = captures.len() as u64;
+    // stats every band file of every capture, keep it off the async workers
+    let archive_dir = state.config.archive.dir.clone();
+    let archive_camera = camera.to_string();
+    let bands = camera_config(state, camera)?.bands.len() as u8;
+    let not_archived = task::spawn_blocking(move || archive::missing_captures(&archive_dir, &archive_camera, bands, &captures))
+        .await
+        .map_err(|e| AppError::Parse("the offload archive".to_string(), e.to_string()))?;
+    let ttl = StdDuration::from_secs(state.config.reformat.confirm_ttl_secs);
+    let token = random_token();
+    state.reformats.insert(camera, PendingReformat {
+        token: token.clone(),
+        user: session.username.clone(),
+        expires: Instant::now() + ttl,
+        not_archived: not_archived.len() as u64,
+    });
+    Ok(ReformatPlan {
+        camera: camera.to_string(),
+        token,
+        expires_in: ttl.as_secs(),
+        sd_gb_free: status.sd_gb_free,
+        sd_gb_total: status.sd_gb_total,
+        captures: captures_count,
+        since: since.map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        not_archived: not_archived.len() as u64,
+        not_archived_examples: not_archived.into_iter().take(NOT_ARCHIVED_EXAMPLES).collect(),
+    })
+}
 
-    let client = Client::new();
+// second step of a reformat: erases the SD card if the token of the prepare step is echoed in time by the same user
+pub async fn reformat_confirm(
+    State(state): State<SharedState>,
+    Extension(session): Extension<Session>,
+    Path(camera): Path<String>,
+    Json(params): Json<ReformatConfirm>,
+) -> Result<Json<ReformatResponse>, AppError> {
+    let override_backup = params.override_backup;
+    let result = confirm_reformat(&state, &session, &camera, params).await;
+    let entry = match &result {
+        Ok(res) => AuditEntry::new(&session.username, "reformat", &camera, "ok").with_detail(format!(
+            "{} ({}){}",
+            res.message,
+            res.reformat_status,
+            if override_backup { ", backup check overridden" } else { "" }
+        )),
+        Err(e) => AuditEntry::new(&session.username, "reformat", &camera, &e.to_string()),
+    };
+    let _ = state.audit.record(entry).await;
+    result.map(Json)
+}
+
+async fn confirm_reformat(state: &AppState, session: &Session, camera: &str, params: ReformatConfirm) -> Result<ReformatResponse, AppError> {
+    let cam_url = &camera_config(state, camera)?.url;
+    let Some(pending) = state.reformats.take(camera, &params.token, &session.username) else {
+        return Err(AppError::Confirmation(format!(
+            "no matching reformat of {} is waiting for confirmation, it may have expired: prepare it again",
+            camera
+        )));
+    };
+    if pending.not_archived > 0 && !params.override_backup {
+        let message = format!(
+            "{} captures of {} are not in the offload archive, copy them first or override the backup check",
+            pending.not_archived, camera
+        );
+        // the token stays valid, so the operator can still confirm with the override
+        state.reformats.insert(camera, pending);
+        return Err(AppError::NotArchived(message));
+    }
+    // recorded before the card is erased, so an erase is in the log even if its outcome never makes it there
+    let attempt = AuditEntry::new(&session.username, "reformat_attempt", camera, "started");
+    let attempt = if params.override_backup { attempt.with_detail("backup check overridden".to_string()) } else { attempt };
+    if let Err(e) = state.audit.record(attempt).await {
+        state.reformats.insert(camera, pending);
+        return Err(AppError::Unavailable(format!("the audit log cannot be written ({}), the card was not erased", e)));
+    }
+    let url = format!("{}/reformatsdcard", cam_url);
 
     let request_body = ReformatRequest {
         erase_all_data: true,
     };
 
-    let response = client
+    let response = state.client
         .post(url)
+        .timeout(StdDuration::from_secs(state.config.reformat.timeout_secs))
         .json(&request_body)
         .send()
         .await
-        .map_err(|e| {
-            info!("Request error: {:?}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+        .map_err(|e| AppError::camera(camera, e))?;
+    if !response.status().is_success() {
+        return Err(AppError::CameraResponse(camera.to_string(), response.status().to_string()));
+    }
     let resp: Result<ReformatResponse, reqwest::Error> = response.json().await;
     match resp {
-        Ok(res) => return Ok(Json(res)),
-        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR)
+        Ok(res) => {
+            info!("Reformat of {}: {}", camera, res.message);
+            Ok(res)
+        }
+        Err(e) => Err(AppError::Parse("the reformat answer of the camera".to_string(), e.to_string())),
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,528 @@
+use brig_types::CaptureId;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/*
+Backend configuration, read once at startup.
+Values come from an optional TOML file (see config.example.toml) and can be overridden by BRIG_* environment variables,
+so the same binary can be deployed on every vessel and on the bench rig.
+*/
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub influx: InfluxConfig,
+    pub limits: LimitsConfig,
+    pub live: LiveConfig,
+    pub auth: AuthConfig,
+    pub reformat: ReformatConfig,
+    pub archive: ArchiveConfig,
+    pub thumbnails: ThumbnailConfig,
+    pub geotags: GeotagConfig,
+    pub tiles: TileConfig,
+    pub cameras: BTreeMap<String, CameraConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: SocketAddr,
+    // origins allowed to call the API from another origin (e.g. "http://192.168.2.9:9080"),
+    // empty when the UI reaches the API through the nginx proxy
+    pub allowed_origins: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InfluxConfig {
+    pub host: String,
+    pub org: String,
+    pub token: String,
+    pub bucket: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    // upper bound on the points a single /api/data request may return
+    pub max_points: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LiveConfig {
+    // how often the latest CTD/GPS values are read from Influx and pushed to /api/live clients
+    pub poll_interval_ms: u64,
+    // samples older than this are marked as stale
+    pub stale_after_ms: u64,
+    // samples older than this are not reported at all
+    pub lookback_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    // TOML file with the users and their password hashes, see users.example.toml;
+    // without it nobody can log in and operator endpoints are unavailable
+    pub users_file: Option<PathBuf>,
+    pub session_ttl_secs: u64,
+    // whether data can be read without logging in (as a viewer)
+    pub anonymous_read: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReformatConfig {
+    // JSON lines audit log recording every reformat
+    pub audit_file: PathBuf,
+    // seconds an operator has to confirm a reformat
+    pub confirm_ttl_secs: u64,
+    // for the camera to answer the erase, far longer than cameras.<id>.timeout_ms
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArchiveConfig {
+    // offload archive on the Pi, one subdirectory per camera (see archive.rs), checked before every reformat
+    pub dir: PathBuf,
+    // per band file downloaded from a camera
+    pub download_timeout_secs: u64,
+    // tries per file before an offload gives up on it
+    pub attempts: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThumbnailConfig {
+    // rendered band thumbnails, laid out like the archive (see thumbnails.rs)
+    pub dir: PathBuf,
+    // thumbnails are scaled down to this width
+    pub width: u32,
+    // size of the in memory and on disk caches, least recently used thumbnails are evicted first
+    pub memory_mb: u64,
+    pub disk_mb: u64,
+    // how often Influx is checked for new captures to render their thumbnails ahead of time, 0 disables it
+    pub pregenerate_interval_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeotagConfig {
+    // geotagged copies of the archived band files, laid out like the archive (see geotags.rs)
+    pub dir: PathBuf,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TileConfig {
+    // MBTiles or PMTiles (by extension) served on /api/tiles, seeded MBTiles are written here too (see tiles.rs)
+    pub file: PathBuf,
+    // tile server the seed command downloads from, with {z}, {x} and {y}; its terms must allow bulk downloads
+    pub source_url: String,
+    // shown under the map, stored in the seeded file
+    pub attribution: String,
+    // pause between two downloads of the seed command
+    pub seed_delay_ms: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraConfig {
+    // base URL of the RedEdge HTTP API, e.g. http://192.168.1.83
+    pub url: String,
+    // shown in the UI, defaults to the camera id
+    #[serde(default)]
+    pub name: String,
+    #[serde(default = "default_model")]
+    pub model: String,
+    // how long status and image requests to the camera may take, so a powered off camera fails fast
+    #[serde(default = "default_camera_timeout")]
+    pub timeout_ms: u64,
+    // in file order, band N is IMG_XXXX_N.tif; defaults to the bands of a RedEdge-MX
+    #[serde(default = "default_bands")]
+    pub bands: Vec<BandConfig>,
+    // calibration panel capture giving the irradiance for reflectance, the DLS is used without it
+    pub panel: Option<PanelConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PanelConfig {
+    // capture id of the panel picture, e.g. cam1-SET0001-000-0001
+    pub capture: String,
+    // reflectance of the panel per band, from its calibration sheet; the albedo the camera wrote in the capture
+    // when empty
+    #[serde(default)]
+    pub albedo: Vec<f64>,
+    // x, y, width and height of the panel in the capture, defaults to the area the camera detected
+    pub region: Option<[u32; 4]>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BandConfig {
+    pub name: String,
+    // center wavelength and bandwidth (FWHM)
+    pub wavelength_nm: f64,
+    pub bandwidth_nm: f64,
+}
+
+impl CameraConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+}
+
+fn default_model() -> String {
+    "RedEdge-MX".to_string()
+}
+
+fn default_camera_timeout() -> u64 {
+    3000
+}
+
+// name, center wavelength and bandwidth of the bands of the two RedEdge-MX variants
+const REDEDGE_MX_BANDS: [(&str, f64, f64); 5] =
+    [("Blue", 475.0, 32.0), ("Green", 560.0, 27.0), ("Red", 668.0, 14.0), ("Red edge", 717.0, 12.0), ("NIR", 842.0, 57.0)];
+const REDEDGE_MX_BLUE_BANDS: [(&str, f64, f64); 5] =
+    [("Coastal blue", 444.0, 28.0), ("Green", 531.0, 14.0), ("Red", 650.0, 16.0), ("Red edge 705", 705.0, 10.0), ("Red edge 740", 740.0, 18.0)];
+
+fn bands(list: &[(&str, f64, f64)]) -> Vec<BandConfig> {
+    list.iter()
+        .map(|&(name, wavelength_nm, bandwidth_nm)| BandConfig { name: name.to_string(), wavelength_nm, bandwidth_nm })
+        .collect()
+}
+
+fn default_bands() -> Vec<BandConfig> {
+    bands(&REDEDGE_MX_BANDS)
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(String, std::io::Error),
+    Parse(String, toml::de::Error),
+    Env(String, String),
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "could not read {}: {}", path, e),
+            ConfigError::Parse(path, e) => write!(f, "could not parse {}: {}", path, e),
+            ConfigError::Env(var, e) => write!(f, "invalid value for {}: {}", var, e),
+            ConfigError::Invalid(problems) => write!(f, "invalid configuration:\n  - {}", problems.join("\n  - ")),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Default for Config {
+    fn default() -> Self {
+        let mut cameras = BTreeMap::new();
+        // the dual camera setup of the Brigantine
+        cameras.insert("cam1".to_string(), CameraConfig {
+            url: "http://192.168.1.83".to_string(),
+            name: "RedEdge-MX Red".to_string(),
+            model: default_model(),
+            timeout_ms: default_camera_timeout(),
+            bands: bands(&REDEDGE_MX_BANDS),
+            panel: None,
+        });
+        cameras.insert("cam2".to_string(), CameraConfig {
+            url: "http://192.168.3.83".to_string(),
+            name: "RedEdge-MX Blue".to_string(),
+            model: "RedEdge-MX Blue".to_string(),
+            timeout_ms: default_camera_timeout(),
+            bands: bands(&REDEDGE_MX_BLUE_BANDS),
+            panel: None,
+        });
+        Self {
+            server: ServerConfig::default(),
+            influx: InfluxConfig::default(),
+            limits: LimitsConfig::default(),
+            live: LiveConfig::default(),
+            auth: AuthConfig::default(),
+            reformat: ReformatConfig::default(),
+            archive: ArchiveConfig::default(),
+            thumbnails: ThumbnailConfig::default(),
+            geotags: GeotagConfig::default(),
+            tiles: TileConfig::default(),
+            cameras,
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([0, 0, 0, 0], 3000)),
+            allowed_origins: Vec::new(),
+        }
+    }
+}
+
+impl Default for InfluxConfig {
+    fn default() -> Self {
+        Self {
+            host: "http://localhost:8086".to_string(),
+            org: "SailingLab".to_string(),
+            token: String::new(), // no sensible default, must be configured
+            bucket: "asv_data".to_string(),
+        }
+    }
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self { max_points: 50_000 }
+    }
+}
+
+impl Default for LiveConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_ms: 2000,
+            stale_after_ms: 10_000,
+            lookback_secs: 600,
+        }
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            users_file: None,
+            session_ttl_secs: 12 * 3600,
+            anonymous_read: true,
+        }
+    }
+}
+
+impl Default for ReformatConfig {
+    fn default() -> Self {
+        Self {
+            audit_file: PathBuf::from("audit.jsonl"),
+            confirm_ttl_secs: 60,
+            timeout_secs: 60,
+        }
+    }
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("/home/pi/rededge_archive"),
+            download_timeout_secs: 60,
+            attempts: 3,
+        }
+    }
+}
+
+impl Default for ThumbnailConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("thumbnails"),
+            width: 640,
+            memory_mb: 32,
+            disk_mb: 1024,
+            pregenerate_interval_secs: 10,
+        }
+    }
+}
+
+impl Default for TileConfig {
+    fn default() -> Self {
+        Self {
+            file: PathBuf::from("/home/pi/tiles.mbtiles"),
+            source_url: String::new(),
+            attribution: String::new(),
+            seed_delay_ms: 100,
+        }
+    }
+}
+
+impl Default for GeotagConfig {
+    fn default() -> Self {
+        Self { dir: PathBuf::from("/home/pi/rededge_geotagged") }
+    }
+}
+
+impl Config {
+    // loads the config file (if given), applies environment overrides and validates the result
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let mut config = match path {
+            Some(path) => {
+                let name = path.display().to_string();
+                let text = std::fs::read_to_string(path).map_err(|e| ConfigError::Io(name.clone(), e))?;
+                toml::from_str::<Config>(&text).map_err(|e| ConfigError::Parse(name, e))?
+            }
+            None => Config::default(),
+        };
+        config.apply_env()?;
+        config.normalize();
+        config.validate()?;
+        Ok(config)
+    }
+
+    // environment variables take precedence over the config file
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        if let Ok(bind) = std::env::var("BRIG_BIND") {
+            self.server.bind = bind
+                .parse()
+                .map_err(|e: std::net::AddrParseError| ConfigError::Env("BRIG_BIND".to_string(), e.to_string()))?;
+        }
+        if let Ok(host) = std::env::var("BRIG_INFLUX_HOST") {
+            self.influx.host = host;
+        }
+        if let Ok(org) = std::env::var("BRIG_INFLUX_ORG") {
+            self.influx.org = org;
+        }
+        if let Ok(token) = std::env::var("BRIG_INFLUX_TOKEN") {
+            self.influx.token = token;
+        }
+        if let Ok(bucket) = std::env::var("BRIG_INFLUX_BUCKET") {
+            self.influx.bucket = bucket;
+        }
+        if let Ok(users_file) = std::env::var("BRIG_USERS_FILE") {
+            self.auth.users_file = Some(PathBuf::from(users_file));
+        }
+        Ok(())
+    }
+
+    // strips trailing slashes so urls can be joined with "/path"
+    fn normalize(&mut self) {
+        self.influx.host = self.influx.host.trim_end_matches('/').to_string();
+        for origin in self.server.allowed_origins.iter_mut() {
+            *origin = origin.trim_end_matches('/').to_string();
+        }
+        for (id, camera) in self.cameras.iter_mut() {
+            camera.url = camera.url.trim_end_matches('/').to_string();
+            if camera.name.trim().is_empty() {
+                camera.name = id.clone();
+            }
+        }
+    }
+
+    // collects every problem instead of stopping at the first one
+    fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        if !is_http_url(&self.influx.host) {
+            problems.push(format!("influx.host must be an http(s) url, got \"{}\"", self.influx.host));
+        }
+        if self.influx.org.trim().is_empty() {
+            problems.push("influx.org must not be empty".to_string());
+        }
+        if self.influx.token.trim().is_empty() {
+            problems.push("influx.token must be set (in the config file or with BRIG_INFLUX_TOKEN)".to_string());
+        }
+        if self.influx.bucket.trim().is_empty() {
+            problems.push("influx.bucket must not be empty".to_string());
+        }
+        if self.limits.max_points <= 0 {
+            problems.push("limits.max_points must be positive".to_string());
+        }
+        if self.live.poll_interval_ms < 100 {
+            problems.push("live.poll_interval_ms must be at least 100".to_string());
+        }
+        if self.live.lookback_secs == 0 || self.live.lookback_secs * 1000 < self.live.stale_after_ms {
+            problems.push("live.lookback_secs must be positive and cover live.stale_after_ms".to_string());
+        }
+        if self.auth.session_ttl_secs == 0 {
+            problems.push("auth.session_ttl_secs must be positive".to_string());
+        }
+        if self.reformat.audit_file.as_os_str().is_empty() {
+            problems.push("reformat.audit_file must not be empty".to_string());
+        }
+        if self.reformat.confirm_ttl_secs == 0 || self.reformat.timeout_secs == 0 {
+            problems.push("reformat.confirm_ttl_secs and reformat.timeout_secs must be positive".to_string());
+        }
+        if self.archive.dir.as_os_str().is_empty() {
+            problems.push("archive.dir must not be empty".to_string());
+        }
+        if self.archive.download_timeout_secs == 0 || self.archive.attempts == 0 {
+            problems.push("archive.download_timeout_secs and archive.attempts must be positive".to_string());
+        }
+        if self.geotags.dir.as_os_str().is_empty() || self.geotags.dir == self.archive.dir {
+            problems.push("geotags.dir must be set and differ from archive.dir".to_string());
+        }
+        if self.tiles.file.as_os_str().is_empty() {
+            problems.push("tiles.file must not be empty".to_string());
+        }
+        let tile_url = is_http_url(&self.tiles.source_url) && ["{z}", "{x}", "{y}"].iter().all(|p| self.tiles.source_url.contains(p));
+        if !self.tiles.source_url.is_empty() && !tile_url {
+            problems.push(format!("tiles.source_url must be an http(s) url with {{z}}, {{x}} and {{y}}, got \"{}\"", self.tiles.source_url));
+        }
+        if self.thumbnails.dir.as_os_str().is_empty() {
+            problems.push("thumbnails.dir must not be empty".to_string());
+        }
+        if self.thumbnails.width < 16 {
+            problems.push("thumbnails.width must be at least 16".to_string());
+        }
+        for origin in &self.server.allowed_origins {
+            if !is_http_url(origin) || origin.parse::<axum::http::HeaderValue>().is_err() {
+                problems.push(format!("server.allowed_origins entries must be http(s) origins, got \"{}\"", origin));
+            }
+        }
+        for (id, camera) in &self.cameras {
+            if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                problems.push(format!("camera id \"{}\" may only contain letters, digits, '_' and '-'", id));
+            }
+            if !is_http_url(&camera.url) {
+                problems.push(format!("cameras.{}.url must be an http(s) url, got \"{}\"", id, camera.url));
+            }
+            if camera.timeout_ms < 100 {
+                problems.push(format!("cameras.{}.timeout_ms must be at least 100", id));
+            }
+            if camera.bands.is_empty() || camera.bands.len() > u8::MAX as usize {
+                problems.push(format!("cameras.{} must have between 1 and {} bands", id, u8::MAX));
+            }
+            if camera.bands.iter().any(|b| b.wavelength_nm <= 0.0 || b.bandwidth_nm <= 0.0) {
+                problems.push(format!("cameras.{} band wavelengths and bandwidths must be positive", id));
+            }
+            if let Some(panel) = &camera.panel {
+                if CaptureId::parse(&panel.capture).is_none_or(|capture| &capture.camera != id) {
+                    problems.push(format!("cameras.{}.panel.capture must be a capture id of {}, got \"{}\"", id, id, panel.capture));
+                }
+                if !panel.albedo.is_empty() && panel.albedo.len() != camera.bands.len() {
+                    problems.push(format!("cameras.{}.panel.albedo must have one value per band", id));
+                }
+                if panel.albedo.iter().any(|&a| !(a > 0.0 && a <= 1.0)) {
+                    problems.push(format!("cameras.{}.panel.albedo values must be between 0 and 1", id));
+                }
+                if panel.region.is_some_and(|[_, _, w, h]| w == 0 || h == 0) {
+                    problems.push(format!("cameras.{}.panel.region must have a positive width and height", id));
+                }
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+
+    // registry entry of the given camera, None if the camera is not configured
+    pub fn camera(&self, camera: &str) -> Option<&CameraConfig> {
+        self.cameras.get(camera)
+    }
+
+    // base url of the given camera, None if the camera is not configured
+    pub fn camera_url(&self, camera: &str) -> Option<&str> {
+        self.cameras.get(camera).map(|c| c.url.as_str())
+    }
+}
+
+fn is_http_url(url: &str) -> bool {
+    let rest = url
+        .strip_prefix("http://")
+        .or_else(|| url.strip_prefix("https://"));
+    matches!(rest, Some(host) if !host.is_empty())
+}
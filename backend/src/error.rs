@@ -0,0 +1,160 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use brig_types::ErrorBody;
+use std::fmt;
+use tracing::{info, warn};
+
+use crate::influx::InfluxError;
+
+/*
+Errors returned by the HTTP handlers.
+Every variant maps to a status code and a JSON ErrorBody with a stable code, so the UI can tell
+"the camera did not answer" from "there is nothing recorded in that range" without parsing messages.
+*/
+
+#[derive(Debug)]
+pub enum AppError {
+    // a query parameter is missing or malformed
+    BadRequest(String),
+    // not logged in, or the login failed
+    Unauthorized(String),
+    // logged in, but the role does not allow it
+    Forbidden(String),
+    // a two-step operation was confirmed with a missing, wrong or expired token
+    Confirmation(String),
+    // a reformat would erase captures that are not in the offload archive
+    NotArchived(String),
+    // the operation is already running (e.g. an offload of the same camera)
+    Busy(String),
+    // the requested range or capture has no matching data
+    NoData(String),
+    // the camera id is not in the configuration
+    UnknownCamera(String),
+    // Influx could not be queried or returned something unusable
+    Influx(InfluxError),
+    // the camera did not answer, or answered with an error
+    CameraTimeout(String, reqwest::Error),
+    CameraUnreachable(String, reqwest::Error),
+    CameraResponse(String, String),
+    // an image or response could not be decoded
+    Parse(String, String),
+    // systemctl could not be run or failed
+    Service(String, String),
+    // a background value (e.g. real time data) is not available yet, or the audit log cannot be written
+    Unavailable(String),
+}
+
+impl AppError {
+    // camera request failure, split between timeouts and other connection errors
+    pub fn camera(camera: &str, e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            AppError::CameraTimeout(camera.to_string(), e)
+        } else {
+            AppError::CameraUnreachable(camera.to_string(), e)
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::Confirmation(_) | AppError::NotArchived(_) | AppError::Busy(_) => StatusCode::CONFLICT,
+            AppError::NoData(_) | AppError::UnknownCamera(_) => StatusCode::NOT_FOUND,
+            AppError::Influx(_) => StatusCode::BAD_GATEWAY,
+            AppError::CameraTimeout(..) => StatusCode::GATEWAY_TIMEOUT,
+            AppError::CameraUnreachable(..) | AppError::CameraResponse(..) => StatusCode::BAD_GATEWAY,
+            AppError::Parse(..) | AppError::Service(..) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            AppError::BadRequest(_) => "bad_request",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::Confirmation(_) => "confirmation_invalid",
+            AppError::NotArchived(_) => "not_archived",
+            AppError::Busy(_) => "busy",
+            AppError::NoData(_) => "no_data",
+            AppError::UnknownCamera(_) => "unknown_camera",
+            AppError::Influx(InfluxError::Request(_) | InfluxError::Status(..)) => "influx_unreachable",
+            AppError::Influx(InfluxError::Query(_)) => "influx_query",
+            AppError::Influx(InfluxError::Csv(_)) => "influx_parse",
+            AppError::CameraTimeout(..) => "camera_timeout",
+            AppError::CameraUnreachable(..) => "camera_unreachable",
+            AppError::CameraResponse(..) => "camera_error",
+            AppError::Parse(..) => "parse_failure",
+            AppError::Service(..) => "service_error",
+            AppError::Unavailable(_) => "unavailable",
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            AppError::BadRequest(msg)
+            | AppError::Unauthorized(msg)
+            | AppError::Forbidden(msg)
+            | AppError::Confirmation(msg)
+            | AppError::NotArchived(msg)
+            | AppError::Busy(msg)
+            | AppError::NoData(msg)
+            | AppError::Unavailable(msg) => msg.clone(),
+            AppError::UnknownCamera(camera) => format!("camera {} is not configured", camera),
+            AppError::Influx(InfluxError::Request(_) | InfluxError::Status(..)) => "the database is not reachable".to_string(),
+            AppError::Influx(_) => "the database query failed".to_string(),
+            AppError::CameraTimeout(camera, _) => format!("camera {} did not answer in time", camera),
+            AppError::CameraUnreachable(camera, _) => format!("camera {} is not reachable", camera),
+            AppError::CameraResponse(camera, _) => format!("camera {} returned an error", camera),
+            AppError::Parse(what, _) => format!("could not read {}", what),
+            AppError::Service(service, _) => format!("systemctl failed for {}", service),
+        }
+    }
+
+    fn cause(&self) -> Option<String> {
+        match self {
+            AppError::Influx(e) => Some(e.to_string()),
+            AppError::CameraTimeout(_, e) | AppError::CameraUnreachable(_, e) => Some(e.to_string()),
+            AppError::CameraResponse(_, cause) | AppError::Parse(_, cause) | AppError::Service(_, cause) => Some(cause.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.cause() {
+            Some(cause) => write!(f, "{}: {}", self.message(), cause),
+            None => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<InfluxError> for AppError {
+    fn from(e: InfluxError) -> Self {
+        AppError::Influx(e)
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        if status.is_server_error() {
+            warn!("{} ({})", self, self.code());
+        } else {
+            info!("{} ({})", self, self.code());
+        }
+        let body = ErrorBody {
+            code: self.code().to_string(),
+            message: self.message(),
+            cause: self.cause(),
+        };
+        (status, Json(body)).into_response()
+    }
+}
@@ -0,0 +1,203 @@
+use axum::{
+    extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+use brig_types::{CaptureId, CaptureMetadataParams, CaptureRecord};
+use chrono::{DateTime, NaiveTime, SecondsFormat, TimeDelta, Utc};
+
+use crate::camera::{camera_config, CAPTURE_PATH};
+use crate::error::AppError;
+use crate::influx::{FluxQuery, FluxRecord};
+use crate::{parse_date, parse_time_param, AppState, SharedState};
+
+/*
+Capture metadata for post-processing (Pix4D, Agisoft Metashape, ...): every capture of a range with the CTD and
+GPS samples nearest to it. image_data_call does the same for one capture with three queries; here the captures of
+the range are counted first, so a range over limits.max_points is refused before anything is loaded, then read,
+and the CTD and GPS samples are read only within the tolerance around them, in one query per run of captures
+close together. Every capture is joined to the nearest sample of each measurement by a binary search on their
+times. Values are left empty when no sample is within the tolerance, so a capture is never dropped for a GPS gap.
+*/
+
+const DEFAULT_TOLERANCE_MS: u64 = 1000;
+const MAX_TOLERANCE_MS: u64 = 60_000;
+// captures whose sample windows are closer than this are read with one query
+const MERGE_GAP: TimeDelta = TimeDelta::minutes(1);
+
+// samples of one measurement, sorted by time
+struct Samples<'a>(Vec<(DateTime<Utc>, &'a FluxRecord)>);
+
+impl<'a> Samples<'a> {
+    fn new(records: &'a [FluxRecord], measurement: &str) -> Self {
+        let mut samples: Vec<_> = records
+            .iter()
+            .filter(|r| r.measurement() == Some(measurement))
+            .filter_map(|r| Some((r.time()?, r)))
+            .collect();
+        samples.sort_by_key(|(time, _)| *time);
+        Samples(samples)
+    }
+
+    // the sample closest to time, if it is within tolerance
+    fn nearest(&self, time: DateTime<Utc>, tolerance: TimeDelta) -> Option<&'a FluxRecord> {
+        let after = self.0.partition_point(|(t, _)| *t < time);
+        let before = after.checked_sub(1).and_then(|i| self.0.get(i));
+        [before, self.0.get(after)]
+            .into_iter()
+            .flatten()
+            .map(|(t, record)| ((*t - time).abs(), *record))
+            .filter(|(distance, _)| *distance <= tolerance)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, record)| record)
+    }
+}
+
+// time range of the request, a whole day when a date is given
+fn range(params: &CaptureMetadataParams) -> Result<(DateTime<Utc>, DateTime<Utc>), AppError> {
+    if let Some(date) = &params.date {
+        let start = parse_date(date)?.and_time(NaiveTime::MIN).and_utc();
+        return Ok((start, start + TimeDelta::days(1)));
+    }
+    let now = Utc::now();
+    let start = parse_time_param(params.start.as_deref().unwrap_or("-24h"), now)?;
+    let stop = match params.stop.as_deref() {
+        Some(stop) => parse_time_param(stop, now)?,
+        None => now,
+    };
+    if start >= stop {
+        return Err(AppError::BadRequest("start must be before stop".to_string()));
+    }
+    Ok((start, stop))
+}
+
+// captures of the request joined to their CTD and GPS values, oldest first
+pub async fn capture_records(state: &AppState, params: &CaptureMetadataParams) -> Result<Vec<CaptureRecord>, AppError> {
+    if let Some(camera) = &params.camera {
+        camera_config(state, camera)?;
+    }
+    let (start, stop) = range(params)?;
+    let tolerance_ms = params.tolerance_ms.unwrap_or(DEFAULT_TOLERANCE_MS);
+    if tolerance_ms > MAX_TOLERANCE_MS {
+        return Err(AppError::BadRequest(format!("tolerance_ms must be at most {}", MAX_TOLERANCE_MS)));
+    }
+    let tolerance = TimeDelta::milliseconds(tolerance_ms as i64);
+
+    let selection = capture_selection(state, params, start, stop);
+    let counts = state.influx.query(&selection.clone().count().build()).await?;
+    let total: u64 = counts.iter().filter_map(|r| r.value()?.parse::<u64>().ok()).sum();
+    let max_points = state.config.limits.max_points as u64;
+    if total > max_points {
+        return Err(AppError::BadRequest(format!(
+            "the range has {} captures (limit {}), use a shorter range, a camera or a set",
+            total, max_points
+        )));
+    }
+    let capture_rows = state.influx.query(&selection.keep(&["_time", "_value", "camera"]).build()).await?;
+    let mut captures: Vec<(DateTime<Utc>, CaptureId, &str)> = capture_rows
+        .iter()
+        .filter_map(|r| {
+            let (time, camera, file) = (r.time()?, r.get("camera")?, r.value()?);
+            Some((time, CaptureId::from_path(camera, file)?, file))
+        })
+        .collect();
+    captures.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.camera.cmp(&b.1.camera)));
+
+    let mut records = Vec::new();
+    for (from, to) in sample_windows(captures.iter().map(|(time, _, _)| *time), tolerance) {
+        let query = state.influx.flux()
+            .range(from, to)
+            .measurements(&["idronaut_data", "gps_data2"])
+            .pivot()
+            .build();
+        records.extend(state.influx.query(&query).await?);
+    }
+    let (ctd, gps) = (Samples::new(&records, "idronaut_data"), Samples::new(&records, "gps_data2"));
+
+    Ok(captures
+        .into_iter()
+        .map(|(time, id, file)| {
+            let ctd = ctd.nearest(time, tolerance);
+            let gps = gps.nearest(time, tolerance);
+            let ctd_value = |field: &str| ctd.and_then(|r| r.f64(field));
+            let gps_value = |field: &str| gps.and_then(|r| r.f64(field));
+            CaptureRecord {
+                capture: id.to_string(),
+                camera: id.camera.clone(),
+                file: file.to_string(),
+                time: time.to_rfc3339_opts(SecondsFormat::Millis, true),
+                lat: gps_value("latitude"),
+                lon: gps_value("longitude"),
+                cog: gps_value("cog"),
+                sog: gps_value("sog"),
+                depth: gps_value("depth"),
+                conductivity: ctd_value("conductivity"),
+                oxygen_percentage: ctd_value("oxygen_percentage"),
+                oxygen_ppm: ctd_value("oxygen_ppm"),
+                ph: ctd_value("ph"),
+                pressure: ctd_value("pressure"),
+                salinity: ctd_value("salinity"),
+                temperature: ctd_value("temperature"),
+            }
+        })
+        .collect())
+}
+
+// the capture paths of the request, narrowed down in Flux so they can be counted before they are read
+fn capture_selection(state: &AppState, params: &CaptureMetadataParams, start: DateTime<Utc>, stop: DateTime<Utc>) -> FluxQuery {
+    let mut query = state.influx.flux().range(start, stop).measurement("micasense_data").field("capture");
+    // captures of cameras missing from the config cannot be resolved to band files, they are left out
+    query = match &params.camera {
+        Some(camera) => query.tag("camera", camera),
+        None => query.tags("camera", &state.config.cameras.keys().collect::<Vec<_>>()),
+    };
+    match &params.set {
+        Some(set) => {
+            let set: String = set.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_string() } else { format!("\\{}", c) }).collect();
+            query.value_matches(&format!(r"(^|/)(?i:{})/[0-9]+/IMG_[0-9]+(_[^/]*)?$", set))
+        }
+        None => query.value_matches(CAPTURE_PATH),
+    }
+}
+
+// time ranges within tolerance of the sorted capture times, runs of captures closer than MERGE_GAP joined
+fn sample_windows(times: impl Iterator<Item = DateTime<Utc>>, tolerance: TimeDelta) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+    let mut windows: Vec<(DateTime<Utc>, DateTime<Utc>)> = Vec::new();
+    for time in times {
+        let (from, to) = (time - tolerance, time + tolerance + TimeDelta::milliseconds(1));
+        match windows.last_mut() {
+            Some((_, last)) if from - *last < MERGE_GAP => *last = to,
+            _ => windows.push((from, to)),
+        }
+    }
+    windows
+}
+
+// every capture of a range with its CTD and GPS values, as a CSV download or JSON
+pub async fn capture_metadata(
+    State(state): State<SharedState>,
+    Query(params): Query<CaptureMetadataParams>,
+) -> Result<Response, AppError> {
+    let format = params.format.as_deref().unwrap_or("csv");
+    if format != "csv" && format != "json" {
+        return Err(AppError::BadRequest(format!("unknown format {}, use csv or json", format)));
+    }
+    let records = capture_records(&state, &params).await?;
+    if format == "json" {
+        return Ok(Json(records).into_response());
+    }
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for record in &records {
+        writer.serialize(record).map_err(|e| AppError::Parse("the capture metadata".to_string(), e.to_string()))?;
+    }
+    let csv = writer.into_inner().map_err(|e| AppError::Parse("the capture metadata".to_string(), e.to_string()))?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"captures.csv\""),
+        ],
+        csv,
+    )
+        .into_response())
+}
@@ -0,0 +1,683 @@
+use axum::{
+    extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use brig_types::{CaptureId, CaptureMetadataParams, CaptureRecord, GeotagParams, GeotagWriteResult};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use std::path::PathBuf;
+use tokio::task;
+use tracing::{info, warn};
+
+use crate::archive;
+use crate::auth::Session;
+use crate::camera::camera_config;
+use crate::error::AppError;
+use crate::export::capture_records;
+use crate::{AppState, SharedState};
+
+/*
+Geotags of the captures for photogrammetry tools, from the capture to GPS join of export.rs.
+They come in three forms: a CSV with one row per band file, in the image geolocation format of Pix4D or the
+reference format of Agisoft Metashape; XMP sidecars (IMG_0001_1.xmp for IMG_0001_1.tif) in a zip laid out like the SD
+card; and geotagged copies of the offloaded band files under geotags.dir, whose GPS IFD is replaced by the position
+of the boat. The archive itself is never modified, its files stay identical to the ones on the camera and to their
+checksums. Positions are those of the boat GPS, not of the GPS of the cameras; the altitude is the height of the
+cameras above the water (altitude_m). Tools match geotags by file name, which repeats between sets, so export one set
+at a time. Captures without a GPS sample within the tolerance are left out.
+*/
+
+// failures listed in GeotagWriteResult.errors, the count covers the rest
+const MAX_ERRORS: usize = 20;
+// entries of a zip without the zip64 extensions
+const MAX_ZIP_ENTRIES: usize = u16::MAX as usize;
+
+// GPS tags of TIFF/EXIF
+const TAG_GPS_IFD: u16 = 0x8825;
+
+fn selection(params: &GeotagParams) -> CaptureMetadataParams {
+    CaptureMetadataParams {
+        start: params.start.clone(),
+        stop: params.stop.clone(),
+        date: params.date.clone(),
+        camera: params.camera.clone(),
+        set: params.set.clone(),
+        tolerance_ms: params.tolerance_ms,
+        format: None,
+    }
+}
+
+// a capture with a position, and the SD card paths of its band files
+struct Geotag {
+    record: CaptureRecord,
+    time: DateTime<Utc>,
+    lat: f64,
+    lon: f64,
+    files: Vec<String>,
+}
+
+async fn geotags(state: &AppState, params: &GeotagParams) -> Result<(Vec<Geotag>, u64), AppError> {
+    let records = capture_records(state, &selection(params)).await?;
+    let total = records.len() as u64;
+    let mut geotags = Vec::new();
+    for record in records {
+        let (Some(lat), Some(lon), Some(id), Ok(time)) =
+            (record.lat, record.lon, CaptureId::parse(&record.capture), record.time.parse::<DateTime<Utc>>())
+        else {
+            continue;
+        };
+        // capture_records only reads configured cameras, a capture whose path names another one is skipped
+        let Ok(config) = camera_config(state, &id.camera) else {
+            warn!("Skipping the geotag of {}: camera {} is not configured", id, id.camera);
+            continue;
+        };
+        let bands = config.bands.len() as u8;
+        let files = (1..=bands).map(|band| id.band_path(band)).collect();
+        geotags.push(Geotag { record, time, lat, lon, files });
+    }
+    let without_position = total - geotags.len() as u64;
+    Ok((geotags, without_position))
+}
+
+// file name of an SD card path, e.g. IMG_0001_1.tif
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+// Pix4D image geolocation or Agisoft Metashape reference CSV, one row per band file
+fn geotag_csv(geotags: &[Geotag], altitude: f64, agisoft: bool) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    if agisoft {
+        writer.write_record(["label", "longitude", "latitude", "altitude"])?;
+    } else {
+        writer.write_record(["imagename", "latitude", "longitude", "altitude"])?;
+    }
+    for geotag in geotags {
+        for file in &geotag.files {
+            let (lat, lon, alt) = (geotag.lat.to_string(), geotag.lon.to_string(), altitude.to_string());
+            if agisoft {
+                writer.write_record([file_name(file), &lon, &lat, &alt])?;
+            } else {
+                writer.write_record([file_name(file), &lat, &lon, &alt])?;
+            }
+        }
+    }
+    writer.into_inner().map_err(|e| e.into_error().into())
+}
+
+// degrees and decimal minutes with the hemisphere, as XMP writes GPS coordinates, e.g. 43,6.123456N; rounded as a
+// whole like dms()
+fn xmp_coordinate(value: f64, positive: char, negative: char) -> String {
+    let micro_minutes = (value.abs() * 60_000_000.0).round() as u64;
+    let (degrees, minutes) = (micro_minutes / 60_000_000, micro_minutes % 60_000_000);
+    format!("{},{}.{:06}{}", degrees, minutes / 1_000_000, minutes % 1_000_000, if value < 0.0 { negative } else { positive })
+}
+
+fn xmp_sidecar(geotag: &Geotag, altitude: f64) -> String {
+    let mut attributes = vec![
+        ("GPSVersionID", "2.3.0.0".to_string()),
+        ("GPSLatitude", xmp_coordinate(geotag.lat, 'N', 'S')),
+        ("GPSLongitude", xmp_coordinate(geotag.lon, 'E', 'W')),
+        ("GPSAltitudeRef", if altitude < 0.0 { "1" } else { "0" }.to_string()),
+        ("GPSAltitude", format!("{}/1000", (altitude.abs() * 1000.0).round() as u64)),
+        ("GPSTimeStamp", geotag.record.time.clone()),
+    ];
+    if let Some(sog) = geotag.record.sog {
+        attributes.push(("GPSSpeedRef", "K".to_string()));
+        attributes.push(("GPSSpeed", format!("{}/1000", (sog * 3.6 * 1000.0).round() as u64)));
+    }
+    if let Some(cog) = geotag.record.cog {
+        attributes.push(("GPSTrackRef", "T".to_string()));
+        attributes.push(("GPSTrack", format!("{}/100", (cog * 100.0).round() as u64)));
+    }
+    let attributes: String = attributes.iter().map(|(name, value)| format!("\n    exif:{}=\"{}\"", name, value)).collect();
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+         <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+         <rdf:Description rdf:about=\"\" xmlns:exif=\"http://ns.adobe.com/exif/1.0/\"{}/>\n\
+         </rdf:RDF>\n\
+         </x:xmpmeta>\n\
+         <?xpacket end=\"w\"?>\n",
+        attributes
+    )
+}
+
+// zip of uncompressed files, enough for text sidecars and readable everywhere
+fn zip(files: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut central = Vec::new();
+    // 1980-01-01 00:00 in DOS format, the sidecars carry their real time
+    let (time, date) = (0u16, 0x21u16);
+    for (name, data) in files {
+        let crc = crc32fast::hash(data);
+        let offset = out.len() as u32;
+        let common = |buf: &mut Vec<u8>| {
+            for value in [20u16, 0, 0, time, date] {
+                buf.extend_from_slice(&value.to_le_bytes());
+            }
+            buf.extend_from_slice(&crc.to_le_bytes());
+            buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            buf.extend_from_slice(&0u16.to_le_bytes());
+        };
+        out.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        common(&mut out);
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(data);
+
+        central.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        central.extend_from_slice(&20u16.to_le_bytes());
+        common(&mut central);
+        // comment length, disk, internal and external attributes
+        central.extend_from_slice(&[0; 10]);
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+    }
+    let (central_offset, central_size) = (out.len() as u32, central.len() as u32);
+    out.extend_from_slice(&central);
+    out.extend_from_slice(&0x06054b50u32.to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    out.extend_from_slice(&central_size.to_le_bytes());
+    out.extend_from_slice(&central_offset.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out
+}
+
+// geotags of the captures of a range as Pix4D or Agisoft CSV, or as a zip of XMP sidecars
+pub async fn geotag_export(State(state): State<SharedState>, Query(params): Query<GeotagParams>) -> Result<Response, AppError> {
+    let format = params.format.as_deref().unwrap_or("pix4d");
+    let altitude = params.altitude_m.unwrap_or(0.0);
+    if !altitude.is_finite() {
+        return Err(AppError::BadRequest("altitude_m must be a number".to_string()));
+    }
+    if !["pix4d", "agisoft", "xmp"].contains(&format) {
+        return Err(AppError::BadRequest(format!("unknown format {}, use pix4d, agisoft or xmp", format)));
+    }
+    let (geotags, _) = geotags(&state, &params).await?;
+    if geotags.is_empty() {
+        return Err(AppError::NoData("no capture with a GPS position in that range".to_string()));
+    }
+    if format == "xmp" {
+        let files: Vec<(String, Vec<u8>)> = geotags
+            .iter()
+            .flat_map(|geotag| {
+                let xmp = xmp_sidecar(geotag, altitude);
+                geotag.files.iter().map(move |file| {
+                    let path = format!("{}/{}", geotag.record.camera, file.trim_start_matches("/files/"));
+                    (format!("{}.xmp", path.trim_end_matches(".tif")), xmp.clone().into_bytes())
+                })
+            })
+            .collect();
+        if files.len() > MAX_ZIP_ENTRIES {
+            return Err(AppError::BadRequest(format!(
+                "the range has {} band files, a zip holds at most {}; use a shorter range, a camera or a set",
+                files.len(), MAX_ZIP_ENTRIES
+            )));
+        }
+        let zip = task::spawn_blocking(move || zip(&files))
+            .await
+            .map_err(|e| AppError::Parse("the XMP sidecars".to_string(), e.to_string()))?;
+        return Ok(([(header::CONTENT_TYPE, "application/zip"), (header::CONTENT_DISPOSITION, "attachment; filename=\"geotags.zip\"")], zip).into_response());
+    }
+    let agisoft = format == "agisoft";
+    let csv = geotag_csv(&geotags, altitude, agisoft).map_err(|e| AppError::Parse("the geotags".to_string(), e.to_string()))?;
+    let disposition = if agisoft { "attachment; filename=\"geotags_agisoft.csv\"" } else { "attachment; filename=\"geotags_pix4d.csv\"" };
+    Ok(([(header::CONTENT_TYPE, "text/csv"), (header::CONTENT_DISPOSITION, disposition)], csv).into_response())
+}
+
+// TIFF byte order, from the header
+#[derive(Clone, Copy)]
+struct ByteOrder(bool);
+
+impl ByteOrder {
+    fn u16(self, bytes: &[u8], at: usize) -> Option<u16> {
+        let b: [u8; 2] = bytes.get(at..at + 2)?.try_into().ok()?;
+        Some(if self.0 { u16::from_le_bytes(b) } else { u16::from_be_bytes(b) })
+    }
+
+    fn u32(self, bytes: &[u8], at: usize) -> Option<u32> {
+        let b: [u8; 4] = bytes.get(at..at + 4)?.try_into().ok()?;
+        Some(if self.0 { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+    }
+
+    fn put_u16(self, out: &mut Vec<u8>, value: u16) {
+        out.extend_from_slice(&if self.0 { value.to_le_bytes() } else { value.to_be_bytes() });
+    }
+
+    fn put_u32(self, out: &mut Vec<u8>, value: u32) {
+        out.extend_from_slice(&if self.0 { value.to_le_bytes() } else { value.to_be_bytes() });
+    }
+}
+
+// an IFD entry to write: tag, type, count and the value bytes, already in the byte order of the file
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    value: Vec<u8>,
+}
+
+impl Entry {
+    fn ascii(tag: u16, text: &str) -> Self {
+        let mut value = text.as_bytes().to_vec();
+        value.push(0);
+        Entry { tag, kind: 2, count: value.len() as u32, value }
+    }
+
+    fn rationals(tag: u16, order: ByteOrder, values: &[(u32, u32)]) -> Self {
+        let mut value = Vec::new();
+        for &(numerator, denominator) in values {
+            order.put_u32(&mut value, numerator);
+            order.put_u32(&mut value, denominator);
+        }
+        Entry { tag, kind: 5, count: values.len() as u32, value }
+    }
+}
+
+// appends an IFD (word aligned) with its out of line values after it, returns its offset
+fn write_ifd(out: &mut Vec<u8>, order: ByteOrder, entries: &mut [Entry], next: u32) -> u32 {
+    entries.sort_by_key(|e| e.tag);
+    if out.len() % 2 == 1 {
+        out.push(0);
+    }
+    let offset = out.len() as u32;
+    let mut data_offset = offset + 2 + 12 * entries.len() as u32 + 4;
+    let mut data = Vec::new();
+    order.put_u16(out, entries.len() as u16);
+    for entry in entries.iter() {
+        order.put_u16(out, entry.tag);
+        order.put_u16(out, entry.kind);
+        order.put_u32(out, entry.count);
+        if entry.value.len() <= 4 {
+            let mut inline = entry.value.clone();
+            inline.resize(4, 0);
+            out.extend_from_slice(&inline);
+        } else {
+            order.put_u32(out, data_offset + data.len() as u32);
+            data.extend_from_slice(&entry.value);
+            if data.len() % 2 == 1 {
+                data.push(0);
+            }
+        }
+    }
+    order.put_u32(out, next);
+    data_offset += data.len() as u32;
+    out.extend_from_slice(&data);
+    debug_assert_eq!(out.len() as u32, data_offset);
+    offset
+}
+
+// degrees, minutes and seconds (to the thousandth) as EXIF rationals; rounded as a whole, so 59.9999" carries into
+// the minutes instead of becoming 60.000"
+fn dms(value: f64) -> [(u32, u32); 3] {
+    let millis = (value.abs() * 3_600_000.0).round() as u64;
+    let (degrees, minutes, seconds) = (millis / 3_600_000, millis / 60_000 % 60, millis % 60_000);
+    [(degrees as u32, 1), (minutes as u32, 1), (seconds as u32, 1000)]
+}
+
+// copy of a TIFF whose first IFD points to a new GPS IFD with the position of the capture; the new IFDs are
+// appended, everything else stays where it was
+fn geotag_tiff(tif: &[u8], geotag: &Geotag, altitude: f64) -> Result<Vec<u8>, String> {
+    let order = match tif.get(0..2) {
+        Some(b"II") => ByteOrder(true),
+        Some(b"MM") => ByteOrder(false),
+        _ => return Err("not a TIFF file".to_string()),
+    };
+    let ifd0 = order.u32(tif, 4).ok_or("truncated TIFF header")? as usize;
+    let count = order.u16(tif, ifd0).ok_or("truncated IFD")? as usize;
+    let next = order.u32(tif, ifd0 + 2 + 12 * count).ok_or("truncated IFD")?;
+    if tif.len() > u32::MAX as usize / 2 {
+        return Err("file too large".to_string());
+    }
+
+    let mut out = tif.to_vec();
+    let time = geotag.time;
+    let mut gps = vec![
+        Entry { tag: 0x0000, kind: 1, count: 4, value: vec![2, 3, 0, 0] },
+        Entry::ascii(0x0001, if geotag.lat < 0.0 { "S" } else { "N" }),
+        Entry::rationals(0x0002, order, &dms(geotag.lat)),
+        Entry::ascii(0x0003, if geotag.lon < 0.0 { "W" } else { "E" }),
+        Entry::rationals(0x0004, order, &dms(geotag.lon)),
+        Entry { tag: 0x0005, kind: 1, count: 1, value: vec![u8::from(altitude < 0.0)] },
+        Entry::rationals(0x0006, order, &[((altitude.abs() * 1000.0).round() as u32, 1000)]),
+        Entry::rationals(
+            0x0007,
+            order,
+            &[(time.hour(), 1), (time.minute(), 1), (time.second() * 1000 + time.timestamp_subsec_millis(), 1000)],
+        ),
+        Entry::ascii(0x001D, &format!("{:04}:{:02}:{:02}", time.year(), time.month(), time.day())),
+    ];
+    if let Some(sog) = geotag.record.sog {
+        gps.push(Entry::ascii(0x000C, "K"));
+        gps.push(Entry::rationals(0x000D, order, &[((sog * 3.6 * 1000.0).round() as u32, 1000)]));
+    }
+    if let Some(cog) = geotag.record.cog {
+        gps.push(Entry::ascii(0x000E, "T"));
+        gps.push(Entry::rationals(0x000F, order, &[((cog * 100.0).round() as u32, 100)]));
+    }
+    let gps_offset = write_ifd(&mut out, order, &mut gps, 0);
+
+    // the entries of the first IFD are copied as they are, their out of line values keep their offsets
+    let mut entries: Vec<Entry> = (0..count)
+        .map(|i| ifd0 + 2 + 12 * i)
+        .filter_map(|at| {
+            let tag = order.u16(tif, at)?;
+            (tag != TAG_GPS_IFD).then(|| Entry {
+                tag,
+                kind: order.u16(tif, at + 2).unwrap_or(0),
+                count: order.u32(tif, at + 4).unwrap_or(0),
+                value: tif[at + 8..at + 12].to_vec(),
+            })
+        })
+        .collect();
+    let mut pointer = Vec::new();
+    order.put_u32(&mut pointer, gps_offset);
+    entries.push(Entry { tag: TAG_GPS_IFD, kind: 4, count: 1, value: pointer });
+    let new_ifd0 = write_ifd(&mut out, order, &mut entries, next);
+    let mut header = Vec::new();
+    order.put_u32(&mut header, new_ifd0);
+    out[4..8].copy_from_slice(&header);
+    Ok(out)
+}
+
+// archived band file and its geotagged copy, None when the path is not a valid SD card path
+type CopyPaths = Option<(PathBuf, PathBuf)>;
+
+// where the geotagged copy of an archived band file goes, and the archived file
+fn copy_paths(state: &AppState, camera: &str, file: &str) -> CopyPaths {
+    Some((
+        archive::archive_path(&state.config.archive.dir, camera, file)?,
+        archive::archive_path(&state.config.geotags.dir, camera, file)?,
+    ))
+}
+
+// writes geotagged copies of the offloaded band files of the captures of a range under geotags.dir
+pub async fn geotag_write(
+    State(state): State<SharedState>,
+    Extension(session): Extension<Session>,
+    Json(params): Json<GeotagParams>,
+) -> Result<Json<GeotagWriteResult>, AppError> {
+    let altitude = params.altitude_m.unwrap_or(0.0);
+    if !altitude.is_finite() {
+        return Err(AppError::BadRequest("altitude_m must be a number".to_string()));
+    }
+    let (geotags, without_position) = geotags(&state, &params).await?;
+    let jobs: Vec<(Geotag, Vec<CopyPaths>)> = geotags
+        .into_iter()
+        .map(|geotag| {
+            let paths = geotag.files.iter().map(|file| copy_paths(&state, &geotag.record.camera, file)).collect();
+            (geotag, paths)
+        })
+        .collect();
+    let mut result = GeotagWriteResult {
+        dir: state.config.geotags.dir.display().to_string(),
+        captures: jobs.len() as u64 + without_position,
+        captures_without_position: without_position,
+        files_written: 0,
+        files_missing: 0,
+        files_failed: 0,
+        errors: Vec::new(),
+    };
+    let result = task::spawn_blocking(move || {
+        for (geotag, paths) in &jobs {
+            for (file, paths) in geotag.files.iter().zip(paths) {
+                let Some((source, dest)) = paths else {
+                    result.files_missing += 1;
+                    continue;
+                };
+                let Ok(tif) = std::fs::read(source) else {
+                    result.files_missing += 1;
+                    continue;
+                };
+                let written = geotag_tiff(&tif, geotag, altitude).and_then(|copy| {
+                    let part = archive::part_path(dest);
+                    if let Some(dir) = dest.parent() {
+                        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+                    }
+                    std::fs::write(&part, copy).map_err(|e| e.to_string())?;
+                    std::fs::rename(&part, dest).map_err(|e| e.to_string())
+                });
+                match written {
+                    Ok(()) => result.files_written += 1,
+                    Err(e) => {
+                        result.files_failed += 1;
+                        if result.errors.len() < MAX_ERRORS {
+                            result.errors.push(format!("{} of {}: {}", file, geotag.record.camera, e));
+                        }
+                    }
+                }
+            }
+        }
+        result
+    })
+    .await
+    .map_err(|e| AppError::Parse("the geotagged copies".to_string(), e.to_string()))?;
+    info!(
+        "Geotagged copies written by {}: {} files, {} not archived, {} failed",
+        session.username, result.files_written, result.files_missing, result.files_failed
+    );
+    Ok(Json(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use tiff::decoder::{ifd::Value, Decoder, DecodingResult};
+    use tiff::encoder::{colortype::Gray16, TiffEncoder};
+    use tiff::tags::{IfdPointer, Tag};
+
+    fn geotag(lat: f64, lon: f64, sog: Option<f64>, cog: Option<f64>) -> Geotag {
+        let time = "2025-06-01T10:20:30.250Z";
+        Geotag {
+            record: CaptureRecord {
+                capture: "cam1-SET0001-000-0001".to_string(),
+                camera: "cam1".to_string(),
+                file: "/files/SET0001/000/IMG_0001_1.tif".to_string(),
+                time: time.to_string(),
+                lat: Some(lat),
+                lon: Some(lon),
+                cog,
+                sog,
+                depth: None,
+                conductivity: None,
+                oxygen_percentage: None,
+                oxygen_ppm: None,
+                ph: None,
+                pressure: None,
+                salinity: None,
+                temperature: None,
+            },
+            time: time.parse().unwrap(),
+            lat,
+            lon,
+            files: vec!["/files/SET0001/000/IMG_0001_1.tif".to_string()],
+        }
+    }
+
+    fn u16_at(bytes: &[u8], at: usize) -> usize {
+        u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap()) as usize
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn zip_central_directory_points_at_the_files() {
+        let files = vec![
+            ("IMG_0001_1.xmp".to_string(), b"<x:xmpmeta/>".to_vec()),
+            ("IMG_0001_2.xmp".to_string(), Vec::new()),
+            ("IMG_0002_1.xmp".to_string(), vec![0xAB; 1000]),
+        ];
+        let zip = zip(&files);
+
+        let end = zip.len() - 22;
+        assert_eq!(u32_at(&zip, end), 0x06054b50);
+        assert_eq!((u16_at(&zip, end + 8), u16_at(&zip, end + 10)), (3, 3));
+        let (size, offset) = (u32_at(&zip, end + 12) as usize, u32_at(&zip, end + 16) as usize);
+        assert_eq!(offset + size, end);
+
+        let mut at = offset;
+        for (name, data) in &files {
+            assert_eq!(u32_at(&zip, at), 0x02014b50);
+            let crc = u32_at(&zip, at + 16);
+            assert_eq!(crc, crc32fast::hash(data), "{}", name);
+            assert_eq!((u32_at(&zip, at + 20) as usize, u32_at(&zip, at + 24) as usize), (data.len(), data.len()));
+            let name_length = u16_at(&zip, at + 28);
+            assert_eq!(&zip[at + 46..at + 46 + name_length], name.as_bytes());
+            let (extra, comment) = (u16_at(&zip, at + 30), u16_at(&zip, at + 32));
+
+            // the local header agrees with the central directory and is followed by the data
+            let local = u32_at(&zip, at + 42) as usize;
+            assert_eq!(u32_at(&zip, local), 0x04034b50);
+            assert_eq!(u16_at(&zip, local + 8), 0, "stored");
+            assert_eq!(u32_at(&zip, local + 14), crc);
+            assert_eq!(u16_at(&zip, local + 26), name_length);
+            let data_start = local + 30 + name_length + u16_at(&zip, local + 28);
+            assert_eq!(&zip[local + 30..local + 30 + name_length], name.as_bytes());
+            assert_eq!(&zip[data_start..data_start + data.len()], &data[..]);
+
+            at += 46 + name_length + extra + comment;
+        }
+        assert_eq!(at, end);
+    }
+
+    #[test]
+    fn dms_rounds_seconds_to_the_thousandth() {
+        assert_eq!(dms(-43.123456), [(43, 1), (7, 1), (24442, 1000)]);
+        assert_eq!(dms(5.5), [(5, 1), (30, 1), (0, 1000)]);
+    }
+
+    #[test]
+    fn rounding_carries_into_minutes_and_degrees() {
+        assert_eq!(dms(10.0 + 59.0 / 60.0 + 59.9999 / 3600.0), [(11, 1), (0, 1), (0, 1000)]);
+        assert_eq!(dms(10.0 + 5.0 / 60.0 + 59.9999 / 3600.0), [(10, 1), (6, 1), (0, 1000)]);
+        assert_eq!(xmp_coordinate(-(10.0 + 59.9999999 / 60.0), 'N', 'S'), "11,0.000000S");
+        assert_eq!(xmp_coordinate(43.1020575, 'N', 'S'), "43,6.123450N");
+    }
+
+    fn gps_tags(tif: &[u8]) -> (Decoder<Cursor<&[u8]>>, tiff::Directory) {
+        let mut decoder = Decoder::new(Cursor::new(tif)).unwrap();
+        let gps = decoder.get_tag_u32(Tag::GpsDirectory).unwrap();
+        let directory = decoder.read_directory(IfdPointer(gps.into())).unwrap();
+        (decoder, directory)
+    }
+
+    fn gps_tag(decoder: &mut Decoder<Cursor<&[u8]>>, directory: &tiff::Directory, tag: u16) -> Value {
+        decoder.read_directory_tags(directory).get_tag(Tag::from_u16_exhaustive(tag)).unwrap()
+    }
+
+    fn rationals(values: &[(u32, u32)]) -> Value {
+        Value::List(values.iter().map(|&(n, d)| Value::Rational(n, d)).collect())
+    }
+
+    #[test]
+    fn geotagged_tiff_keeps_the_image_and_its_tags() {
+        let pixels: Vec<u16> = (0..12).map(|i| i * 1000).collect();
+        let mut tif = Cursor::new(Vec::new());
+        let mut encoder = TiffEncoder::new(&mut tif).unwrap();
+        let mut image = encoder.new_image::<Gray16>(4, 3).unwrap();
+        image.encoder().write_tag(Tag::Make, "MicaSense").unwrap();
+        image.encoder().write_tag(Tag::Model, "RedEdge-M").unwrap();
+        image.write_data(&pixels).unwrap();
+        let tif = tif.into_inner();
+
+        let out = geotag_tiff(&tif, &geotag(-43.123456, 5.5, Some(2.0), Some(181.25)), 2.5).unwrap();
+        // the original bytes stay where they were, only the first IFD offset changes
+        assert_eq!(&out[..4], &tif[..4]);
+        assert_eq!(&out[8..tif.len()], &tif[8..]);
+
+        let mut decoder = Decoder::new(Cursor::new(out.as_slice())).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (4, 3));
+        assert_eq!(decoder.get_tag_ascii_string(Tag::Make).unwrap(), "MicaSense");
+        assert_eq!(decoder.get_tag_ascii_string(Tag::Model).unwrap(), "RedEdge-M");
+        match decoder.read_image().unwrap() {
+            DecodingResult::U16(decoded) => assert_eq!(decoded, pixels),
+            other => panic!("unexpected image data {:?}", other),
+        }
+
+        let (mut decoder, gps) = gps_tags(&out);
+        assert_eq!(gps_tag(&mut decoder, &gps, 0x0000).into_u32_vec().unwrap(), [2, 3, 0, 0]);
+        assert_eq!(gps_tag(&mut decoder, &gps, 0x0001), Value::Ascii("S".to_string()));
+        assert_eq!(gps_tag(&mut decoder, &gps, 0x0002), rationals(&[(43, 1), (7, 1), (24442, 1000)]));
+        assert_eq!(gps_tag(&mut decoder, &gps, 0x0003), Value::Ascii("E".to_string()));
+        assert_eq!(gps_tag(&mut decoder, &gps, 0x0004), rationals(&[(5, 1), (30, 1), (0, 1000)]));
+        assert_eq!(gps_tag(&mut decoder, &gps, 0x0005).into_u32().unwrap(), 0);
+        assert_eq!(gps_tag(&mut decoder, &gps, 0x0006), Value::Rational(2500, 1000));
+        assert_eq!(gps_tag(&mut decoder, &gps, 0x0007), rationals(&[(10, 1), (20, 1), (30250, 1000)]));
+        assert_eq!(gps_tag(&mut decoder, &gps, 0x000C), Value::Ascii("K".to_string()));
+        assert_eq!(gps_tag(&mut decoder, &gps, 0x000D), Value::Rational(7200, 1000));
+        assert_eq!(gps_tag(&mut decoder, &gps, 0x000E), Value::Ascii("T".to_string()));
+        assert_eq!(gps_tag(&mut decoder, &gps, 0x000F), Value::Rational(18125, 100));
+        assert_eq!(gps_tag(&mut decoder, &gps, 0x001D), Value::Ascii("2025:06:01".to_string()));
+    }
+
+    fn short(tag: u16, order: ByteOrder, value: u16) -> Entry {
+        let mut bytes = Vec::new();
+        order.put_u16(&mut bytes, value);
+        Entry { tag, kind: 3, count: 1, value: bytes }
+    }
+
+    fn long(tag: u16, order: ByteOrder, value: u32) -> Entry {
+        let mut bytes = Vec::new();
+        order.put_u32(&mut bytes, value);
+        Entry { tag, kind: 4, count: 1, value: bytes }
+    }
+
+    #[test]
+    fn geotagging_replaces_the_gps_ifd_of_a_big_endian_tiff() {
+        let order = ByteOrder(false);
+        // 2x2 8 bit grayscale, a GPS IFD saying north and the first IFD pointing at it
+        let mut tif = b"MM\0\x2a\0\0\0\0".to_vec();
+        tif.extend_from_slice(&[10, 20, 30, 40]);
+        let old_gps = write_ifd(&mut tif, order, &mut [Entry::ascii(0x0001, "N")], 0);
+        let ifd0 = write_ifd(
+            &mut tif,
+            order,
+            &mut [
+                short(256, order, 2),
+                short(257, order, 2),
+                short(258, order, 8),
+                short(259, order, 1),
+                short(262, order, 1),
+                long(273, order, 8),
+                short(278, order, 2),
+                long(279, order, 4),
+                Entry::ascii(305, "RedEdge firmware"),
+                long(TAG_GPS_IFD, order, old_gps),
+            ],
+            0,
+        );
+        tif[4..8].copy_from_slice(&ifd0.to_be_bytes());
+
+        let out = geotag_tiff(&tif, &geotag(-12.5, -70.25, None, None), -3.0).unwrap();
+        let mut decoder = Decoder::new(Cursor::new(out.as_slice())).unwrap();
+        assert_eq!(decoder.get_tag_ascii_string(Tag::Software).unwrap(), "RedEdge firmware");
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(decoded) => assert_eq!(decoded, [10, 20, 30, 40]),
+            other => panic!("unexpected image data {:?}", other),
+        }
+        assert_ne!(decoder.get_tag_u32(Tag::GpsDirectory).unwrap(), old_gps);
+
+        let (mut decoder, gps) = gps_tags(&out);
+        assert_eq!(gps_tag(&mut decoder, &gps, 0x0001), Value::Ascii("S".to_string()));
+        assert_eq!(gps_tag(&mut decoder, &gps, 0x0002), rationals(&[(12, 1), (30, 1), (0, 1000)]));
+        assert_eq!(gps_tag(&mut decoder, &gps, 0x0003), Value::Ascii("W".to_string()));
+        assert_eq!(gps_tag(&mut decoder, &gps, 0x0004), rationals(&[(70, 1), (15, 1), (0, 1000)]));
+        assert_eq!(gps_tag(&mut decoder, &gps, 0x0005).into_u32().unwrap(), 1);
+        assert_eq!(gps_tag(&mut decoder, &gps, 0x0006), Value::Rational(3000, 1000));
+        assert!(decoder.read_directory_tags(&gps).find_tag(Tag::from_u16_exhaustive(0x000D)).unwrap().is_none());
+    }
+
+    #[test]
+    fn geotagging_rejects_other_files() {
+        assert!(geotag_tiff(b"\x89PNG\r\n\x1a\n", &geotag(0.0, 0.0, None, None), 0.0).is_err());
+        assert!(geotag_tiff(b"II\x2a\0\xff\0\0\0", &geotag(0.0, 0.0, None, None), 0.0).is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::header,
+    response::IntoResponse,
+};
+use brig_types::{CaptureId, CompositeParams};
+use image::codecs::jpeg::JpegEncoder;
+use image::{load_from_memory_with_format, DynamicImage, GrayImage, ImageBuffer, ImageFormat, Luma, RgbImage};
+use tokio::task;
+
+use crate::camera::camera_config;
+use crate::config::BandConfig;
+use crate::error::AppError;
+use crate::reflectance;
+use crate::SharedState;
+
+/*
+Rendering of RedEdge captures for the UI.
+Every band is a single channel 16 bit TIFF whose values only use a small part of the 16 bit range, so a plain
+conversion to 8 bit is nearly black. Bands are instead stretched between two percentiles of their own histogram
+(2% and 98% by default), and up to three bands of a capture can be put in the red, green and blue channels of a
+composite, e.g. NIR-R-G to make vegetation stand out. The imagers of a RedEdge are side by side and the bands are
+not registered to each other, so composites of close objects show colour fringes.
+*/
+
+// percentiles mapped to black and white unless the request says otherwise
+pub const DEFAULT_LOW: f64 = 2.0;
+pub const DEFAULT_HIGH: f64 = 98.0;
+const JPEG_QUALITY: u8 = 90;
+
+pub type Band = ImageBuffer<Luma<u16>, Vec<u16>>;
+
+pub fn decode_band(tif: &[u8]) -> Result<Band, image::ImageError> {
+    load_from_memory_with_format(tif, ImageFormat::Tiff).map(|img| img.to_luma16())
+}
+
+// values at the low and high percentiles (0 to 100) of a band
+pub fn percentile_range(band: &Band, low: f64, high: f64) -> (u16, u16) {
+    let mut histogram = vec![0u64; u16::MAX as usize + 1];
+    for &value in band.as_raw() {
+        histogram[value as usize] += 1;
+    }
+    let total = band.as_raw().len() as u64;
+    let value_at = |percentile: f64| {
+        let target = (percentile / 100.0 * total as f64).round() as u64;
+        let mut seen = 0;
+        for (value, &count) in histogram.iter().enumerate() {
+            seen += count;
+            if seen > 0 && seen >= target {
+                return value as u16;
+            }
+        }
+        u16::MAX
+    };
+    (value_at(low), value_at(high))
+}
+
+// maps lo..=hi linearly to 0..=255, clipping the values outside
+pub fn stretch(band: &Band, lo: u16, hi: u16) -> GrayImage {
+    let span = hi.saturating_sub(lo).max(1) as f64;
+    ImageBuffer::from_fn(band.width(), band.height(), |x, y| {
+        let value = band.get_pixel(x, y)[0].saturating_sub(lo) as f64;
+        Luma([(value / span * 255.0).round().min(255.0) as u8])
+    })
+}
+
+// one band as grayscale, or three bands as red, green and blue, each stretched between its percentiles
+pub fn render(bands: &[Band], low: f64, high: f64) -> Result<DynamicImage, String> {
+    let stretched: Vec<GrayImage> = bands
+        .iter()
+        .map(|band| {
+            let (lo, hi) = percentile_range(band, low, high);
+            stretch(band, lo, hi)
+        })
+        .collect();
+    match stretched.as_slice() {
+        [gray] => Ok(DynamicImage::ImageLuma8(gray.clone())),
+        [r, g, b] => {
+            if r.dimensions() != g.dimensions() || r.dimensions() != b.dimensions() {
+                return Err("the bands do not have the same size".to_string());
+            }
+            let rgb = RgbImage::from_fn(r.width(), r.height(), |x, y| {
+                image::Rgb([r.get_pixel(x, y)[0], g.get_pixel(x, y)[0], b.get_pixel(x, y)[0]])
+            });
+            Ok(DynamicImage::ImageRgb8(rgb))
+        }
+        _ => Err(format!("a composite takes 1 or 3 bands, got {}", bands.len())),
+    }
+}
+
+pub fn encode_jpeg(img: &DynamicImage) -> Result<Vec<u8>, image::ImageError> {
+    let mut jpeg = Vec::new();
+    img.write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY))?;
+    Ok(jpeg)
+}
+
+// renders bands as a JPEG, off the async workers
+pub async fn render_jpeg(what: String, bands: Vec<Band>, low: f64, high: f64) -> Result<Vec<u8>, AppError> {
+    task::spawn_blocking(move || {
+        let img = render(&bands, low, high)?;
+        encode_jpeg(&img).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result)
+    .map_err(|e| AppError::Parse(what, e))
+}
+
+// number of a band by name, ignoring case, spaces and punctuation; a name that is the start of a band name
+// also matches when nothing matches exactly, e.g. "red edge" for "Red edge 705" of an MX Blue
+pub fn band_by_name(bands: &[BandConfig], name: &str) -> Option<u8> {
+    let simplify = |s: &str| s.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_ascii_lowercase();
+    let name = simplify(name);
+    let exact = bands.iter().position(|b| simplify(&b.name) == name);
+    let prefix = || bands.iter().position(|b| !name.is_empty() && simplify(&b.name).starts_with(&name));
+    exact.or_else(prefix).map(|i| i as u8 + 1)
+}
+
+// number of a band given by number or name
+pub fn parse_band(entry: &str, bands: &[BandConfig]) -> Result<u8, AppError> {
+    let band = match entry.trim().parse::<usize>() {
+        Ok(number) => (1..=bands.len()).contains(&number).then_some(number as u8),
+        Err(_) => band_by_name(bands, entry),
+    };
+    band.ok_or_else(|| {
+        let names: Vec<&str> = bands.iter().map(|b| b.name.as_str()).collect();
+        AppError::BadRequest(format!("unknown band {}, use 1 to {} or one of {}", entry.trim(), bands.len(), names.join(", ")))
+    })
+}
+
+// band numbers from a list of numbers or names, e.g. "5,3,2" or "nir,red,green"
+fn parse_bands(list: &str, bands: &[BandConfig]) -> Result<Vec<u8>, AppError> {
+    list.split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| parse_band(entry, bands))
+        .collect()
+}
+
+// percentiles of the request, checked
+fn percentiles(params: &CompositeParams) -> Result<(f64, f64), AppError> {
+    let (low, high) = (params.low.unwrap_or(DEFAULT_LOW), params.high.unwrap_or(DEFAULT_HIGH));
+    if !(0.0..100.0).contains(&low) || !(low..=100.0).contains(&high) || low >= high {
+        return Err(AppError::BadRequest("low and high must be percentiles with 0 <= low < high <= 100".to_string()));
+    }
+    Ok((low, high))
+}
+
+// JPEG of one band or a three band composite of a capture, raw or in reflectance; the band files come from the
+// archive when offloaded
+pub async fn capture_composite(
+    State(state): State<SharedState>,
+    Path(capture): Path<String>,
+    Query(params): Query<CompositeParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let id = CaptureId::parse(&capture)
+        .ok_or_else(|| AppError::BadRequest(format!("{} is not a capture id like cam1-SET0001-000-0001", capture)))?;
+    let config = camera_config(&state, &id.camera)?;
+    let bands = parse_bands(&params.bands, &config.bands)?;
+    if bands.len() != 1 && bands.len() != 3 {
+        return Err(AppError::BadRequest(format!("a composite takes 1 or 3 bands, got {}", bands.len())));
+    }
+    let (low, high) = percentiles(&params)?;
+    let images = reflectance::capture_bands(&state, &id, &bands, params.reflectance).await?;
+    let jpeg = render_jpeg(format!("capture {}", id), images, low, high).await?;
+    Ok(([(header::CONTENT_TYPE, "image/jpeg")], jpeg))
+}
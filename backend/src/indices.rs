@@ -0,0 +1,261 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::header,
+    response::IntoResponse,
+    Json,
+};
+use brig_types::{CaptureId, IndexParams, IndexStats, LegendStop, Percentile};
+use image::{DynamicImage, ImageBuffer, ImageFormat, Rgba, RgbaImage};
+use std::io::Cursor;
+use tokio::task;
+
+use crate::camera::camera_config;
+use crate::config::BandConfig;
+use crate::error::AppError;
+use crate::imaging::{band_by_name, parse_band, Band};
+use crate::reflectance;
+use crate::{AppState, SharedState};
+
+/*
+Spectral indices of a capture: normalized differences (a - b) / (a + b) of two of its bands, per pixel.
+NDVI (NIR, Red) shows vegetation, NDWI (Green, NIR, McFeeters) open water and NDRE (NIR, Red edge) the state of
+vegetation where NDVI saturates; any other pair can be asked for as a custom index.
+The index is computed on the raw sensor values unless reflectance is asked for (see reflectance.rs); raw indices are
+only comparable between captures taken with the same exposure and light. The bands are not registered to each other
+either, edges of close objects are unreliable.
+Images use one fixed colour map from -1 to 1 so captures can be compared at a glance, with the colour bar and its
+labels drawn below the capture; pixels where a + b is 0 are transparent.
+*/
+
+// name, band a and band b of the predefined indices
+const INDICES: [(&str, &str, &str); 3] = [("NDVI", "NIR", "Red"), ("NDWI", "Green", "NIR"), ("NDRE", "NIR", "Red edge")];
+
+// percentiles reported in IndexStats
+const PERCENTILES: [f64; 5] = [2.0, 25.0, 50.0, 75.0, 98.0];
+
+// diverging red-yellow-green colour map, from -1 to 1
+const COLOR_MAP: [(f64, [u8; 3]); 5] = [
+    (-1.0, [165, 0, 38]),
+    (-0.5, [244, 109, 67]),
+    (0.0, [255, 255, 191]),
+    (0.5, [102, 189, 99]),
+    (1.0, [0, 104, 55]),
+];
+
+// index of a capture, the values in row order
+struct IndexImage {
+    name: String,
+    formula: String,
+    a: u8,
+    b: u8,
+    reflectance: bool,
+    width: u32,
+    height: u32,
+    values: Vec<Option<f32>>,
+}
+
+// name, formula and bands of the requested index
+fn resolve(params: &IndexParams, bands: &[BandConfig]) -> Result<(String, String, u8, u8), AppError> {
+    let band_name = |band: u8| bands[band as usize - 1].name.clone();
+    if params.index.eq_ignore_ascii_case("custom") {
+        let (Some(a), Some(b)) = (params.a.as_deref(), params.b.as_deref()) else {
+            return Err(AppError::BadRequest("a custom index needs the bands a and b".to_string()));
+        };
+        let (a, b) = (parse_band(a, bands)?, parse_band(b, bands)?);
+        let (name_a, name_b) = (band_name(a), band_name(b));
+        return Ok(("Custom".to_string(), format!("({} - {}) / ({} + {})", name_a, name_b, name_a, name_b), a, b));
+    }
+    let Some(&(name, a, b)) = INDICES.iter().find(|(name, ..)| name.eq_ignore_ascii_case(&params.index)) else {
+        return Err(AppError::BadRequest(format!("unknown index {}, use ndvi, ndwi, ndre or custom", params.index)));
+    };
+    let find = |band: &str| {
+        band_by_name(bands, band)
+            .ok_or_else(|| AppError::BadRequest(format!("{} needs a {} band, which this camera does not have", name, band)))
+    };
+    let (a, b) = (find(a)?, find(b)?);
+    let formula = format!("({} - {}) / ({} + {})", band_name(a), band_name(b), band_name(a), band_name(b));
+    Ok((name.to_string(), formula, a, b))
+}
+
+// (a - b) / (a + b) per pixel, None where a + b is 0
+pub fn normalized_difference(a: &Band, b: &Band) -> Result<Vec<Option<f32>>, String> {
+    if a.dimensions() != b.dimensions() {
+        return Err("the bands do not have the same size".to_string());
+    }
+    Ok(a.as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(&a, &b)| {
+            let (a, b) = (a as f32, b as f32);
+            (a + b > 0.0).then(|| (a - b) / (a + b))
+        })
+        .collect())
+}
+
+async fn compute(state: &AppState, capture: &str, params: &IndexParams) -> Result<(CaptureId, IndexImage), AppError> {
+    let id = CaptureId::parse(capture)
+        .ok_or_else(|| AppError::BadRequest(format!("{} is not a capture id like cam1-SET0001-000-0001", capture)))?;
+    let config = camera_config(state, &id.camera)?;
+    let (name, formula, a, b) = resolve(params, &config.bands)?;
+    let reflectance = params.reflectance;
+    let [band_a, band_b]: [Band; 2] = reflectance::capture_bands(state, &id, &[a, b], reflectance)
+        .await?
+        .try_into()
+        .map_err(|_| AppError::Parse(format!("capture {}", id), "missing bands".to_string()))?;
+    let index = task::spawn_blocking(move || {
+        let values = normalized_difference(&band_a, &band_b)?;
+        let (width, height) = band_a.dimensions();
+        Ok::<_, String>(IndexImage { name, formula, a, b, reflectance, width, height, values })
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result)
+    .map_err(|e| AppError::Parse(format!("capture {}", id), e))?;
+    Ok((id, index))
+}
+
+fn stats(id: &CaptureId, index: &IndexImage) -> IndexStats {
+    let mut valid: Vec<f32> = index.values.iter().flatten().copied().collect();
+    valid.sort_unstable_by(f32::total_cmp);
+    let percentile = |p: f64| valid[(p / 100.0 * (valid.len() - 1) as f64).round() as usize] as f64;
+    let has_values = !valid.is_empty();
+    IndexStats {
+        capture: id.to_string(),
+        index: index.name.clone(),
+        formula: index.formula.clone(),
+        a_band: index.a,
+        b_band: index.b,
+        reflectance: index.reflectance,
+        valid_pixels: valid.len() as u64,
+        total_pixels: index.values.len() as u64,
+        mean: has_values.then(|| valid.iter().map(|&v| v as f64).sum::<f64>() / valid.len() as f64),
+        min: valid.first().map(|&v| v as f64),
+        max: valid.last().map(|&v| v as f64),
+        percentiles: if has_values {
+            PERCENTILES.iter().map(|&p| Percentile { percentile: p, value: percentile(p) }).collect()
+        } else {
+            Vec::new()
+        },
+        legend: COLOR_MAP
+            .iter()
+            .map(|(value, [r, g, b])| LegendStop { value: *value, color: format!("#{:02x}{:02x}{:02x}", r, g, b) })
+            .collect(),
+    }
+}
+
+// colour of an index value, interpolated between the stops of COLOR_MAP
+fn color(value: f64) -> [u8; 3] {
+    let value = value.clamp(-1.0, 1.0);
+    for pair in COLOR_MAP.windows(2) {
+        let ((v0, c0), (v1, c1)) = (pair[0], pair[1]);
+        if value <= v1 {
+            let t = (value - v0) / (v1 - v0);
+            return [0, 1, 2].map(|i| (c0[i] as f64 + t * (c1[i] as f64 - c0[i] as f64)).round() as u8);
+        }
+    }
+    COLOR_MAP[COLOR_MAP.len() - 1].1
+}
+
+// height of the legend below the capture, and its labels
+const LEGEND_HEIGHT: u32 = 40;
+const LEGEND_LABELS: [(f64, &str); 5] = [(-1.0, "-1"), (-0.5, "-0.5"), (0.0, "0"), (0.5, "0.5"), (1.0, "1")];
+
+// 3x5 pixel glyphs of the legend labels, one row per entry, leftmost pixel in the highest bit
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        _ => [0; 5],
+    }
+}
+
+// draws text with its top center at (x, y), every glyph pixel as a scale x scale square
+fn draw_text(img: &mut RgbaImage, text: &str, x: i64, y: i64, scale: i64) {
+    let advance = 4 * scale;
+    let left = x - (text.len() as i64 * advance - scale) / 2;
+    for (n, c) in text.chars().enumerate() {
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..3 {
+                if bits & (0b100 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let (px, py) = (left + n as i64 * advance + col * scale + dx, y + row as i64 * scale + dy);
+                        if px >= 0 && py >= 0 && (px as u32) < img.width() && (py as u32) < img.height() {
+                            img.put_pixel(px as u32, py as u32, Rgba([0, 0, 0, 255]));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// colour mapped index with the colour bar below
+fn index_image(index: &IndexImage) -> RgbaImage {
+    let (width, height) = (index.width, index.height);
+    let mut img: RgbaImage = ImageBuffer::from_pixel(width, height + LEGEND_HEIGHT, Rgba([255, 255, 255, 255]));
+    for (i, value) in index.values.iter().enumerate() {
+        let (x, y) = (i as u32 % width, i as u32 / width);
+        let pixel = match value {
+            Some(v) => {
+                let [r, g, b] = color(*v as f64);
+                Rgba([r, g, b, 255])
+            }
+            None => Rgba([0, 0, 0, 0]),
+        };
+        img.put_pixel(x, y, pixel);
+    }
+    let margin = 16.min(width / 4);
+    let (bar_left, bar_right) = (margin, width.saturating_sub(margin).max(margin + 1));
+    let x_of = |value: f64| bar_left + ((value + 1.0) / 2.0 * (bar_right - bar_left - 1) as f64).round() as u32;
+    for x in bar_left..bar_right {
+        let [r, g, b] = color((x - bar_left) as f64 / (bar_right - bar_left - 1).max(1) as f64 * 2.0 - 1.0);
+        for y in height + 4..height + 16 {
+            img.put_pixel(x, y, Rgba([r, g, b, 255]));
+        }
+    }
+    for (value, label) in LEGEND_LABELS {
+        let x = x_of(value);
+        for y in height + 16..height + 20 {
+            img.put_pixel(x, y, Rgba([0, 0, 0, 255]));
+        }
+        draw_text(&mut img, label, x as i64, (height + 23) as i64, 2);
+    }
+    img
+}
+
+// summary statistics of an index of a capture
+pub async fn capture_index(
+    State(state): State<SharedState>,
+    Path(capture): Path<String>,
+    Query(params): Query<IndexParams>,
+) -> Result<Json<IndexStats>, AppError> {
+    let (id, index) = compute(&state, &capture, &params).await?;
+    let stats = task::spawn_blocking(move || stats(&id, &index))
+        .await
+        .map_err(|e| AppError::Parse(format!("capture {}", capture), e.to_string()))?;
+    Ok(Json(stats))
+}
+
+// PNG of an index of a capture, colour mapped from -1 to 1 with a legend
+pub async fn capture_index_image(
+    State(state): State<SharedState>,
+    Path(capture): Path<String>,
+    Query(params): Query<IndexParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let (id, index) = compute(&state, &capture, &params).await?;
+    let png = task::spawn_blocking(move || {
+        let mut png = Cursor::new(Vec::new());
+        DynamicImage::ImageRgba8(index_image(&index)).write_to(&mut png, ImageFormat::Png).map(|_| png.into_inner())
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result.map_err(|e| e.to_string()))
+    .map_err(|e| AppError::Parse(format!("the index image of {}", id), e))?;
+    Ok(([(header::CONTENT_TYPE, "image/png")], png))
+}
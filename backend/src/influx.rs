@@ -0,0 +1,654 @@
+use chrono::{DateTime, Utc};
+use csv::ReaderBuilder;
+use reqwest::Client;
+use std::collections::HashMap;
+use std::fmt;
+use tracing::info;
+
+use crate::config::InfluxConfig;
+
+/*
+InfluxDB v2 client.
+Queries are built with FluxQuery from typed parameters instead of format!, and responses are parsed by column name
+into FluxRecords, so a new tag or a different column order does not shift the values we read.
+*/
+
+#[derive(Clone)]
+pub struct InfluxClient {
+    client: Client,
+    url: String,
+    token: String,
+    bucket: String,
+}
+
+#[derive(Debug)]
+pub enum InfluxError {
+    // influx could not be reached or the response could not be read
+    Request(reqwest::Error),
+    // influx answered with a non-2xx status
+    Status(reqwest::StatusCode, String),
+    // influx answered with an error table inside the CSV body
+    Query(String),
+    Csv(csv::Error),
+}
+
+impl fmt::Display for InfluxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InfluxError::Request(e) => write!(f, "influx request failed: {}", e),
+            InfluxError::Status(status, body) => write!(f, "influx returned {}: {}", status, body),
+            InfluxError::Query(msg) => write!(f, "flux query failed: {}", msg),
+            InfluxError::Csv(e) => write!(f, "could not parse influx response: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for InfluxError {}
+
+impl InfluxClient {
+    pub fn new(client: Client, config: &InfluxConfig) -> Self {
+        Self {
+            client,
+            url: format!("{}/api/v2/query?org={}", config.host, config.org),
+            token: config.token.clone(),
+            bucket: config.bucket.clone(),
+        }
+    }
+
+    // starts a query on the configured bucket
+    pub fn flux(&self) -> FluxQuery {
+        FluxQuery::new(&self.bucket)
+    }
+
+    // runs a flux query and returns the CSV body as is
+    pub async fn query_raw(&self, flux: &str) -> Result<String, InfluxError> {
+        info!("Executing flux query:\n{}", flux);
+        let response = self.client
+            .post(&self.url)
+            .header("Authorization", format!("Token {}", self.token))
+            .header("Accept", "application/csv")
+            .header("Content-Type", "application/vnd.flux")
+            .body(flux.to_string())
+            .send()
+            .await
+            .map_err(InfluxError::Request)?;
+        let status = response.status();
+        let text = response.text().await.map_err(InfluxError::Request)?;
+        if !status.is_success() {
+            return Err(InfluxError::Status(status, text));
+        }
+        Ok(text)
+    }
+
+    // runs a flux query and parses the result into records
+    pub async fn query(&self, flux: &str) -> Result<Vec<FluxRecord>, InfluxError> {
+        let text = self.query_raw(flux).await?;
+        parse_csv(&text)
+    }
+}
+
+// Influx duration literal, e.g. -24h, 1m, 1h30m
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Duration(String);
+
+impl Duration {
+    pub fn parse(s: &str) -> Option<Self> {
+        let body = s.strip_prefix('-').unwrap_or(s);
+        let mut rest = body;
+        if rest.is_empty() {
+            return None;
+        }
+        while !rest.is_empty() {
+            let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            if digits == 0 {
+                return None;
+            }
+            rest = &rest[digits..];
+            let unit = ["ns", "us", "ms", "mo", "s", "m", "h", "d", "w", "y"]
+                .into_iter()
+                .find(|u| rest.starts_with(u))?;
+            rest = &rest[unit.len()..];
+        }
+        Some(Self(s.to_string()))
+    }
+
+    // length in seconds, months and years are approximated as 30 and 365 days
+    pub fn seconds(&self) -> i64 {
+        let body = self.0.strip_prefix('-').unwrap_or(&self.0);
+        let sign = if body.len() < self.0.len() { -1.0 } else { 1.0 };
+        let mut total = 0.0;
+        let mut rest = body;
+        while !rest.is_empty() {
+            let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let n: f64 = rest[..digits].parse().unwrap_or(0.0);
+            rest = &rest[digits..];
+            let (unit, secs) = [("ns", 1e-9), ("us", 1e-6), ("ms", 1e-3), ("mo", 2_592_000.0), ("s", 1.0),
+                                ("m", 60.0), ("h", 3_600.0), ("d", 86_400.0), ("w", 604_800.0), ("y", 31_536_000.0)]
+                .into_iter()
+                .find(|(u, _)| rest.starts_with(u))
+                .unwrap_or(("", 0.0));
+            rest = &rest[unit.len()..];
+            total += n * secs;
+        }
+        (sign * total) as i64
+    }
+
+    // for literals in the code, panics on an invalid duration
+    pub fn from_static(s: &'static str) -> Self {
+        Self::parse(s).unwrap_or_else(|| panic!("invalid flux duration literal {}", s))
+    }
+}
+
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum TimeBound {
+    Relative(Duration),
+    Absolute(DateTime<Utc>),
+}
+
+impl fmt::Display for TimeBound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeBound::Relative(d) => write!(f, "{}", d),
+            TimeBound::Absolute(t) => write!(f, "{}", t.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    Mean,
+    Min,
+    Max,
+    First,
+    Last,
+    Median,
+}
+
+impl Aggregate {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "mean" => Some(Aggregate::Mean),
+            "min" => Some(Aggregate::Min),
+            "max" => Some(Aggregate::Max),
+            "first" => Some(Aggregate::First),
+            "last" => Some(Aggregate::Last),
+            "median" => Some(Aggregate::Median),
+            _ => None,
+        }
+    }
+
+    fn as_flux(&self) -> &'static str {
+        match self {
+            Aggregate::Mean => "mean",
+            Aggregate::Min => "min",
+            Aggregate::Max => "max",
+            Aggregate::First => "first",
+            Aggregate::Last => "last",
+            Aggregate::Median => "median",
+        }
+    }
+}
+
+// Builder for the flux queries used by the backend.
+// Stages are always emitted in the same order: range, filters, keep/drop, window, last, sort, limit, count, pivot, yield.
+#[derive(Debug, Clone)]
+pub struct FluxQuery {
+    bucket: String,
+    start: TimeBound,
+    stop: Option<TimeBound>,
+    measurements: Vec<String>,
+    fields: Vec<String>,
+    tags: Vec<(String, Vec<String>)>,
+    value: Option<String>,
+    value_regex: Option<String>,
+    keep: Vec<String>,
+    drop: Vec<String>,
+    window: Option<(Duration, Aggregate)>,
+    last: bool,
+    sort: bool,
+    limit: Option<(u64, u64)>,
+    count: bool,
+    pivot: bool,
+    yield_name: Option<String>,
+}
+
+impl FluxQuery {
+    pub fn new(bucket: &str) -> Self {
+        Self {
+            bucket: bucket.to_string(),
+            start: TimeBound::Relative(Duration::from_static("-1h")),
+            stop: None,
+            measurements: Vec::new(),
+            fields: Vec::new(),
+            tags: Vec::new(),
+            value: None,
+            value_regex: None,
+            keep: Vec::new(),
+            drop: Vec::new(),
+            window: None,
+            last: false,
+            sort: false,
+            limit: None,
+            count: false,
+            pivot: false,
+            yield_name: None,
+        }
+    }
+
+    // range relative to now, e.g. Duration::from_static("-24h")
+    pub fn range_since(mut self, start: Duration) -> Self {
+        self.start = TimeBound::Relative(start);
+        self.stop = None;
+        self
+    }
+
+    pub fn range(mut self, start: DateTime<Utc>, stop: DateTime<Utc>) -> Self {
+        self.start = TimeBound::Absolute(start);
+        self.stop = Some(TimeBound::Absolute(stop));
+        self
+    }
+
+    pub fn measurement(mut self, measurement: &str) -> Self {
+        self.measurements.push(measurement.to_string());
+        self
+    }
+
+    pub fn measurements<S: AsRef<str>>(mut self, measurements: &[S]) -> Self {
+        self.measurements.extend(measurements.iter().map(|m| m.as_ref().to_string()));
+        self
+    }
+
+    pub fn field(mut self, field: &str) -> Self {
+        self.fields.push(field.to_string());
+        self
+    }
+
+    pub fn fields<S: AsRef<str>>(mut self, fields: &[S]) -> Self {
+        self.fields.extend(fields.iter().map(|f| f.as_ref().to_string()));
+        self
+    }
+
+    pub fn tag(mut self, tag: &str, value: &str) -> Self {
+        self.tags.push((tag.to_string(), vec![value.to_string()]));
+        self
+    }
+
+    // rows whose tag is any of the values, none if there are no values
+    pub fn tags<S: AsRef<str>>(mut self, tag: &str, values: &[S]) -> Self {
+        self.tags.push((tag.to_string(), values.iter().map(|v| v.as_ref().to_string()).collect()));
+        self
+    }
+
+    // only rows whose (string) _value equals the given value
+    pub fn value_eq(mut self, value: &str) -> Self {
+        self.value = Some(value.to_string());
+        self
+    }
+
+    // only rows whose (string) _value matches the regular expression
+    pub fn value_matches(mut self, regex: &str) -> Self {
+        self.value_regex = Some(regex.to_string());
+        self
+    }
+
+    pub fn keep(mut self, columns: &[&str]) -> Self {
+        self.keep.extend(columns.iter().map(|c| c.to_string()));
+        self
+    }
+
+    pub fn drop(mut self, columns: &[&str]) -> Self {
+        self.drop.extend(columns.iter().map(|c| c.to_string()));
+        self
+    }
+
+    pub fn aggregate_window(mut self, every: Duration, aggregate: Aggregate) -> Self {
+        self.window = Some((every, aggregate));
+        self
+    }
+
+    pub fn last(mut self) -> Self {
+        self.last = true;
+        self
+    }
+
+    // oldest first within each table
+    pub fn sort_by_time(mut self) -> Self {
+        self.sort = true;
+        self
+    }
+
+    // at most n rows of each table, after skipping the first offset
+    pub fn limit(mut self, n: u64, offset: u64) -> Self {
+        self.limit = Some((n, offset));
+        self
+    }
+
+    // one row per table whose _value is its number of rows
+    pub fn count(mut self) -> Self {
+        self.count = true;
+        self
+    }
+
+    // one row per timestamp with a column per field
+    pub fn pivot(mut self) -> Self {
+        self.pivot = true;
+        self
+    }
+
+    pub fn yield_as(mut self, name: &str) -> Self {
+        self.yield_name = Some(name.to_string());
+        self
+    }
+
+    pub fn build(&self) -> String {
+        let mut q = format!("from(bucket: {})\n", flux_string(&self.bucket));
+        match &self.stop {
+            Some(stop) => q.push_str(&format!("    |> range(start: {}, stop: {})\n", self.start, stop)),
+            None => q.push_str(&format!("    |> range(start: {})\n", self.start)),
+        }
+        if !self.measurements.is_empty() {
+            q.push_str(&format!("    |> filter(fn: (r) => {})\n", any_of("_measurement", &self.measurements)));
+        }
+        if !self.fields.is_empty() {
+            q.push_str(&format!("    |> filter(fn: (r) => {})\n", any_of("_field", &self.fields)));
+        }
+        for (tag, values) in &self.tags {
+            let filter = if values.is_empty() { "false".to_string() } else { any_of(tag, values) };
+            q.push_str(&format!("    |> filter(fn: (r) => {})\n", filter));
+        }
+        if let Some(value) = &self.value {
+            q.push_str(&format!("    |> filter(fn: (r) => r._value == {})\n", flux_string(value)));
+        }
+        if let Some(regex) = &self.value_regex {
+            q.push_str(&format!("    |> filter(fn: (r) => r._value =~ {})\n", flux_regex(regex)));
+        }
+        if !self.keep.is_empty() {
+            q.push_str(&format!("    |> keep(columns: [{}])\n", string_list(&self.keep)));
+        }
+        if !self.drop.is_empty() {
+            q.push_str(&format!("    |> drop(columns: [{}])\n", string_list(&self.drop)));
+        }
+        if let Some((every, aggregate)) = &self.window {
+            q.push_str(&format!("    |> aggregateWindow(every: {}, fn: {}, createEmpty: false)\n", every, aggregate.as_flux()));
+        }
+        if self.last {
+            q.push_str("    |> last()\n");
+        }
+        if self.sort {
+            q.push_str("    |> sort(columns: [\"_time\"])\n");
+        }
+        if let Some((n, offset)) = self.limit {
+            q.push_str(&format!("    |> limit(n: {}, offset: {})\n", n, offset));
+        }
+        if self.count {
+            q.push_str("    |> count()\n");
+        }
+        if self.pivot {
+            q.push_str("    |> pivot(rowKey: [\"_time\"], columnKey: [\"_field\"], valueColumn: \"_value\")\n");
+        }
+        match &self.yield_name {
+            Some(name) => q.push_str(&format!("    |> yield(name: {})", flux_string(name))),
+            None => q.push_str("    |> yield()"),
+        }
+        q
+    }
+}
+
+// quotes and escapes a flux string literal
+fn flux_string(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace("${", "\\${");
+    format!("\"{}\"", escaped)
+}
+
+// flux regular expression literal, a / would end it early
+fn flux_regex(regex: &str) -> String {
+    format!("/{}/", regex.replace('/', "\\/"))
+}
+
+fn string_list(items: &[String]) -> String {
+    items.iter().map(|i| flux_string(i)).collect::<Vec<_>>().join(", ")
+}
+
+fn any_of(column: &str, values: &[String]) -> String {
+    values
+        .iter()
+        .map(|v| format!("r[{}] == {}", flux_string(column), flux_string(v)))
+        .collect::<Vec<_>>()
+        .join(" or ")
+}
+
+// One row of a flux result, values are looked up by column name
+#[derive(Debug, Clone, Default)]
+pub struct FluxRecord {
+    values: HashMap<String, String>,
+}
+
+impl FluxRecord {
+    // raw value of a column, None if the column is missing or empty
+    pub fn get(&self, column: &str) -> Option<&str> {
+        self.values.get(column).map(|v| v.as_str()).filter(|v| !v.is_empty())
+    }
+
+    pub fn f64(&self, column: &str) -> Option<f64> {
+        self.get(column)?.parse().ok()
+    }
+
+    pub fn time_of(&self, column: &str) -> Option<DateTime<Utc>> {
+        self.get(column)?.parse().ok()
+    }
+
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        self.time_of("_time")
+    }
+
+    pub fn value(&self) -> Option<&str> {
+        self.get("_value")
+    }
+
+    pub fn field(&self) -> Option<&str> {
+        self.get("_field")
+    }
+
+    pub fn measurement(&self) -> Option<&str> {
+        self.get("_measurement")
+    }
+}
+
+// the record whose _time is closest to the given time
+pub fn nearest_record(records: &[FluxRecord], time: DateTime<Utc>) -> Option<&FluxRecord> {
+    records
+        .iter()
+        .filter_map(|r| Some((r, (r.time()? - time).abs())))
+        .min_by_key(|(_, distance)| *distance)
+        .map(|(r, _)| r)
+}
+
+// Parses an (optionally annotated) flux CSV response.
+// Tables are separated by blank lines and each one starts with its own header row,
+// annotation rows (#datatype, #group, #default) are skipped.
+pub fn parse_csv(text: &str) -> Result<Vec<FluxRecord>, InfluxError> {
+    let mut records = Vec::new();
+    for table in text.split("\n\n").flat_map(|t| t.split("\r\n\r\n")) {
+        if table.trim().is_empty() {
+            continue;
+        }
+        let mut reader = ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(table.as_bytes());
+        let mut header: Option<Vec<String>> = None;
+        for row in reader.records() {
+            let row = row.map_err(InfluxError::Csv)?;
+            if row.get(0).is_some_and(|c| c.starts_with('#')) {
+                continue;
+            }
+            let Some(columns) = &header else {
+                header = Some(row.iter().map(|c| c.to_string()).collect());
+                continue;
+            };
+            let values: HashMap<String, String> = columns
+                .iter()
+                .zip(row.iter())
+                .filter(|(name, _)| !name.is_empty())
+                .map(|(name, value)| (name.clone(), value.to_string()))
+                .collect();
+            // errors that happen while streaming come back as an "error,reference" table
+            if columns.iter().any(|c| c == "error") && columns.iter().any(|c| c == "reference") {
+                return Err(InfluxError::Query(values.get("error").cloned().unwrap_or_default()));
+            }
+            records.push(FluxRecord { values });
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn flux_string_escapes_quotes_backslashes_and_interpolation() {
+        assert_eq!(flux_string("cam1"), r#""cam1""#);
+        assert_eq!(flux_string(r#"a"b\c"#), r#""a\"b\\c""#);
+        assert_eq!(flux_string("${token}"), r#""\${token}""#);
+        // an escaped backslash in front of a quote must not end up escaping the quote
+        assert_eq!(flux_string(r#"\""#), r#""\\\"""#);
+    }
+
+    #[test]
+    fn tag_values_cannot_leave_the_string_literal() {
+        let query = FluxQuery::new("asv_data").tag("camera", r#"cam1") or r["camera"] != ("x"#).build();
+        assert!(query.contains(r#"r["camera"] == "cam1\") or r[\"camera\"] != (\"x")"#), "{}", query);
+        let query = FluxQuery::new("asv_data").field(r#"x" or true or "y"#).value_eq("${v}").build();
+        assert!(query.contains(r#"r["_field"] == "x\" or true or \"y")"#), "{}", query);
+        assert!(query.contains(r#"r._value == "\${v}")"#), "{}", query);
+    }
+
+    #[test]
+    fn tags_match_any_of_the_values_and_nothing_without_values() {
+        let query = FluxQuery::new("asv_data").tags("camera", &["cam1", "cam2"]).build();
+        assert!(query.contains(r#"filter(fn: (r) => r["camera"] == "cam1" or r["camera"] == "cam2")"#), "{}", query);
+        let query = FluxQuery::new("asv_data").tags::<&str>("camera", &[]).build();
+        assert!(query.contains("filter(fn: (r) => false)"), "{}", query);
+    }
+
+    #[test]
+    fn regex_literals_escape_slashes() {
+        let query = FluxQuery::new("b").value_matches("^/files/").build();
+        assert!(query.contains(r"r._value =~ /^\/files\//)"), "{}", query);
+    }
+
+    #[test]
+    fn build_emits_the_stages_in_order() {
+        let start = Utc.with_ymd_and_hms(2025, 6, 1, 0, 0, 0).unwrap();
+        let stop = Utc.with_ymd_and_hms(2025, 6, 2, 12, 30, 0).unwrap();
+        let query = FluxQuery::new("asv_data")
+            .yield_as("mean")
+            .pivot()
+            .aggregate_window(Duration::from_static("1m"), Aggregate::Mean)
+            .keep(&["_time", "_value", "_field"])
+            .tag("camera", "cam1")
+            .fields(&["temperature", "salinity"])
+            .measurement("idronaut_data")
+            .range(start, stop)
+            .build();
+        assert_eq!(
+            query,
+            "from(bucket: \"asv_data\")\n\
+             \x20   |> range(start: 2025-06-01T00:00:00Z, stop: 2025-06-02T12:30:00Z)\n\
+             \x20   |> filter(fn: (r) => r[\"_measurement\"] == \"idronaut_data\")\n\
+             \x20   |> filter(fn: (r) => r[\"_field\"] == \"temperature\" or r[\"_field\"] == \"salinity\")\n\
+             \x20   |> filter(fn: (r) => r[\"camera\"] == \"cam1\")\n\
+             \x20   |> keep(columns: [\"_time\", \"_value\", \"_field\"])\n\
+             \x20   |> aggregateWindow(every: 1m, fn: mean, createEmpty: false)\n\
+             \x20   |> pivot(rowKey: [\"_time\"], columnKey: [\"_field\"], valueColumn: \"_value\")\n\
+             \x20   |> yield(name: \"mean\")"
+        );
+    }
+
+    #[test]
+    fn build_defaults_to_the_last_hour() {
+        let query = FluxQuery::new("asv_data").last().build();
+        assert_eq!(query, "from(bucket: \"asv_data\")\n    |> range(start: -1h)\n    |> last()\n    |> yield()");
+        let query = FluxQuery::new("asv_data").range_since(Duration::from_static("-24h")).sort_by_time().limit(24, 48).build();
+        assert_eq!(
+            query,
+            "from(bucket: \"asv_data\")\n    |> range(start: -24h)\n    |> sort(columns: [\"_time\"])\n    |> limit(n: 24, offset: 48)\n    |> yield()"
+        );
+        assert!(FluxQuery::new("asv_data").count().build().ends_with("    |> count()\n    |> yield()"));
+    }
+
+    #[test]
+    fn parse_csv_reads_every_table_by_column_name() {
+        let text = "\
+#datatype,string,long,dateTime:RFC3339,double,string,string
+#group,false,false,false,false,true,true
+#default,_result,,,,,
+,result,table,_time,_value,_field,_measurement
+,,0,2025-06-01T00:00:00Z,21.5,temperature,idronaut_data
+,,0,2025-06-01T00:01:00Z,21.6,temperature,idronaut_data
+
+#datatype,string,long,string,dateTime:RFC3339,string
+#group,false,false,true,false,false
+#default,_result,,,,
+,result,table,camera,_time,_value
+,,1,cam1,2025-06-01T00:00:05Z,/files/SET0001/000/IMG_0001_1.tif
+";
+        let records = parse_csv(text).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].f64("_value"), Some(21.5));
+        assert_eq!(records[1].field(), Some("temperature"));
+        assert_eq!(records[1].measurement(), Some("idronaut_data"));
+        assert_eq!(records[2].get("camera"), Some("cam1"));
+        assert_eq!(records[2].value(), Some("/files/SET0001/000/IMG_0001_1.tif"));
+        assert_eq!(records[2].time(), Some(Utc.with_ymd_and_hms(2025, 6, 1, 0, 0, 5).unwrap()));
+        // the unnamed first column and empty values are not values
+        assert_eq!(records[0].get(""), None);
+        assert_eq!(records[0].get("result"), None);
+    }
+
+    #[test]
+    fn parse_csv_accepts_crlf() {
+        let text = ",result,table,_time,_value\r\n,_result,0,2025-06-01T00:00:00Z,1.5\r\n\r\n\
+                    ,result,table,_time,_value,camera\r\n,_result,1,2025-06-01T00:00:01Z,2.5,cam2\r\n\r\n";
+        let records = parse_csv(text).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].f64("_value"), Some(1.5));
+        assert_eq!(records[1].get("camera"), Some("cam2"));
+        assert_eq!(records[1].time(), Some(Utc.with_ymd_and_hms(2025, 6, 1, 0, 0, 1).unwrap()));
+    }
+
+    #[test]
+    fn parse_csv_reports_error_tables() {
+        let text = "\
+,result,table,_time,_value
+,_result,0,2025-06-01T00:00:00Z,1.5
+
+#datatype,string,string
+#group,true,true
+#default,,
+,error,reference
+,\"runtime error: unsupported aggregate column type string\",897
+";
+        match parse_csv(text) {
+            Err(InfluxError::Query(message)) => assert_eq!(message, "runtime error: unsupported aggregate column type string"),
+            other => panic!("expected a query error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_csv_of_an_empty_result() {
+        assert!(parse_csv("").unwrap().is_empty());
+        assert!(parse_csv("\r\n\r\n").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,90 @@
+use chrono::{SecondsFormat, Utc};
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use brig_types::{RTDataPoint, RTValue, LIVE_EVENT};
+use std::convert::Infallible;
+use tokio_stream::{wrappers::WatchStream, Stream, StreamExt};
+use tracing::info;
+
+use crate::influx::{Duration, InfluxError};
+use crate::{AppState, SharedState};
+
+/*
+Real time data.
+A single background task polls Influx for the latest CTD and GPS values and publishes them on a watch channel,
+every client connected to /api/live receives the updates as server-sent events.
+*/
+
+// polls Influx forever, publishing every result (None when Influx could not be queried)
+pub async fn poll_latest(state: SharedState) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_millis(state.config.live.poll_interval_ms));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    loop {
+        interval.tick().await;
+        let latest = match fetch_latest(&state).await {
+            Ok(latest) => latest,
+            Err(e) => {
+                info!("Live data poll failed: {}", e);
+                None
+            }
+        };
+        state.live.send_replace(latest);
+    }
+}
+
+// queries the last value of every CTD and GPS field within the lookback window
+async fn fetch_latest(state: &AppState) -> Result<Option<RTDataPoint>, InfluxError> {
+    let live = &state.config.live;
+    let lookback = Duration::parse(&format!("-{}s", live.lookback_secs)).unwrap_or(Duration::from_static("-10m"));
+    let query = state.influx.flux()
+        .range_since(lookback)
+        .measurements(&["idronaut_data", "gps_data2"])
+        .last()
+        .build();
+    
+    let records = state.influx.query(&query).await?;
+    let now = Utc::now();
+    let mut rtd = RTDataPoint::default();
+    for record in &records {
+        let (Some(field), Some(value), Some(time), Some(measurement)) =
+            (record.field(), record.f64("_value"), record.time(), record.measurement()) else {
+            continue;
+        };
+        let slot = match field {
+            "ph" => &mut rtd.ph,
+            "temperature" => &mut rtd.temperature,
+            "pressure" => &mut rtd.pressure,
+            "salinity" => &mut rtd.salinity,
+            "conductivity" => &mut rtd.conductivity,
+            "oxygen_percentage" => &mut rtd.oxygen_perc,
+            "oxygen_ppm" => &mut rtd.oxygen_ppm,
+            "cog" => &mut rtd.cog,
+            "sog" => &mut rtd.sog,
+            "latitude" => &mut rtd.latitude,
+            "longitude" => &mut rtd.longitude,
+            "depth" => &mut rtd.depth,
+            _ => continue,
+        };
+        let age_ms = (now - time).num_milliseconds().max(0);
+        *slot = Some(RTValue {
+            value,
+            time: time.to_rfc3339_opts(SecondsFormat::Millis, true),
+            age_ms,
+            stale: age_ms > live.stale_after_ms as i64,
+            measurement: measurement.to_string(),
+        });
+    }
+    Ok(Some(rtd))
+}
+
+// server-sent events stream of LIVE_EVENT events, the data is an RTDataPoint or null when Influx is unavailable.
+// The current value is sent right away, then every new poll result.
+pub async fn live_stream(State(state): State<SharedState>) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let stream = WatchStream::new(state.live.subscribe()).map(|latest| {
+        let event = Event::default().event(LIVE_EVENT);
+        Ok(event.json_data(&latest).unwrap_or_else(|_| Event::default().event(LIVE_EVENT).data("null")))
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
@@ -1,183 +1,213 @@
 //use axum::body::Bytes;
 use axum::extract::Query;
-use axum::http::Method;
+use axum::http::{header, HeaderValue, Method};
 use reqwest::Client;
-use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
+use brig_types::{endpoints, CsvDataParams, DataParams, DataPoint, ImageDataParams, ImageDataPoint, RTDataPoint, ServiceStatus};
 use std::process::Command;
-use tracing::info;
 use axum::{
     Json,
-    extract::Path,
-    http::StatusCode,
-    response::IntoResponse,
+    extract::{Path, State},
+    middleware,
     routing::{get, post},
     Router,
 };
-use csv::ReaderBuilder;
-use std::io::Cursor;
-use chrono::{DateTime, Utc};
-use tower_http::cors::{CorsLayer, Any};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, SecondsFormat, TimeDelta, Utc};
+use tower_http::cors::CorsLayer;
 use tokio::task;
-mod util;
+use std::path::PathBuf;
+use std::sync::Arc;
+use clap::{Parser, Subcommand};
+use config::Config;
+use error::AppError;
+use influx::{nearest_record, Aggregate, Duration, InfluxClient};
+use tokio::sync::watch;
+use tracing::{info, warn};
+mod archive;
+mod audit;
+mod auth;
 mod camera;
-
-#[derive(Debug, Serialize, Deserialize)]
-struct DataPoint {
-    time: String,
-    field: String,
-    value: f64,
-    epochtime: i64, 
+mod config;
+mod error;
+mod export;
+mod geotags;
+mod imaging;
+mod indices;
+mod influx;
+mod live;
+mod offload;
+mod reflectance;
+mod thumbnails;
+mod tiles;
+
+#[derive(Parser)]
+#[command(about = "Brigantine ASV web UI backend")]
+struct Args {
+    /// Path to the TOML configuration file
+    #[arg(long, env = "BRIG_CONFIG")]
+    config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Action>,
 }
 
-impl DataPoint {
-    fn from_raw(time: &str, value: f64, field: &str) -> Option<Self> {
-        if let Ok(dt) = time.parse::<DateTime<Utc>>() {
-            Some(Self {
-                time: time.to_string(),                // Keep original string
-                epochtime: dt.timestamp_millis(), // Convert to milliseconds since epoch
-                value,
-                field: field.to_string(),
-            })
-        } else {
-            None
-        }
-    }
+#[derive(Subcommand)]
+enum Action {
+    /// Read a password from stdin and print its hash for the users file
+    HashPassword,
+    /// Download the map tiles of a bounding box into tiles.file, to have a map at sea
+    SeedTiles {
+        /// west,south,east,north in degrees, e.g. 9.8,43.5,10.4,43.9
+        #[arg(long, required = true, value_delimiter = ',', allow_hyphen_values = true)]
+        bbox: Vec<f64>,
+        #[arg(long, default_value_t = 0)]
+        min_zoom: u8,
+        #[arg(long, default_value_t = 14)]
+        max_zoom: u8,
+        /// stop before downloading more tiles than this
+        #[arg(long, default_value_t = 20_000)]
+        max_tiles: u64,
+        /// download the tiles already in the file again
+        #[arg(long)]
+        refresh: bool,
+    },
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct RTDataPoint {
-    pub ph: Option<f64>,
-    pub conductivity: Option<f64>,
-    pub salinity: Option<f64>,
-    pub temperature: Option<f64>,
-    pub pressure: Option<f64>,
-    pub oxygen_perc: Option<f64>,
-    pub oxygen_ppm: Option<f64>,
-    pub cog: Option<f64>,
-    pub sog: Option<f64>,
-    pub latitude: Option<f64>,
-    pub longitude: Option<f64>,
-    pub depth: Option<f64>,
+// state shared by all handlers
+pub struct AppState {
+    pub config: Config,
+    pub client: Client,
+    pub influx: InfluxClient,
+    pub auth: auth::Auth,
+    pub audit: audit::AuditLog,
+    pub reformats: camera::PendingReformats,
+    pub offloads: offload::Offloads,
+    pub thumbnails: thumbnails::Thumbnails,
+    pub tiles: tiles::Tiles,
+    // latest real time data, None until the first successful poll or after a failed one
+    pub live: watch::Sender<Option<RTDataPoint>>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct ServiceStatus {
-    idronaut: bool,
-    camera_capture: bool,
+pub type SharedState = Arc<AppState>;
+
+// builds a DataPoint from the raw influx values, None if the timestamp cannot be parsed
+fn data_point(time: &str, value: f64, field: &str, measurement: &str) -> Option<DataPoint> {
+    if let Ok(dt) = time.parse::<DateTime<Utc>>() {
+        Some(DataPoint {
+            time: time.to_string(),                // Keep original string
+            epochtime: dt.timestamp_millis(), // Convert to milliseconds since epoch
+            value,
+            field: field.to_string(),
+            measurement: measurement.to_string(),
+        })
+    } else {
+        None
+    }
 }
 
-#[derive(Deserialize)]
-struct ImageDataParams {
-    camera: String,
-    date: String,
-    set: String,
-    folder: String,
-    img_num: String,
-}
-#[derive(Deserialize)]
-struct CsvDataParams {
-    start: String,
-    end: String,
+// fields that can be requested from /api/data, per measurement
+const DATA_FIELDS: &[(&str, &[&str])] = &[
+    ("idronaut_data", &["conductivity", "oxygen_percentage", "oxygen_ppm", "ph", "pressure", "temperature", "salinity"]),
+    ("gps_data2", &["latitude", "longitude", "cog", "sog", "depth"]),
+];
+
+// parses a comma separated query parameter, skipping empty entries
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',').map(|i| i.trim().to_string()).filter(|i| !i.is_empty()).collect()
 }
-#[derive(Debug, Serialize, Deserialize)]
-struct ImageDataPoint{
-    date: String,
-    lat: Option<f64>,
-    lon: Option<f64>,
-    cog: Option<f64>,
-    sog: Option<f64>,
-    conductivity: Option<f64>,
-    depth: Option<f64>,
-    oxygen_percentage: Option<f64>,
-    oxygen_ppm: Option<f64>,
-    ph: Option<f64>,
-    pressure: Option<f64>,
-    salinity: Option<f64>,
-    temperature: Option<f64>,
+
+// parses an RFC3339 timestamp or a duration relative to now
+pub fn parse_time_param(s: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, AppError> {
+    if let Some(offset) = Duration::parse(s) {
+        return TimeDelta::try_seconds(offset.seconds())
+            .and_then(|offset| now.checked_add_signed(offset))
+            .ok_or_else(|| AppError::BadRequest(format!("{} is too far from now", s)));
+    }
+    s.parse::<DateTime<Utc>>()
+        .map_err(|_| AppError::BadRequest(format!("\"{}\" is neither an RFC3339 time nor a duration like -24h", s)))
 }
 
-// queries influxdb for idronaut data
-async fn query_data() -> Result<Json<Vec<DataPoint>>, StatusCode> {
-    let host = "http://localhost:8086"; // InfluxDB v2 server
-    let org = "SailingLab";
-    let token="ijL6ry3VP0Hm5nAvP-wvHouC1l3ysIWty-VWCPgF7Bz-aKt-4Oi9zFMV_t8UkVnQSVwdxlRpdKjbAuPxx9umsA==";
-    let bucket = "asv_data";
-
-    let query = format!(
-        "from(bucket: \"{}\")
-        |> range(start: -24h)
-        |> filter(fn: (r) => r[\"_measurement\"] == \"idronaut_data\")
-        |> filter(fn: (r) => r[\"_field\"] == \"conductivity\" 
-        or r[\"_field\"] == \"oxygen_percentage\" 
-        or r[\"_field\"] == \"oxygen_ppm\" 
-        or r[\"_field\"] == \"ph\" 
-        or r[\"_field\"] == \"pressure\" 
-        or r[\"_field\"] == \"temperature\" 
-        or r[\"_field\"] == \"salinity\")
-        |> aggregateWindow(every: 1m, fn: last, createEmpty: false)
-        |> yield()",
-        bucket
-    );
+// queries influxdb for sensor data in the requested range, aggregated in fixed windows
+async fn query_data(State(state): State<SharedState>, Query(params): Query<DataParams>) -> Result<Json<Vec<DataPoint>>, AppError> {
+    let now = Utc::now();
+    let start = parse_time_param(params.start.as_deref().unwrap_or("-24h"), now)?;
+    let stop = match params.stop.as_deref() {
+        Some(stop) => parse_time_param(stop, now)?,
+        None => now,
+    };
+    if start >= stop {
+        return Err(AppError::BadRequest("start must be before stop".to_string()));
+    }
 
-    let client = Client::new();
-    let response = client
-        .post(format!("{}/api/v2/query?org={}", host, org))
-        .header("Authorization", format!("Token {}", token))
-        .header("Accept", "application/csv")
-        .header("Content-Type", "application/vnd.flux")
-        .body(query)
-        .send()
-        .await
-        .map_err(|e| {
-            info!("Request error: {:?}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-    
-    //println!("{:?}", responsee);
-    let response_text = response.text().await.map_err(|e| {
-        info!("Response text error: {:?}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-    println!("Raw CSV Response:\n{}", response_text);  // debug
-
-    let mut data_points = Vec::new();
-    let mut reader = ReaderBuilder::new()
-        .has_headers(true)
-        .from_reader(Cursor::new(response_text));
-
-        for result in reader.records() {
-            if let Ok(record) = result {
-                if let (Some(time), Some(value), Some(field)) = (record.get(5), record.get(6), record.get(7)) { // 5 -> timestamp, 6..11 -> sensors
-                    if let Ok(parsed_value) = value.parse::<f64>() {
-                        if let Some(data_point) = DataPoint::from_raw(time, parsed_value, field) {
-                            data_points.push(data_point);
-                        }
-                    }
-                }
-            }
-        }
-    println!("Sent data:\n{:?}", data_points);
+    let measurements = split_list(params.measurements.as_deref().unwrap_or("idronaut_data"));
+    let mut allowed_fields: Vec<&str> = Vec::new();
+    for measurement in &measurements {
+        let Some((_, fields)) = DATA_FIELDS.iter().find(|(m, _)| m == measurement) else {
+            return Err(AppError::BadRequest(format!("unknown measurement {}", measurement)));
+        };
+        allowed_fields.extend(fields.iter());
+    }
+    let fields = match params.fields.as_deref() {
+        Some(fields) => split_list(fields),
+        None => allowed_fields.iter().map(|f| f.to_string()).collect(),
+    };
+    if measurements.is_empty() || fields.is_empty() {
+        return Err(AppError::BadRequest("no measurement or field selected".to_string()));
+    }
+    if let Some(field) = fields.iter().find(|f| !allowed_fields.contains(&f.as_str())) {
+        return Err(AppError::BadRequest(format!("field {} is not part of the selected measurements", field)));
+    }
+
+    let window = params.window.as_deref().unwrap_or("1m");
+    let window = Duration::parse(window).ok_or_else(|| AppError::BadRequest(format!("invalid window {}", window)))?;
+    let aggregate = params.aggregate.as_deref().unwrap_or("last");
+    let aggregate = Aggregate::parse(aggregate).ok_or_else(|| AppError::BadRequest(format!("invalid aggregate function {}", aggregate)))?;
+    if window.seconds() < 1 {
+        return Err(AppError::BadRequest("window must be at least 1s".to_string()));
+    }
+
+    // at most one point per window per field
+    let max_points = state.config.limits.max_points;
+    let expected_points = ((stop - start).num_seconds() / window.seconds() + 1) * fields.len() as i64;
+    if expected_points > max_points {
+        return Err(AppError::BadRequest(format!(
+            "the request would return ~{} points (limit {}), use a shorter range or a larger window",
+            expected_points, max_points
+        )));
+    }
+
+    let query = state.influx.flux()
+        .range(start, stop)
+        .measurements(&measurements)
+        .fields(&fields)
+        .aggregate_window(window, aggregate)
+        .build();
+
+    let records = state.influx.query(&query).await?;
+    let data_points: Vec<DataPoint> = records
+        .iter()
+        .filter_map(|r| data_point(r.get("_time")?, r.f64("_value")?, r.field()?, r.measurement()?))
+        .take(max_points as usize)
+        .collect();
+    info!("Sent {} data points", data_points.len());
     Ok(Json(data_points))
 }
 
 // start/stop services
-async fn service_call(Path((service, action)): Path<(String, String)>) -> impl IntoResponse {
+async fn service_call(Path((service, action)): Path<(String, String)>) -> Result<String, AppError> {
     let valid_services = ["camera_capture", "IDRONAUT"];
     let valid_actions = ["start", "stop"];
     // sanitize input
     if !valid_services.contains(&service.as_str()) || !valid_actions.contains(&action.as_str()) {
-        return (StatusCode::BAD_REQUEST, "Invalid service or action").into_response();
+        return Err(AppError::BadRequest("Invalid service or action".to_string()));
     }
-    //let msg = format!("Service {} has been {}ed", service, action);
-    //println!("Service {} has been {}ed", service, action);
     match control_service(&service, &action).await {
-        Ok(msg) => 
-            {println!("Service {} has been {}ed", service, action);
-            (StatusCode::OK, msg).into_response()},
-        Err(err) =>
-            {println!("Service {} has could not be {}ed", service, action);
-            (StatusCode::INTERNAL_SERVER_ERROR, err).into_response()},
+        Ok(msg) => {
+            info!("systemctl {} {} succeeded", action, service);
+            Ok(msg)
+        }
+        Err(err) => {
+            warn!("systemctl {} {} failed: {}", action, service, err);
+            Err(AppError::Service(service, err))
+        }
     }
 }
 
@@ -230,9 +260,10 @@ async fn check_service_status(service: &str) -> Result<bool, String> {
     }
 }
 
-async fn status_call() -> Result<Json<ServiceStatus>, StatusCode> {
-    let idronaut_status = check_service_status("IDRONAUT").await.map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
-    let camera_capture_status = check_service_status("camera_capture").await.map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+async fn status_call() -> Result<Json<ServiceStatus>, AppError> {
+    let idronaut_status = check_service_status("IDRONAUT").await.map_err(|e| AppError::Service("IDRONAUT".to_string(), e))?;
+    let camera_capture_status = check_service_status("camera_capture").await
+        .map_err(|e| AppError::Service("camera_capture".to_string(), e))?;
     
     let status = ServiceStatus {
         idronaut: idronaut_status,
@@ -243,244 +274,110 @@ async fn status_call() -> Result<Json<ServiceStatus>, StatusCode> {
 
 }
 
-async fn image_data_call(Query(params): Query<ImageDataParams>) -> Result<Json<ImageDataPoint>, StatusCode> {
+async fn image_data_call(
+    State(state): State<SharedState>,
+    Path(camera): Path<String>,
+    Query(params): Query<ImageDataParams>,
+) -> Result<Json<ImageDataPoint>, AppError> {
+    camera::camera_config(&state, &camera)?;
     let file = format!("/files/{}/{}/IMG_{}_1.tif", params.set, params.folder, params.img_num);
-    let ts_query = format!(
-        r#"from(bucket: "asv_data")
-            |> range(start: {}T00:00:00Z, stop: {}T23:59:59Z)  // Replace with your date
-            |> filter(fn: (r) => r._measurement == "micasense_data")
-            |> filter(fn: (r) => r._field == "capture")
-            |> filter(fn: (r) => r.camera == "{}")
-            |> filter(fn: (r) => r._value == "{}")
-            |> keep(columns: ["_time"])"#,
-            params.date, params.date, params.camera, file
-    );
-    println!("Query 1:\n{}", ts_query);
-    
-    let ts_response: String = util::post_influx_query(ts_query).await?;
-    if ts_response.is_empty() {
-        return Err(StatusCode::NOT_FOUND);
-    }
-    let mut timestamp = String::new();
-    let mut reader = ReaderBuilder::new()
-        .has_headers(true)
-        .from_reader(Cursor::new(ts_response));
-        for result in reader.records() {
-            println!("Result:\n{:?}", result);
-            if let Ok(record) = result {
-                if let Some(time) = record.get(3) { // 5 -> timestamp, 6..11 -> sensors
-                    timestamp = time.to_string();
-                }
-            }
-        }
-
-    let idro_query = format!(
-        r#"import "experimental"
-from(bucket: "asv_data")
-            |> range(start: experimental.addDuration(d: -1s, to: {}), stop: experimental.addDuration(d: 1s, to: {})) 
-            |> filter(fn: (r) => r._measurement == "idronaut_data")
-            |> pivot(rowKey:["_time"], columnKey: ["_field"], valueColumn: "_value")
-            |> elapsed(unit: 1ns)
-            |> sort(columns: ["elapsed"], desc: false)
-            |> limit(n: 1)"#,
-            timestamp, timestamp
-    );
-
-    println!("Query 2:\n{}", idro_query);
-    let idro_response: String = util::post_influx_query(idro_query).await?;
-    println!("Raw CSV Response:\n{}", idro_response);
-    if idro_response.is_empty() {
-        return Err(StatusCode::NOT_FOUND);
-    }
-
-    let mut f_cond = None;
-    let mut f_oxperc = None;
-    let mut f_oxppm = None;
-    let mut f_ph = None;
-    let mut f_press = None;
-    let mut f_sal = None;
-    let mut f_temp = None;
-    reader = ReaderBuilder::new()
-        .has_headers(true)
-        .from_reader(Cursor::new(idro_response));
-        for result in reader.records() {
-            if let Ok(record) = result {
-                if let (Some(cond), Some(oxperc), Some(oxppm),
-                        Some(ph), Some(press), Some(sal), Some(temp)) = (record.get(7), record.get(8), record.get(9),
-                                                                                                 record.get(10), record.get(11), record.get(12), record.get(13)) { // 5 -> timestamp, 6..11 -> sensors
-                    if let Ok(parsed_cond) = cond.parse::<f64>(){
-                        f_cond = Some(parsed_cond);
-                    }
-                    if let Ok(parsed_oxperc) = oxperc.parse::<f64>(){
-                        f_oxperc = Some(parsed_oxperc);
-                    }
-                    if let Ok(parsed_oxppm) = oxppm.parse::<f64>(){
-                        f_oxppm = Some(parsed_oxppm);
-                    }
-                    if let Ok(parsed_ph) = ph.parse::<f64>(){
-                        f_ph = Some(parsed_ph);
-                    }
-                    if let Ok(parsed_press) = press.parse::<f64>(){
-                        f_press = Some(parsed_press);
-                    }
-                    if let Ok(parsed_sal) = sal.parse::<f64>(){
-                        f_sal = Some(parsed_sal);
-                    }
-                    if let Ok(parsed_temp) = temp.parse::<f64>(){
-                        f_temp = Some(parsed_temp);
-                    }
-                }
-            }
-        }
+    let day = parse_date(&params.date)?;
+    let day_start = day.and_time(NaiveTime::MIN).and_utc();
+    let ts_query = state.influx.flux()
+        .range(day_start, day_start + TimeDelta::days(1))
+        .measurement("micasense_data")
+        .field("capture")
+        .tag("camera", &camera)
+        .value_eq(&file)
+        .keep(&["_time"])
+        .build();
+
+    let captures = state.influx.query(&ts_query).await?;
+    let Some(capture_time) = captures.iter().find_map(|r| r.time()) else {
+        return Err(AppError::NoData(format!("no capture {} by {} on {}", file, camera, params.date)));
+    };
 
-    let gps_query = format!(
-            r#"import "experimental"
-    from(bucket: "asv_data")
-                |> range(start: experimental.addDuration(d: -1s, to: {}), stop: experimental.addDuration(d: 1s, to: {})) 
-                |> filter(fn: (r) => r._measurement == "gps_data2")
-                |> pivot(rowKey:["_time"], columnKey: ["_field"], valueColumn: "_value")
-                |> elapsed(unit: 1ns)
-                |> sort(columns: ["elapsed"], desc: false)
-                |> limit(n: 1)"#,
-                timestamp, timestamp
-    );
-    let gps_response: String = util::post_influx_query(gps_query).await?;
-    println!("Raw CSV Response:\n{}", gps_response);
-    if gps_response.is_empty() {
-        return Err(StatusCode::NOT_FOUND);
-    }
+    // CTD and GPS samples closest to the capture, within one second
+    let window_start = capture_time - TimeDelta::seconds(1);
+    let window_stop = capture_time + TimeDelta::seconds(1);
+    let idro_query = state.influx.flux()
+        .range(window_start, window_stop)
+        .measurement("idronaut_data")
+        .pivot()
+        .build();
+    let idro_records = state.influx.query(&idro_query).await?;
+    let Some(idro) = nearest_record(&idro_records, capture_time) else {
+        return Err(AppError::NoData("no CTD sample within 1s of the capture".to_string()));
+    };
 
-    let mut f_cog = None;
-    let mut f_depth = None;
-    let mut f_lat = None;
-    let mut f_lon = None;
-    let mut f_sog = None;
-    reader = ReaderBuilder::new()
-        .has_headers(true)
-        .from_reader(Cursor::new(gps_response));
-        for result in reader.records() {
-            if let Ok(record) = result {
-
-                if let (Some(cog), Some(depth), Some(lat),
-                        Some(lon), Some(sog)) = (record.get(7), record.get(8), record.get(9),
-                                                             record.get(11), record.get(13)) {
-                    if let Ok(parsed_cog) = cog.parse::<f64>(){
-                        f_cog = Some(parsed_cog);
-                    }
-                    if let Ok(parsed_depth) = depth.parse::<f64>(){
-                        f_depth = Some(parsed_depth);
-                    }
-                    if let Ok(parsed_lat) = lat.parse::<f64>(){
-                        f_lat = Some(parsed_lat);
-                    }
-                    if let Ok(parsed_lon) = lon.parse::<f64>(){
-                        f_lon = Some(parsed_lon);
-                    }
-                    if let Ok(parsed_sog) = sog.parse::<f64>(){
-                        f_sog = Some(parsed_sog);
-                    }
-                }
-            }
-        }
+    let gps_query = state.influx.flux()
+        .range(window_start, window_stop)
+        .measurement("gps_data2")
+        .pivot()
+        .build();
+    let gps_records = state.influx.query(&gps_query).await?;
+    let Some(gps) = nearest_record(&gps_records, capture_time) else {
+        return Err(AppError::NoData("no GPS sample within 1s of the capture".to_string()));
+    };
 
     let datapoints= ImageDataPoint{
-        date: timestamp,
-        lat: f_lat,
-        lon: f_lon,
-        cog: f_cog,
-        sog: f_sog,
-        conductivity: f_cond,
-        depth: f_depth,
-        oxygen_percentage: f_oxperc,
-        oxygen_ppm: f_oxppm,
-        ph: f_ph,
-        pressure: f_press,
-        salinity: f_sal,
-        temperature: f_temp,
+        date: capture_time.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        lat: gps.f64("latitude"),
+        lon: gps.f64("longitude"),
+        cog: gps.f64("cog"),
+        sog: gps.f64("sog"),
+        conductivity: idro.f64("conductivity"),
+        depth: gps.f64("depth"),
+        oxygen_percentage: idro.f64("oxygen_percentage"),
+        oxygen_ppm: idro.f64("oxygen_ppm"),
+        ph: idro.f64("ph"),
+        pressure: idro.f64("pressure"),
+        salinity: idro.f64("salinity"),
+        temperature: idro.f64("temperature"),
     };
     Ok(Json(datapoints))
 }
 
-async fn get_csv_data(Query(params): Query<CsvDataParams>) -> Result<String, StatusCode>{
-    let flux_query = format!(
-        r#"from(bucket: "asv_data")
-            |> range(start: {}:00Z, stop: {}:00Z)
-            |> filter(fn: (r) => r["_measurement"] == "gps_data2" or r["_measurement"] == "idronaut_data" or r["_measurement"] == "micasense_data")
-            |> drop(columns: ["_start", "_stop", "table", "result"])  
-            |> yield(name: "last")"#,
-        params.start, params.end
-    );
-
-    match util::post_influx_query(flux_query).await{
-        Ok(res) => return Ok(res),
-        Err(e) => return Err(e)
-    }
+// parses the datetime-local values sent by the frontend (YYYY-MM-DDTHH:MM), interpreted as UTC
+fn parse_minute(s: &str) -> Result<DateTime<Utc>, AppError> {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M")
+        .map(|t| t.and_utc())
+        .map_err(|_| AppError::BadRequest(format!("\"{}\" is not a YYYY-MM-DDTHH:MM time", s)))
 }
 
-async fn query_latest_data() -> Result<Json<RTDataPoint>, StatusCode>{
-    let ts_query = format!(
-        r#"from(bucket: "asv_data")
-            |> range(start: -10s) 
-            |> filter(fn: (r) => (r._measurement == "idronaut_data" or r._measurement == "gps_data2"))
-            |> last()"#
-    );
-    println!("Query RTD:\n{}", ts_query);
-    
-    let response: String = util::post_influx_query(ts_query).await?;
-    if response.is_empty() {
-        return Err(StatusCode::NOT_FOUND);
-    }
-    let mut reader = ReaderBuilder::new()
-        .has_headers(true)
-        .from_reader(Cursor::new(response));
-    let mut rtd = RTDataPoint{
-        ph: None,
-        conductivity: None,
-        salinity: None,
-        temperature: None,
-        pressure: None,
-        oxygen_perc: None,
-        oxygen_ppm: None,
-        cog: None,
-        sog: None,
-        latitude: None,
-        longitude: None,
-        depth: None,
-    };
-    for result in reader.records() {
-        if let Ok(record) = result {
-            if let (Some(value), Some(field)) = (record.get(6), record.get(7)) { // 5 -> timestamp, 6..11 -> sensors
-                if let Ok(parsed_value) = value.parse::<f64>() {
-                    if field == "ph"{
-                        rtd.ph = Some(parsed_value);
-                    } else if field == "temperature"{
-                        rtd.temperature = Some(parsed_value);
-                    } else if field == "pressure"{
-                        rtd.pressure = Some(parsed_value);
-                    } else if field == "salinity"{
-                        rtd.salinity = Some(parsed_value);
-                    } else if field == "conductivity"{
-                        rtd.conductivity = Some(parsed_value);
-                    } else if field == "oxygen_percentage"{
-                        rtd.oxygen_perc = Some(parsed_value);
-                    } else if field == "oxygen_ppm"{
-                        rtd.oxygen_ppm = Some(parsed_value);
-                    } else if field == "cog"{
-                        rtd.cog = Some(parsed_value);
-                    }  else if field == "sog"{
-                        rtd.sog = Some(parsed_value);
-                    }  else if field == "latitude"{
-                        rtd.latitude = Some(parsed_value);
-                    }  else if field == "longitude"{
-                        rtd.longitude = Some(parsed_value);
-                    }  else if field == "depth"{
-                        rtd.depth = Some(parsed_value);
-                    }
-                }
-            }
-        }
+// parses a YYYY-MM-DD date parameter
+pub fn parse_date(s: &str) -> Result<NaiveDate, AppError> {
+    s.parse::<NaiveDate>()
+        .map_err(|_| AppError::BadRequest(format!("\"{}\" is not a YYYY-MM-DD date", s)))
+}
+
+async fn get_csv_data(State(state): State<SharedState>, Query(params): Query<CsvDataParams>) -> Result<String, AppError>{
+    let flux_query = state.influx.flux()
+        .range(parse_minute(&params.start)?, parse_minute(&params.end)?)
+        .measurements(&["gps_data2", "idronaut_data", "micasense_data"])
+        .drop(&["_start", "_stop", "table", "result"])
+        .yield_as("last")
+        .build();
+
+    Ok(state.influx.query_raw(&flux_query).await?)
+}
+
+// latest real time values, as last polled by the live module
+async fn query_latest_data(State(state): State<SharedState>) -> Result<Json<RTDataPoint>, AppError>{
+    match state.live.borrow().clone() {
+        Some(rtd) => Ok(Json(rtd)),
+        None => Err(AppError::Unavailable("no real time data yet, the database has not been polled successfully".to_string())),
     }
-    Ok(Json(rtd))
+}
+
+// cross-origin access is limited to the configured origins, with cookies so that sessions work
+fn cors_layer(allowed_origins: &[String]) -> CorsLayer {
+    let origins: Vec<HeaderValue> = allowed_origins.iter().filter_map(|o| o.parse().ok()).collect();
+    CorsLayer::new()
+        .allow_origin(origins)
+        .allow_credentials(true)
+        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
+        .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION])
 }
 
 #[tokio::main]
@@ -488,29 +385,127 @@ async fn main() {
     // initialize logging.
     tracing_subscriber::fmt::init();
 
+    let args = Args::parse();
+    if let Some(Action::HashPassword) = args.command {
+        let mut password = String::new();
+        if let Err(e) = std::io::stdin().read_line(&mut password) {
+            eprintln!("could not read password: {}", e);
+            std::process::exit(1);
+        }
+        println!("{}", auth::hash_password(password.trim_end_matches(['\r', '\n'])));
+        return;
+    }
+    if let Some(Action::SeedTiles { bbox, min_zoom, max_zoom, max_tiles, refresh }) = args.command {
+        let Ok(bbox) = <[f64; 4]>::try_from(bbox) else {
+            eprintln!("--bbox takes west,south,east,north");
+            std::process::exit(2);
+        };
+        let seeded = match Config::load(args.config.as_deref()) {
+            Ok(config) => tiles::seed(&config.tiles, bbox, min_zoom, max_zoom, max_tiles, refresh).await,
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = seeded {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let (config, auth) = match Config::load(args.config.as_deref()).and_then(|c| auth::Auth::load(&c.auth).map(|a| (c, a))) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let addr = config.server.bind;
+    let cors = cors_layer(&config.server.allowed_origins);
+    let client = Client::new();
+    let state: SharedState = Arc::new(AppState {
+        influx: InfluxClient::new(client.clone(), &config.influx),
+        audit: audit::AuditLog::new(config.reformat.audit_file.clone()),
+        thumbnails: thumbnails::Thumbnails::new(config.thumbnails.clone()),
+        config,
+        client,
+        auth,
+        reformats: camera::PendingReformats::default(),
+        offloads: offload::Offloads::default(),
+        tiles: tiles::Tiles::default(),
+        live: watch::Sender::new(None),
+    });
+    tokio::spawn(live::poll_latest(state.clone()));
+    tokio::spawn(thumbnails::pregenerate(state.clone()));
+
+    // reading data, open to everyone if auth.anonymous_read is set
+    let viewer_routes = Router::new()
+        .route(endpoints::DATA, get(query_data))
+        .route(endpoints::LATEST_DATA, get(query_latest_data))
+        .route(endpoints::LIVE, get(live::live_stream))
+        .route(endpoints::STATUS, get(status_call))
+        .route(endpoints::CAMERAS, get(camera::cameras_call))
+        .route(endpoints::CAMERA_STATUS, get(camera::camera_status_call))
+        .route(endpoints::CAMERA_FOLDERS, get(camera::camera_folders_call))
+        .route(endpoints::CAMERA_HEALTH, get(camera::camera_health))
+        .route(endpoints::IMAGE_DATA, get(image_data_call))
+        .route(endpoints::LAST_CAPTURE, get(camera::get_last_capture))
+        .route(endpoints::LATEST_CAPTURE, get(camera::latest_capture))
+        .route(endpoints::CAMERA_CAPTURES, get(camera::camera_captures))
+        .route(endpoints::CAPTURE_COMPOSITE, get(imaging::capture_composite))
+        .route(endpoints::CAPTURE_INDEX, get(indices::capture_index))
+        .route(endpoints::CAPTURE_INDEX_IMAGE, get(indices::capture_index_image))
+        .route(endpoints::CAPTURE_THUMBNAIL, get(thumbnails::capture_thumbnail))
+        .route(endpoints::CAPTURE_CALIBRATION, get(reflectance::capture_calibration))
+        .route(endpoints::DOWNLOAD_DATA, get(get_csv_data))
+        .route(endpoints::CAPTURE_METADATA, get(export::capture_metadata))
+        .route(endpoints::GEOTAGS, get(geotags::geotag_export))
+        .route(endpoints::TILES, get(tiles::tile_info))
+        .route(endpoints::TILE, get(tiles::tile))
+        .route(endpoints::OFFLOAD, get(offload::offload_status))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::require_viewer));
+
+    // anything that changes the state of the boat
+    let operator_routes = Router::new()
+        .route(endpoints::REFORMAT_PREPARE, post(camera::reformat_prepare))
+        .route(endpoints::REFORMAT_CONFIRM, post(camera::reformat_confirm))
+        .route(endpoints::OFFLOAD_START, post(offload::offload_start))
+        .route(endpoints::OFFLOAD_CANCEL, post(offload::offload_cancel))
+        .route(endpoints::GEOTAGS_WRITE, post(geotags::geotag_write))
+        .route(endpoints::SERVICE_ACTION, post(service_call))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::require_operator));
+
     let app = Router::new()
-        .route("/api/data", get(query_data))
-        .route("/api/latest_data", get(query_latest_data))
-        .route("/api/status", get(status_call))
-        .route("/api/camera_status", get(camera::camera_status_call))
-        .route("/api/camera_folders", get(camera::camera_folders_call))
-        .route("/api/image_data", get(image_data_call))
-        .route("/api/reformat", get(camera::format_sd))
-        .route("/api/:service/:action", post(service_call))
-        .route("/api/get_last_capture", get(camera::get_last_capture))
-        .route("/api/download_data", get(get_csv_data))
-        .layer(
-            CorsLayer::new()
-                .allow_origin(Any)
-                .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
-                .allow_headers(Any)
-        );
-
-    let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
+        .route(endpoints::LOGIN, post(auth::login))
+        .route(endpoints::LOGOUT, post(auth::logout))
+        .route(endpoints::SESSION, get(auth::session))
+        .merge(viewer_routes)
+        .merge(operator_routes)
+        .with_state(state)
+        .layer(cors);
+
     println!("Server running on {}", addr);
 
     axum_server::bind(addr)
         .serve(app.into_make_service())
         .await
         .unwrap();
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_time_param_accepts_times_and_durations() {
+        let now = "2025-06-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(parse_time_param("-1h30m", now).unwrap(), "2025-06-01T10:30:00Z".parse::<DateTime<Utc>>().unwrap());
+        assert_eq!(parse_time_param("2025-05-01T00:00:00Z", now).unwrap(), "2025-05-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap());
+        assert!(matches!(parse_time_param("yesterday", now), Err(AppError::BadRequest(_))));
+    }
+
+    #[test]
+    fn parse_time_param_rejects_durations_out_of_range() {
+        let now = Utc::now();
+        for s in ["-999999999999999999s", "-1000000y", "999999999999999999999999w"] {
+            assert!(matches!(parse_time_param(s, now), Err(AppError::BadRequest(_))), "{}", s);
+        }
+    }
+}
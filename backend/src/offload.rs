@@ -0,0 +1,233 @@
+use axum::{extract::{Path, State}, Extension, Json};
+use brig_types::{OffloadRequest, OffloadState, OffloadStatus};
+use chrono::{SecondsFormat, Utc};
+use ring::digest::{Context, SHA256};
+use std::collections::BTreeMap;
+use std::path::Path as FsPath;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::task;
+use tracing::info;
+
+use crate::archive;
+use crate::auth::Session;
+use crate::camera::{camera_config, capture_paths};
+use crate::error::AppError;
+use crate::{parse_time_param, AppState, SharedState};
+
+/*
+Offload of RedEdge captures to the archive on the Pi (see archive.rs).
+An operator starts a job for a camera and a time range, the captures are the micasense_data paths logged in that
+range and every one of their band files is downloaded from the camera, verified and renamed into the archive.
+Files whose archived copy still matches its checksum are skipped, others are downloaded again, so a cancelled or
+failed job is resumed by starting it again. At most one job runs per camera; the last job of every camera stays listed until the backend restarts.
+*/
+
+// failures listed in OffloadStatus.errors, the count covers the rest
+const MAX_ERRORS: usize = 20;
+
+struct Job {
+    status: Mutex<OffloadStatus>,
+    cancel: AtomicBool,
+}
+
+impl Job {
+    fn status(&self) -> OffloadStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut OffloadStatus)) {
+        f(&mut self.status.lock().unwrap());
+    }
+}
+
+// last job of every camera
+#[derive(Default)]
+pub struct Offloads(Mutex<BTreeMap<String, Arc<Job>>>);
+
+impl Offloads {
+    fn statuses(&self) -> Vec<OffloadStatus> {
+        self.0.lock().unwrap().values().map(|job| job.status()).collect()
+    }
+
+    fn get(&self, camera: &str) -> Option<Arc<Job>> {
+        self.0.lock().unwrap().get(camera).cloned()
+    }
+
+    // registers a new job, unless the camera already has a running one
+    fn begin(&self, status: OffloadStatus) -> Result<Arc<Job>, AppError> {
+        let mut jobs = self.0.lock().unwrap();
+        if let Some(job) = jobs.get(&status.camera) {
+            if job.status().state == OffloadState::Running {
+                return Err(AppError::Busy(format!("an offload of {} is already running", status.camera)));
+            }
+        }
+        let job = Arc::new(Job { status: Mutex::new(status.clone()), cancel: AtomicBool::new(false) });
+        jobs.insert(status.camera, job.clone());
+        Ok(job)
+    }
+}
+
+// progress of the last offload of every camera
+pub async fn offload_status(State(state): State<SharedState>) -> Json<Vec<OffloadStatus>> {
+    Json(state.offloads.statuses())
+}
+
+// resolves the captures of the requested range and starts copying them in the background
+pub async fn offload_start(
+    State(state): State<SharedState>,
+    Extension(session): Extension<Session>,
+    Path(camera): Path<String>,
+    Json(request): Json<OffloadRequest>,
+) -> Result<Json<OffloadStatus>, AppError> {
+    let config = camera_config(&state, &camera)?;
+    let (base_url, bands) = (config.url.clone(), config.bands.len() as u8);
+    let now = Utc::now();
+    let start = parse_time_param(&request.start, now)?;
+    let stop = parse_time_param(&request.stop, now)?;
+    if start >= stop {
+        return Err(AppError::BadRequest("start must be before stop".to_string()));
+    }
+    let captures = capture_paths(&state, &camera, start, stop).await?;
+    if captures.is_empty() {
+        return Err(AppError::NoData(format!("no captures by {} in that range", camera)));
+    }
+    let job = state.offloads.begin(OffloadStatus {
+        camera: camera.clone(),
+        state: OffloadState::Running,
+        user: session.username.clone(),
+        start: start.to_rfc3339_opts(SecondsFormat::Secs, true),
+        stop: stop.to_rfc3339_opts(SecondsFormat::Secs, true),
+        started: now.to_rfc3339_opts(SecondsFormat::Secs, true),
+        captures_total: captures.len() as u64,
+        captures_done: 0,
+        files_downloaded: 0,
+        files_skipped: 0,
+        files_failed: 0,
+        bytes_downloaded: 0,
+        errors: Vec::new(),
+    })?;
+    info!("Offload of {} captures of {} started by {}", captures.len(), camera, session.username);
+    tokio::spawn(run(state.clone(), base_url, bands, captures, job.clone()));
+    Ok(Json(job.status()))
+}
+
+// stops the running job of a camera after the capture it is copying
+pub async fn offload_cancel(
+    State(state): State<SharedState>,
+    Extension(session): Extension<Session>,
+    Path(camera): Path<String>,
+) -> Result<Json<OffloadStatus>, AppError> {
+    match state.offloads.get(&camera) {
+        Some(job) if job.status().state == OffloadState::Running => {
+            info!("Offload of {} cancelled by {}", camera, session.username);
+            job.cancel.store(true, Ordering::Relaxed);
+            Ok(Json(job.status()))
+        }
+        _ => Err(AppError::NoData(format!("no offload of {} is running", camera))),
+    }
+}
+
+async fn run(state: SharedState, base_url: String, bands: u8, captures: Vec<String>, job: Arc<Job>) {
+    let camera = job.status().camera;
+    for capture in &captures {
+        if job.cancel.load(Ordering::Relaxed) {
+            job.update(|s| s.state = OffloadState::Cancelled);
+            return;
+        }
+        for band in 1..=bands {
+            let file = archive::band_file(capture, band);
+            let outcome = match archive::archive_path(&state.config.archive.dir, &camera, &file) {
+                Some(dest) => offload_file(&state, &format!("{}{}", base_url, file), &dest).await,
+                None => Err("the path would leave the archive".to_string()),
+            };
+            job.update(|s| match outcome {
+                Ok(Copied::Skipped) => s.files_skipped += 1,
+                Ok(Copied::Downloaded(bytes)) => {
+                    s.files_downloaded += 1;
+                    s.bytes_downloaded += bytes;
+                }
+                Err(e) => {
+                    s.files_failed += 1;
+                    if s.errors.len() < MAX_ERRORS {
+                        s.errors.push(format!("{}: {}", file, e));
+                    }
+                }
+            });
+        }
+        job.update(|s| s.captures_done += 1);
+    }
+    job.update(|s| s.state = if s.files_failed == 0 { OffloadState::Done } else { OffloadState::Failed });
+    let status = job.status();
+    info!(
+        "Offload of {} finished: {} downloaded, {} already archived, {} failed",
+        camera, status.files_downloaded, status.files_skipped, status.files_failed
+    );
+}
+
+enum Copied {
+    // a verified copy was already in the archive
+    Skipped,
+    // bytes downloaded
+    Downloaded(u64),
+}
+
+// copies a band file unless a verified copy is already archived, retrying up to archive.attempts times
+async fn offload_file(state: &AppState, url: &str, dest: &FsPath) -> Result<Copied, String> {
+    let path = dest.to_path_buf();
+    if task::spawn_blocking(move || archive::verify(&path)).await.unwrap_or(false) {
+        return Ok(Copied::Skipped);
+    }
+    let mut error = String::new();
+    for attempt in 1..=state.config.archive.attempts {
+        match download(state, url, dest).await {
+            Ok(bytes) => return Ok(Copied::Downloaded(bytes)),
+            Err(e) => {
+                info!("Download of {} failed (attempt {}): {}", url, attempt, e);
+                error = e;
+            }
+        }
+    }
+    let _ = tokio::fs::remove_file(archive::part_path(dest)).await;
+    Err(error)
+}
+
+// downloads to the .part file while hashing, checks the size against the Content-Length of the camera,
+// then stores the checksum and renames the file into place. Without a Content-Length a short read
+// could not be told from a complete file, so such a download fails
+async fn download(state: &AppState, url: &str, dest: &FsPath) -> Result<u64, String> {
+    if let Some(dir) = dest.parent() {
+        tokio::fs::create_dir_all(dir).await.map_err(|e| format!("could not create {}: {}", dir.display(), e))?;
+    }
+    let part = archive::part_path(dest);
+    let timeout = Duration::from_secs(state.config.archive.download_timeout_secs);
+    let mut response = state.client.get(url).timeout(timeout).send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("the camera answered {}", response.status()));
+    }
+    let expected = response
+        .content_length()
+        .ok_or_else(|| "the camera sent no Content-Length, the download cannot be verified".to_string())?;
+    let mut file = tokio::fs::File::create(&part).await.map_err(|e| e.to_string())?;
+    let mut context = Context::new(&SHA256);
+    let mut written = 0u64;
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        context.update(&chunk);
+        file.write_all(&chunk).await.map_err(|e| e.to_string())?;
+        written += chunk.len() as u64;
+    }
+    file.sync_all().await.map_err(|e| e.to_string())?;
+    drop(file);
+    if written != expected {
+        return Err(format!("received {} of {} bytes", written, expected));
+    }
+    let checksum = archive::hex(context.finish().as_ref());
+    let name = dest.file_name().unwrap_or_default().to_string_lossy();
+    tokio::fs::write(archive::checksum_path(dest), format!("{}  {}\n", checksum, name))
+        .await
+        .map_err(|e| e.to_string())?;
+    tokio::fs::rename(&part, dest).await.map_err(|e| e.to_string())?;
+    Ok(written)
+}
@@ -0,0 +1,410 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use brig_types::{BandCalibration, CalibrationInfo, CaptureId};
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::PI;
+use tokio::task;
+
+use crate::camera::{camera_config, load_band_file};
+use crate::error::AppError;
+use crate::imaging::{decode_band, Band};
+use crate::{AppState, SharedState};
+
+/*
+Radiometric calibration of RedEdge band files, after image.py and metadata.py of the micasense package in
+sensor_modules. Every band TIFF carries its exposure, gain and black level (EXIF) and its radiometric calibration,
+vignetting model and DLS irradiance (XMP), which turn the raw values into radiance:
+    L = V(r) * R(y) * (raw - black level) / (gain * exposure) * a1 / 2^bits
+with V the vignetting correction at the distance r from the vignetting center and R(y) = 1 / (1 + a2 y / exposure - a3 y)
+the row gradient of the rolling shutter. Reflectance is then radiance * pi / irradiance, the irradiance coming from
+the calibration panel capture registered for the camera (cameras.<id>.panel) if there is one, otherwise from the DLS.
+The DLS spectral irradiance is used without the sun angle correction of dls.py, so captures without a panel are only
+roughly calibrated when the boat rolls. Reflectance bands are handed to the imaging and index code as 16 bit images
+scaled by REFLECTANCE_SCALE.
+*/
+
+// 16 bit value of a reflectance of 1
+pub const REFLECTANCE_SCALE: f64 = 10000.0;
+
+// EXIF and TIFF tags used by the calibration
+const TAG_BITS_PER_SAMPLE: u16 = 0x0102;
+const TAG_XMP: u16 = 0x02BC;
+const TAG_EXPOSURE_TIME: u16 = 0x829A;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_ISO_SPEED_RATINGS: u16 = 0x8827;
+const TAG_ISO_SPEED: u16 = 0x8833;
+const TAG_BLACK_LEVEL: u16 = 0xC61A;
+
+enum TagValue {
+    Numbers(Vec<f64>),
+    Bytes(Vec<u8>),
+}
+
+// tags of the first IFD and of its EXIF IFD, None if the file is not a TIFF
+fn read_tags(tif: &[u8]) -> Option<HashMap<u16, TagValue>> {
+    let little = match tif.get(0..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let u16_at = |at: usize| tif.get(at..at + 2).map(|b| if little { u16::from_le_bytes([b[0], b[1]]) } else { u16::from_be_bytes([b[0], b[1]]) });
+    let u32_at = |at: usize| {
+        tif.get(at..at + 4).map(|b| {
+            let b = [b[0], b[1], b[2], b[3]];
+            if little { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) }
+        })
+    };
+    let mut tags = HashMap::new();
+    let mut ifds = vec![u32_at(4)? as usize];
+    // an EXIF IFD pointing back to an IFD already read would loop forever
+    let mut visited = HashSet::new();
+    while let Some(ifd) = ifds.pop() {
+        if !visited.insert(ifd) {
+            continue;
+        }
+        let count = u16_at(ifd)? as usize;
+        for entry in (0..count).map(|i| ifd + 2 + i * 12) {
+            let (tag, kind, n) = (u16_at(entry)?, u16_at(entry + 2)?, u32_at(entry + 4)? as usize);
+            let size = match kind {
+                1 | 2 | 6 | 7 => 1,
+                3 | 8 => 2,
+                4 | 9 | 11 => 4,
+                5 | 10 | 12 => 8,
+                _ => continue,
+            };
+            let at = if size * n <= 4 { entry + 8 } else { u32_at(entry + 8)? as usize };
+            let Some(data) = tif.get(at..at + size * n) else {
+                continue;
+            };
+            let value = match kind {
+                1 | 2 | 6 | 7 => TagValue::Bytes(data.to_vec()),
+                _ => TagValue::Numbers(
+                    (0..n)
+                        .map(|i| {
+                            let at = at + i * size;
+                            match kind {
+                                3 => u16_at(at).map(f64::from),
+                                8 => u16_at(at).map(|v| v as i16 as f64),
+                                4 => u32_at(at).map(f64::from),
+                                9 => u32_at(at).map(|v| v as i32 as f64),
+                                11 => u32_at(at).map(|v| f32::from_bits(v) as f64),
+                                12 => {
+                                    let (a, b) = (u32_at(at)? as u64, u32_at(at + 4)? as u64);
+                                    Some(f64::from_bits(if little { b << 32 | a } else { a << 32 | b }))
+                                }
+                                5 => Some(u32_at(at)? as f64 / u32_at(at + 4)?.max(1) as f64),
+                                _ => Some(u32_at(at)? as i32 as f64 / (u32_at(at + 4)? as i32 as f64)),
+                            }
+                        })
+                        .collect::<Option<Vec<_>>>()?,
+                ),
+            };
+            if tag == TAG_EXIF_IFD {
+                if let TagValue::Numbers(offset) = &value {
+                    ifds.extend(offset.first().map(|&o| o as usize));
+                }
+            }
+            // the first IFD wins over the EXIF one
+            tags.entry(tag).or_insert(value);
+        }
+    }
+    Some(tags)
+}
+
+// text of a simple XMP property, written either as attribute (Camera:BandName="Blue") or as element,
+// whatever the namespace prefix
+fn xmp_text<'a>(xmp: &'a str, name: &str) -> Option<&'a str> {
+    if let Some(start) = xmp.find(&format!(":{}=\"", name)) {
+        let value = &xmp[start + name.len() + 3..];
+        return value.split('"').next();
+    }
+    let start = xmp.find(&format!(":{}>", name))? + name.len() + 2;
+    xmp[start..].split('<').next()
+}
+
+// numbers of an XMP sequence (<rdf:Seq><rdf:li>...) or of a comma separated property
+fn xmp_numbers(xmp: &str, name: &str) -> Vec<f64> {
+    let open = format!(":{}>", name);
+    if let Some(start) = xmp.find(&open) {
+        let body = &xmp[start + open.len()..];
+        let body = &body[..body.find(&open).unwrap_or(body.len())];
+        if body.contains("<rdf:li") {
+            return body
+                .split("<rdf:li")
+                .skip(1)
+                .filter_map(|item| item.split_once('>')?.1.split('<').next()?.trim().parse().ok())
+                .collect();
+        }
+    }
+    xmp_text(xmp, name)
+        .map(|text| text.split(',').filter_map(|v| v.trim().parse().ok()).collect())
+        .unwrap_or_default()
+}
+
+fn xmp_number(xmp: &str, name: &str) -> Option<f64> {
+    xmp_text(xmp, name)?.trim().parse().ok()
+}
+
+// calibration metadata of one band file
+pub struct BandMeta {
+    pub exposure: f64,
+    pub gain: f64,
+    pub black_level: f64,
+    pub bits: u32,
+    // a1, a2, a3
+    pub radiometric: [f64; 3],
+    pub vignette_center: (f64, f64),
+    pub vignette_polynomial: Vec<f64>,
+    // W/m^2/nm, already scaled to SI units, 0 when not measured
+    pub horizontal_irradiance: f64,
+    pub spectral_irradiance: f64,
+    // panel found by the camera in an auto calibration capture: albedo and x1,y1,...,x4,y4 of its corners
+    pub panel_albedo: Option<f64>,
+    pub panel_corners: Vec<f64>,
+}
+
+pub fn read_meta(tif: &[u8]) -> Result<BandMeta, String> {
+    let tags = read_tags(tif).ok_or("not a TIFF file")?;
+    let number = |tag: u16| match tags.get(&tag) {
+        Some(TagValue::Numbers(values)) => values.first().copied(),
+        _ => None,
+    };
+    let xmp = match tags.get(&TAG_XMP) {
+        Some(TagValue::Bytes(bytes)) => String::from_utf8_lossy(bytes).into_owned(),
+        Some(TagValue::Numbers(_)) | None => return Err("no XMP metadata".to_string()),
+    };
+    let mut exposure = number(TAG_EXPOSURE_TIME).ok_or("no exposure time")?;
+    // legacy RedEdge firmwares report 1/6329 s for 0.274 ms, see metadata.py
+    if (exposure - 1.0 / 6329.0).abs() < 1e-6 {
+        exposure = 0.000274;
+    }
+    let iso = number(TAG_ISO_SPEED).or_else(|| number(TAG_ISO_SPEED_RATINGS)).ok_or("no ISO speed")?;
+    let black_level = match tags.get(&TAG_BLACK_LEVEL) {
+        Some(TagValue::Numbers(values)) if !values.is_empty() => values.iter().sum::<f64>() / values.len() as f64,
+        _ => 0.0,
+    };
+    let radiometric = xmp_numbers(&xmp, "RadiometricCalibration");
+    let [a1, a2, a3] = radiometric[..] else {
+        return Err("no radiometric calibration (RedEdge firmware before 2.1?)".to_string());
+    };
+    let vignette_center = match xmp_numbers(&xmp, "VignettingCenter")[..] {
+        [x, y] => (x, y),
+        _ => return Err("no vignetting center".to_string()),
+    };
+    // IrradianceScaleToSIUnits, or 0.01 for DLS2 files without it and 1 for DLS1, see metadata.py
+    let scale = xmp_number(&xmp, "IrradianceScaleToSIUnits")
+        .unwrap_or(if xmp_text(&xmp, "HorizontalIrradiance").is_some() { 0.01 } else { 1.0 });
+    let panel = xmp_number(&xmp, "CalibrationPicture") == Some(2.0);
+    Ok(BandMeta {
+        exposure,
+        gain: iso / 100.0,
+        black_level,
+        bits: number(TAG_BITS_PER_SAMPLE).unwrap_or(16.0) as u32,
+        radiometric: [a1, a2, a3],
+        vignette_center,
+        vignette_polynomial: xmp_numbers(&xmp, "VignettingPolynomial"),
+        horizontal_irradiance: xmp_number(&xmp, "HorizontalIrradiance").unwrap_or(0.0) * scale,
+        spectral_irradiance: xmp_number(&xmp, "SpectralIrradiance").unwrap_or(0.0) * scale,
+        panel_albedo: xmp_number(&xmp, "Albedo").filter(|_| panel),
+        panel_corners: if panel { xmp_numbers(&xmp, "ReflectArea") } else { Vec::new() },
+    })
+}
+
+// radiance of every pixel, in row order
+pub fn radiance(raw: &Band, meta: &BandMeta) -> Vec<f32> {
+    let [a1, a2, a3] = meta.radiometric;
+    let (cx, cy) = meta.vignette_center;
+    let max_dn = 2f64.powi(meta.bits as i32);
+    let scale = a1 / (meta.gain * meta.exposure * max_dn);
+    let width = raw.width() as usize;
+    raw.as_raw()
+        .iter()
+        .enumerate()
+        .map(|(i, &value)| {
+            let (x, y) = ((i % width) as f64, (i / width) as f64);
+            let r = (x - cx).hypot(y - cy);
+            // 1 + k0 r + k1 r^2 + ...
+            let polynomial = meta.vignette_polynomial.iter().rev().fold(0.0, |acc, k| (acc + k) * r) + 1.0;
+            let row = 1.0 / (1.0 + a2 * y / meta.exposure - a3 * y);
+            let corrected = (row / polynomial * (value as f64 - meta.black_level)).max(0.0);
+            (corrected * scale) as f32
+        })
+        .collect()
+}
+
+// where the irradiance of a band comes from
+#[derive(Clone, Copy)]
+enum Source {
+    Panel,
+    DlsHorizontal,
+    DlsSpectral,
+}
+
+impl Source {
+    fn name(self) -> &'static str {
+        match self {
+            Source::Panel => "panel",
+            Source::DlsHorizontal => "dls_horizontal",
+            Source::DlsSpectral => "dls_spectral",
+        }
+    }
+}
+
+// irradiance of one band from the registered panel capture: pi * mean panel radiance / panel albedo
+async fn panel_irradiance(state: &AppState, camera: &str, band: u8) -> Result<Option<f64>, AppError> {
+    let Some(panel) = &camera_config(state, camera)?.panel else {
+        return Ok(None);
+    };
+    let id = CaptureId::parse(&panel.capture)
+        .ok_or_else(|| AppError::BadRequest(format!("the panel capture of {} is not a capture id", camera)))?;
+    let tif = load_band_file(state, camera, &id.band_path(band)).await?;
+    let (albedo, region) = (panel.albedo.get(band as usize - 1).copied(), panel.region);
+    let what = format!("the panel capture {}", id);
+    let irradiance = task::spawn_blocking(move || {
+        let meta = read_meta(&tif)?;
+        let raw = decode_band(&tif).map_err(|e| e.to_string())?;
+        let albedo = albedo.or(meta.panel_albedo).ok_or("no albedo for the panel in the config nor in the capture")?;
+        let [x, y, w, h] = match (region, &meta.panel_corners[..]) {
+            (Some(region), _) => region,
+            (None, corners) if corners.len() == 8 => {
+                let xs = [corners[0], corners[2], corners[4], corners[6]];
+                let ys = [corners[1], corners[3], corners[5], corners[7]];
+                let (x0, x1) = (xs.iter().copied().fold(f64::MAX, f64::min), xs.iter().copied().fold(0.0, f64::max));
+                let (y0, y1) = (ys.iter().copied().fold(f64::MAX, f64::min), ys.iter().copied().fold(0.0, f64::max));
+                [x0 as u32, y0 as u32, (x1 - x0) as u32, (y1 - y0) as u32]
+            }
+            _ => return Err("no panel region in the config nor in the capture".to_string()),
+        };
+        let radiance = radiance(&raw, &meta);
+        let width = raw.width();
+        let values: Vec<f64> = (y..(y + h).min(raw.height()))
+            .flat_map(|row| (x..(x + w).min(width)).map(move |col| (row * width + col) as usize))
+            .map(|i| radiance[i] as f64)
+            .collect();
+        if values.is_empty() {
+            return Err("the panel region is outside the image".to_string());
+        }
+        Ok(values.iter().sum::<f64>() / values.len() as f64 * PI / albedo)
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result)
+    .map_err(|e| AppError::Parse(what, e))?;
+    Ok(Some(irradiance))
+}
+
+// irradiance for a band file and where it comes from
+async fn irradiance(state: &AppState, camera: &str, band: u8, meta: &BandMeta) -> Result<(f64, Source), AppError> {
+    if let Some(irradiance) = panel_irradiance(state, camera, band).await? {
+        return Ok((irradiance, Source::Panel));
+    }
+    if meta.horizontal_irradiance > 0.0 {
+        return Ok((meta.horizontal_irradiance, Source::DlsHorizontal));
+    }
+    if meta.spectral_irradiance > 0.0 {
+        return Ok((meta.spectral_irradiance, Source::DlsSpectral));
+    }
+    Err(AppError::NoData(format!("no irradiance for band {} of {}: no panel is registered and there is no DLS data", band, camera)))
+}
+
+// reflectance band scaled by REFLECTANCE_SCALE
+fn to_band(radiance: &[f32], width: u32, height: u32, irradiance: f64) -> Band {
+    let values = radiance
+        .iter()
+        .map(|&l| (l as f64 * PI / irradiance * REFLECTANCE_SCALE).round().clamp(0.0, u16::MAX as f64) as u16)
+        .collect();
+    Band::from_raw(width, height, values).unwrap_or_else(|| Band::new(width, height))
+}
+
+// bands of a capture, raw or converted to reflectance
+pub async fn capture_bands(state: &AppState, id: &CaptureId, bands: &[u8], reflectance: bool) -> Result<Vec<Band>, AppError> {
+    let mut images = Vec::new();
+    for &band in bands {
+        let tif = load_band_file(state, &id.camera, &id.band_path(band)).await?;
+        let what = format!("band {} of capture {}", band, id);
+        if !reflectance {
+            let raw = task::spawn_blocking(move || decode_band(&tif))
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|result| result.map_err(|e| e.to_string()))
+                .map_err(|e| AppError::Parse(what, e))?;
+            images.push(raw);
+            continue;
+        }
+        let (meta, raw) = decode_with_meta(what.clone(), tif).await?;
+        let (irradiance, _) = irradiance(state, &id.camera, band, &meta).await?;
+        let image = task::spawn_blocking(move || to_band(&radiance(&raw, &meta), raw.width(), raw.height(), irradiance))
+            .await
+            .map_err(|e| AppError::Parse(what, e.to_string()))?;
+        images.push(image);
+    }
+    Ok(images)
+}
+
+async fn decode_with_meta(what: String, tif: Vec<u8>) -> Result<(BandMeta, Band), AppError> {
+    task::spawn_blocking(move || Ok::<_, String>((read_meta(&tif)?, decode_band(&tif).map_err(|e| e.to_string())?)))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result)
+        .map_err(|e| AppError::Parse(what, e))
+}
+
+// calibration of every band of a capture: metadata, irradiance and mean reflectance
+pub async fn capture_calibration(
+    State(state): State<SharedState>,
+    Path(capture): Path<String>,
+) -> Result<Json<CalibrationInfo>, AppError> {
+    let id = CaptureId::parse(&capture)
+        .ok_or_else(|| AppError::BadRequest(format!("{} is not a capture id like cam1-SET0001-000-0001", capture)))?;
+    let bands = camera_config(&state, &id.camera)?.bands.len() as u8;
+    let mut calibrations = Vec::new();
+    for band in 1..=bands {
+        let tif = load_band_file(&state, &id.camera, &id.band_path(band)).await?;
+        let what = format!("band {} of capture {}", band, id);
+        let (meta, raw) = decode_with_meta(what.clone(), tif).await?;
+        let mut calibration = BandCalibration {
+            band,
+            exposure_s: meta.exposure,
+            gain: meta.gain,
+            black_level: meta.black_level,
+            irradiance: None,
+            irradiance_source: None,
+            mean_reflectance: None,
+        };
+        match irradiance(&state, &id.camera, band, &meta).await {
+            Ok((irradiance, source)) => {
+                let mean_radiance = task::spawn_blocking(move || {
+                    radiance(&raw, &meta).iter().map(|&l| l as f64).sum::<f64>() / raw.as_raw().len().max(1) as f64
+                })
+                .await
+                .map_err(|e| AppError::Parse(what, e.to_string()))?;
+                calibration.irradiance = Some(irradiance);
+                calibration.irradiance_source = Some(source.name().to_string());
+                calibration.mean_reflectance = Some(mean_radiance * PI / irradiance);
+            }
+            // reported as missing, the metadata of the band is still useful
+            Err(AppError::NoData(_)) => {}
+            Err(e) => return Err(e),
+        }
+        calibrations.push(calibration);
+    }
+    Ok(Json(CalibrationInfo { capture: id.to_string(), bands: calibrations }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_tags_stops_on_an_exif_ifd_loop() {
+        // little endian TIFF whose only IFD has an EXIF IFD pointer back to itself, next to a black level
+        let mut tif = b"II*\0\x08\0\0\0\x02\0".to_vec();
+        tif.extend([0x69, 0x87, 4, 0, 1, 0, 0, 0, 8, 0, 0, 0]);
+        tif.extend([0x1A, 0xC6, 3, 0, 1, 0, 0, 0, 0, 16, 0, 0]);
+        tif.extend([0, 0, 0, 0]);
+        let tags = read_tags(&tif).unwrap();
+        assert!(matches!(&tags[&TAG_BLACK_LEVEL], TagValue::Numbers(v) if v == &[4096.0]));
+    }
+}
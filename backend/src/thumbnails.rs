@@ -0,0 +1,341 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use brig_types::{CaptureId, CaptureParams};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use image::imageops::{self, FilterType};
+use image::DynamicImage;
+use ring::digest::{digest, SHA256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tokio::task;
+use tracing::{info, warn};
+
+use crate::camera::{camera_config, capture_records, load_band_file};
+use crate::config::ThumbnailConfig;
+use crate::error::AppError;
+use crate::imaging::{self, decode_band, encode_jpeg, percentile_range, stretch};
+use crate::{AppState, SharedState};
+
+/*
+Cache of the JPEG thumbnails of single capture bands, shown by the camera page.
+A thumbnail is a band stretched between the default percentiles (see imaging.rs) and scaled down to
+thumbnails.width; rendering one means fetching a full TIFF from the camera or the archive, so they are kept twice:
+in memory for the ones shown right now and on disk under thumbnails.dir (laid out like the archive, with .jpg files)
+for the older ones, both evicted least recently used first when over their size. A capture never changes, so the
+key is the capture and the band and cached thumbnails are never invalidated. Responses carry an ETag (a hash of the
+JPEG) so browsers revalidate instead of downloading the image again.
+A background task watches micasense_data for the captures camera_capture logs and renders the thumbnails of every
+new one, oldest first, so the first page load after a capture does not wait for the camera.
+*/
+
+// disk eviction goes down to this share of thumbnails.disk_mb, so it does not run on every write
+const DISK_LOW_WATER: f64 = 0.9;
+
+// captures logged this long before the backend started are rendered ahead of the first request too
+const PREGENERATE_LOOKBACK: chrono::Duration = chrono::Duration::hours(1);
+// polls a capture is tried on while the camera or Influx fail before it is skipped
+const PREGENERATE_ATTEMPTS: u32 = 5;
+
+#[derive(Clone)]
+pub struct Thumbnail {
+    pub jpeg: Bytes,
+    // quoted, as sent in the ETag header
+    pub etag: String,
+}
+
+impl Thumbnail {
+    fn new(jpeg: Vec<u8>) -> Self {
+        let hash = digest(&SHA256, &jpeg);
+        let etag = format!("\"{}\"", hash.as_ref()[..12].iter().map(|b| format!("{:02x}", b)).collect::<String>());
+        Thumbnail { jpeg: Bytes::from(jpeg), etag }
+    }
+}
+
+// in memory thumbnails, `order` maps the last use of every entry to its key
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<String, (Thumbnail, u64)>,
+    order: BTreeMap<u64, String>,
+    clock: u64,
+    bytes: u64,
+}
+
+impl Lru {
+    fn get(&mut self, key: &str) -> Option<Thumbnail> {
+        self.clock += 1;
+        let (thumbnail, used) = self.entries.get_mut(key)?;
+        self.order.remove(used);
+        *used = self.clock;
+        self.order.insert(self.clock, key.to_string());
+        Some(thumbnail.clone())
+    }
+
+    fn insert(&mut self, key: String, thumbnail: Thumbnail, limit: u64) {
+        let size = thumbnail.jpeg.len() as u64;
+        if size > limit {
+            return;
+        }
+        if let Some((old, used)) = self.entries.remove(&key) {
+            self.order.remove(&used);
+            self.bytes -= old.jpeg.len() as u64;
+        }
+        while self.bytes + size > limit {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some((old, _)) = self.entries.remove(&oldest) {
+                self.bytes -= old.jpeg.len() as u64;
+            }
+        }
+        self.clock += 1;
+        self.order.insert(self.clock, key.clone());
+        self.entries.insert(key, (thumbnail, self.clock));
+        self.bytes += size;
+    }
+}
+
+pub struct Thumbnails {
+    config: ThumbnailConfig,
+    memory: Mutex<Lru>,
+    // bytes of thumbnails on disk, None until the directory has been scanned
+    disk_bytes: Mutex<Option<u64>>,
+    // numbers the temporary files, two requests for the same thumbnail may render it at once
+    writes: AtomicU64,
+    // set while one write evicts, so concurrent writes do not remove the same files
+    evicting: AtomicBool,
+}
+
+impl Thumbnails {
+    pub fn new(config: ThumbnailConfig) -> Self {
+        Thumbnails { config, memory: Mutex::new(Lru::default()), disk_bytes: Mutex::new(None), writes: AtomicU64::new(0), evicting: AtomicBool::new(false) }
+    }
+
+    fn memory_limit(&self) -> u64 {
+        self.config.memory_mb * 1024 * 1024
+    }
+
+    fn disk_limit(&self) -> u64 {
+        self.config.disk_mb * 1024 * 1024
+    }
+
+    // file of a thumbnail, next to the ones of the other bands of its capture
+    fn disk_path(&self, id: &CaptureId, band: u8) -> PathBuf {
+        self.config.dir.join(&id.camera).join(&id.set).join(&id.folder).join(format!("IMG_{}_{}.jpg", id.number, band))
+    }
+}
+
+// thumbnail of a band of a capture, from memory, disk or rendered
+pub async fn thumbnail(state: &AppState, id: &CaptureId, band: u8) -> Result<Thumbnail, AppError> {
+    let thumbnails = &state.thumbnails;
+    let key = format!("{}-{}", id, band);
+    if let Some(thumbnail) = thumbnails.memory.lock().unwrap().get(&key) {
+        return Ok(thumbnail);
+    }
+    let path = thumbnails.disk_path(id, band);
+    if let Ok(jpeg) = tokio::fs::read(&path).await {
+        // the modification time is the last use for the disk eviction
+        let _ = task::spawn_blocking(move || std::fs::File::options().write(true).open(&path)?.set_modified(SystemTime::now())).await;
+        let thumbnail = Thumbnail::new(jpeg);
+        thumbnails.memory.lock().unwrap().insert(key, thumbnail.clone(), thumbnails.memory_limit());
+        return Ok(thumbnail);
+    }
+    let tif = load_band_file(state, &id.camera, &id.band_path(band)).await?;
+    let width = thumbnails.config.width;
+    let jpeg = task::spawn_blocking(move || {
+        let band = decode_band(&tif).map_err(|e| e.to_string())?;
+        encode_jpeg(&render(&band, width)).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|result| result)
+    .map_err(|e| AppError::Parse(format!("band {} of capture {}", band, id), e))?;
+    if let Err(e) = store(thumbnails, &path, &jpeg).await {
+        info!("Could not store the thumbnail {}: {}", path.display(), e);
+    }
+    let thumbnail = Thumbnail::new(jpeg);
+    thumbnails.memory.lock().unwrap().insert(key, thumbnail.clone(), thumbnails.memory_limit());
+    Ok(thumbnail)
+}
+
+// a band stretched like the composites, no wider than width
+fn render(band: &imaging::Band, width: u32) -> DynamicImage {
+    let (lo, hi) = percentile_range(band, imaging::DEFAULT_LOW, imaging::DEFAULT_HIGH);
+    let gray = stretch(band, lo, hi);
+    if gray.width() <= width {
+        return DynamicImage::ImageLuma8(gray);
+    }
+    let height = (gray.height() as u64 * width as u64 / gray.width() as u64).max(1) as u32;
+    DynamicImage::ImageLuma8(imageops::resize(&gray, width, height, FilterType::Triangle))
+}
+
+// writes a thumbnail to disk through a temporary file of its own, then evicts old ones if the cache got too big
+async fn store(thumbnails: &Thumbnails, path: &FsPath, jpeg: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let mut part = path.as_os_str().to_owned();
+    part.push(format!(".{}.part", thumbnails.writes.fetch_add(1, Ordering::Relaxed)));
+    tokio::fs::write(&part, jpeg).await?;
+    if let Err(e) = tokio::fs::rename(&part, path).await {
+        let _ = tokio::fs::remove_file(&part).await;
+        return Err(e);
+    }
+    let added = thumbnails.disk_bytes.lock().unwrap().as_mut().map(|bytes| {
+        *bytes += jpeg.len() as u64;
+        *bytes
+    });
+    let total = match added {
+        Some(total) => total,
+        None => {
+            let dir = thumbnails.config.dir.clone();
+            let scanned = task::spawn_blocking(move || disk_files(&dir).iter().map(|(_, size, _)| size).sum()).await.unwrap_or(0);
+            // the first scan to finish is kept, it counts every file renamed before it
+            *thumbnails.disk_bytes.lock().unwrap().get_or_insert(scanned)
+        }
+    };
+    if total > thumbnails.disk_limit() && !thumbnails.evicting.swap(true, Ordering::Relaxed) {
+        let (dir, target) = (thumbnails.config.dir.clone(), (thumbnails.disk_limit() as f64 * DISK_LOW_WATER) as u64);
+        let freed = task::spawn_blocking(move || evict(&dir, target)).await.unwrap_or(0);
+        if let Some(bytes) = thumbnails.disk_bytes.lock().unwrap().as_mut() {
+            *bytes = bytes.saturating_sub(freed);
+        }
+        thumbnails.evicting.store(false, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+// every thumbnail under dir with its size and last use
+fn disk_files(dir: &FsPath) -> Vec<(PathBuf, u64, SystemTime)> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let (path, Ok(meta)) = (entry.path(), entry.metadata()) else {
+                continue;
+            };
+            if meta.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|e| e == "jpg") {
+                files.push((path, meta.len(), meta.modified().unwrap_or(SystemTime::UNIX_EPOCH)));
+            }
+        }
+    }
+    files
+}
+
+// removes the least recently used thumbnails until at most target bytes are left, returns the bytes removed
+fn evict(dir: &FsPath, target: u64) -> u64 {
+    let mut files = disk_files(dir);
+    files.sort_by_key(|(_, _, used)| *used);
+    let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+    let mut freed = 0;
+    for (path, size, _) in files {
+        if total <= target {
+            break;
+        }
+        if std::fs::remove_file(&path).is_ok() {
+            total -= size;
+            freed += size;
+        }
+    }
+    freed
+}
+
+// JPEG response, or 304 when the browser already has this thumbnail
+pub fn respond(thumbnail: Thumbnail, headers: &HeaderMap, cache_control: &'static str) -> Response {
+    let cached = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|tags| tags.split(',').any(|tag| tag.trim() == thumbnail.etag || tag.trim() == "*"));
+    let caching = [(header::ETAG, thumbnail.etag), (header::CACHE_CONTROL, cache_control.to_string())];
+    if cached {
+        return (StatusCode::NOT_MODIFIED, caching).into_response();
+    }
+    (caching, [(header::CONTENT_TYPE, "image/jpeg")], thumbnail.jpeg).into_response()
+}
+
+// thumbnail of one band of any capture; captures do not change, so browsers may keep it for a day
+pub async fn capture_thumbnail(
+    State(state): State<SharedState>,
+    Path(capture): Path<String>,
+    Query(params): Query<CaptureParams>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let id = CaptureId::parse(&capture)
+        .ok_or_else(|| AppError::BadRequest(format!("{} is not a capture id like cam1-SET0001-000-0001", capture)))?;
+    let band = imaging::parse_band(&params.band, &camera_config(&state, &id.camera)?.bands)?;
+    let thumbnail = thumbnail(&state, &id, band).await?;
+    Ok(respond(thumbnail, &headers, "private, max-age=86400"))
+}
+
+// renders the thumbnails of every band of each capture as soon as it is logged
+pub async fn pregenerate(state: SharedState) {
+    if state.config.thumbnails.pregenerate_interval_secs == 0 {
+        return;
+    }
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(state.config.thumbnails.pregenerate_interval_secs));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    // time of the last capture rendered (or skipped) per camera, the next poll starts after it
+    let started = Utc::now() - PREGENERATE_LOOKBACK;
+    let mut rendered: HashMap<String, DateTime<Utc>> = HashMap::new();
+    // capture that failed on the last poll per camera, with the polls it failed on
+    let mut failing: HashMap<String, (DateTime<Utc>, u32)> = HashMap::new();
+    loop {
+        interval.tick().await;
+        for (camera, config) in &state.config.cameras {
+            let since = *rendered.get(camera).unwrap_or(&started);
+            let captures = match capture_records(&state, camera, since, Utc::now()).await {
+                Ok(captures) => captures,
+                Err(e) => {
+                    info!("Thumbnail poll of {} failed: {}", camera, e);
+                    continue;
+                }
+            };
+            for (time, path) in captures.into_iter().filter(|(time, _)| *time > since) {
+                if let Some(id) = CaptureId::from_path(camera, &path) {
+                    if let Err(e) = render_bands(&state, &id, config.bands.len() as u8).await {
+                        let attempts = match failing.get(camera) {
+                            Some((failed, attempts)) if *failed == time => attempts + 1,
+                            _ => 1,
+                        };
+                        // tried again from this capture on the next poll, e.g. when the camera was still writing the files
+                        if transient(&e) && attempts < PREGENERATE_ATTEMPTS {
+                            info!("Could not render the thumbnails of {} (attempt {}): {}", id, attempts, e);
+                            failing.insert(camera.clone(), (time, attempts));
+                            break;
+                        }
+                        warn!("Skipping the thumbnails of {}: {}", id, e);
+                    }
+                }
+                failing.remove(camera);
+                rendered.insert(camera.clone(), time);
+            }
+        }
+    }
+}
+
+// errors a later poll may not run into, unlike a capture that is gone or cannot be decoded
+fn transient(e: &AppError) -> bool {
+    matches!(
+        e,
+        AppError::Influx(_) | AppError::CameraTimeout(..) | AppError::CameraUnreachable(..) | AppError::CameraResponse(..)
+    )
+}
+
+// thumbnails of every band of a capture, stops at the first band that fails
+async fn render_bands(state: &AppState, id: &CaptureId, bands: u8) -> Result<(), AppError> {
+    for band in 1..=bands {
+        thumbnail(state, id, band).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,712 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use brig_types::TileSetInfo;
+use flate2::read::GzDecoder;
+use reqwest::Client;
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::fs::FileExt;
+use std::path::Path as FsPath;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tokio::task;
+
+use crate::config::TileConfig;
+use crate::error::AppError;
+use crate::SharedState;
+
+/*
+Offline map tiles, so the map of the UI works at sea.
+tiles.file is an MBTiles (SQLite, rows in the TMS scheme) or a PMTiles v3 archive, told apart by the extension,
+and is served on /api/tiles/{z}/{x}/{y} in the XYZ scheme of web maps. The file is opened on the first request and
+again whenever it changes, so it can be replaced or seeded while the backend runs. Only raster tiles (png, jpg,
+webp) are served, the map draws them as images.
+The seed-tiles command fills an MBTiles file with the tiles of a bounding box downloaded from tiles.source_url while
+on shore; tiles already in the file are skipped, so an interrupted seed resumes where it stopped. PMTiles archives
+cannot be added to, build them with the pmtiles tool instead.
+*/
+
+const CACHE_CONTROL: &str = "private, max-age=86400";
+// deepest zoom level of web maps
+const MAX_ZOOM: u8 = 22;
+// leaf directories of a PMTiles archive kept in memory
+const MAX_LEAF_DIRECTORIES: usize = 64;
+const SEED_TIMEOUT: Duration = Duration::from_secs(30);
+const SEED_ATTEMPTS: u32 = 3;
+
+// tile set of tiles.file, None until the first request or when the file is missing
+#[derive(Default)]
+pub struct Tiles {
+    opened: Mutex<Option<(SystemTime, TileSource)>>,
+}
+
+impl Tiles {
+    // runs f on the tile set, reopened if the file changed; None when the file does not exist
+    fn with_source<T>(&self, file: &FsPath, f: impl FnOnce(&mut TileSource) -> Result<T, String>) -> Result<Option<T>, String> {
+        let mut opened = self.opened.lock().unwrap_or_else(|e| e.into_inner());
+        let Ok(modified) = std::fs::metadata(file).and_then(|m| m.modified()) else {
+            *opened = None;
+            return Ok(None);
+        };
+        if opened.as_ref().is_none_or(|(time, _)| *time != modified) {
+            *opened = Some((modified, TileSource::open(file)?));
+        }
+        let (_, source) = opened.as_mut().expect("opened above");
+        f(source).map(Some)
+    }
+}
+
+enum TileSource {
+    // the connection and the format of the metadata
+    MbTiles(Connection, String),
+    PmTiles(PmTiles),
+}
+
+impl TileSource {
+    fn open(file: &FsPath) -> Result<Self, String> {
+        if file.extension().is_some_and(|e| e.eq_ignore_ascii_case("pmtiles")) {
+            return PmTiles::open(file).map(TileSource::PmTiles);
+        }
+        let connection = Connection::open_with_flags(file, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+            .map_err(|e| e.to_string())?;
+        let format = mbtiles_metadata(&connection).map_err(|e| e.to_string())?.remove("format").unwrap_or_default();
+        Ok(TileSource::MbTiles(connection, format))
+    }
+
+    fn format(&self) -> &str {
+        match self {
+            TileSource::MbTiles(_, format) => format,
+            TileSource::PmTiles(pmtiles) => pmtiles.format,
+        }
+    }
+
+    fn info(&mut self) -> Result<TileSetInfo, String> {
+        match self {
+            TileSource::MbTiles(connection, _) => mbtiles_info(connection).map_err(|e| e.to_string()),
+            TileSource::PmTiles(pmtiles) => pmtiles.info(),
+        }
+    }
+
+    // tile in the XYZ scheme, None when the tile set does not have it
+    fn tile(&mut self, z: u8, x: u32, y: u32) -> Result<Option<Vec<u8>>, String> {
+        match self {
+            TileSource::MbTiles(connection, _) => connection
+                .query_row(
+                    "SELECT tile_data FROM tiles WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+                    (z, x, tms_row(z, y)),
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(|e| e.to_string()),
+            TileSource::PmTiles(pmtiles) => pmtiles.tile(z, x, y),
+        }
+    }
+}
+
+// MBTiles rows count from the south
+fn tms_row(z: u8, y: u32) -> u32 {
+    (1u32 << z) - 1 - y
+}
+
+fn mbtiles_metadata(connection: &Connection) -> rusqlite::Result<HashMap<String, String>> {
+    let mut statement = connection.prepare("SELECT name, value FROM metadata")?;
+    let rows = statement.query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+
+fn mbtiles_info(connection: &Connection) -> rusqlite::Result<TileSetInfo> {
+    let metadata = mbtiles_metadata(connection)?;
+    let (min, max): (Option<u8>, Option<u8>) =
+        connection.query_row("SELECT MIN(zoom_level), MAX(zoom_level) FROM tiles", (), |row| Ok((row.get(0)?, row.get(1)?)))?;
+    let zoom = |name: &str, fallback: Option<u8>| metadata.get(name).and_then(|z| z.parse().ok()).or(fallback).unwrap_or(0);
+    Ok(TileSetInfo {
+        format: metadata.get("format").cloned().unwrap_or_else(|| "png".to_string()),
+        min_zoom: zoom("minzoom", min),
+        max_zoom: zoom("maxzoom", max),
+        bounds: metadata.get("bounds").and_then(|b| parse_bounds(b)),
+        attribution: metadata.get("attribution").filter(|a| !a.is_empty()).cloned(),
+    })
+}
+
+// west,south,east,north as in the MBTiles metadata
+fn parse_bounds(bounds: &str) -> Option<[f64; 4]> {
+    let values: Vec<f64> = bounds.split(',').map(|v| v.trim().parse().ok()).collect::<Option<_>>()?;
+    values.try_into().ok()
+}
+
+fn content_type(format: &str) -> &'static str {
+    match format {
+        "jpg" | "jpeg" => "image/jpeg",
+        "webp" => "image/webp",
+        _ => "image/png",
+    }
+}
+
+// format of a raster tile from its first bytes
+fn sniff_format(tile: &[u8]) -> Option<&'static str> {
+    if tile.starts_with(b"\x89PNG") {
+        Some("png")
+    } else if tile.starts_with(&[0xFF, 0xD8]) {
+        Some("jpg")
+    } else if tile.len() > 12 && &tile[0..4] == b"RIFF" && &tile[8..12] == b"WEBP" {
+        Some("webp")
+    } else {
+        None
+    }
+}
+
+// entry of a PMTiles directory: a run of tiles, or a leaf directory when run_length is 0
+#[derive(Clone, Copy)]
+struct Entry {
+    tile_id: u64,
+    offset: u64,
+    length: u64,
+    run_length: u64,
+}
+
+// PMTiles v3 archive, https://github.com/protomaps/PMTiles/blob/main/spec/v3/spec.md
+struct PmTiles {
+    file: File,
+    root: Vec<Entry>,
+    leaves: HashMap<u64, Vec<Entry>>,
+    leaf_offset: u64,
+    data_offset: u64,
+    internal_gzip: bool,
+    tile_gzip: bool,
+    format: &'static str,
+    min_zoom: u8,
+    max_zoom: u8,
+    bounds: [f64; 4],
+    attribution: Option<String>,
+}
+
+impl PmTiles {
+    fn open(path: &FsPath) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        let mut header = [0u8; 127];
+        file.read_exact_at(&mut header, 0).map_err(|e| format!("not a PMTiles archive: {}", e))?;
+        if &header[0..7] != b"PMTiles" || header[7] != 3 {
+            return Err("not a PMTiles v3 archive".to_string());
+        }
+        let u64_at = |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().expect("8 bytes"));
+        let degrees_at = |at: usize| i32::from_le_bytes(header[at..at + 4].try_into().expect("4 bytes")) as f64 / 1e7;
+        // compressions: 1 none, 2 gzip; other ones (brotli, zstd) are not supported
+        let gzip = |compression: u8| match compression {
+            0 | 1 => Ok(false),
+            2 => Ok(true),
+            other => Err(format!("unsupported PMTiles compression {}", other)),
+        };
+        let format = match header[99] {
+            2 => "png",
+            3 => "jpg",
+            4 => "webp",
+            1 => return Err("vector tiles are not supported, use a raster tile set".to_string()),
+            other => return Err(format!("unsupported PMTiles tile type {}", other)),
+        };
+        let mut pmtiles = PmTiles {
+            file,
+            root: Vec::new(),
+            leaves: HashMap::new(),
+            leaf_offset: u64_at(40),
+            data_offset: u64_at(56),
+            internal_gzip: gzip(header[97])?,
+            tile_gzip: gzip(header[98])?,
+            format,
+            min_zoom: header[100],
+            max_zoom: header[101],
+            bounds: [degrees_at(102), degrees_at(106), degrees_at(110), degrees_at(114)],
+            attribution: None,
+        };
+        pmtiles.root = pmtiles.directory(u64_at(8), u64_at(16))?;
+        // the JSON metadata, only its attribution is used
+        if u64_at(32) > 0 {
+            let metadata = pmtiles.read(u64_at(24), u64_at(32), pmtiles.internal_gzip)?;
+            pmtiles.attribution = serde_json::from_slice::<serde_json::Value>(&metadata)
+                .ok()
+                .and_then(|m| Some(m.get("attribution")?.as_str()?.to_string()));
+        }
+        Ok(pmtiles)
+    }
+
+    fn read(&self, offset: u64, length: u64, gzip: bool) -> Result<Vec<u8>, String> {
+        let mut bytes = vec![0u8; usize::try_from(length).map_err(|e| e.to_string())?];
+        self.file.read_exact_at(&mut bytes, offset).map_err(|e| e.to_string())?;
+        if !gzip {
+            return Ok(bytes);
+        }
+        let mut decompressed = Vec::new();
+        GzDecoder::new(bytes.as_slice()).read_to_end(&mut decompressed).map_err(|e| e.to_string())?;
+        Ok(decompressed)
+    }
+
+    // directory as varints: count, then the tile id deltas, run lengths, lengths and offsets of the entries
+    fn directory(&self, offset: u64, length: u64) -> Result<Vec<Entry>, String> {
+        let bytes = self.read(offset, length, self.internal_gzip)?;
+        let mut position = 0;
+        let mut next = || -> Result<u64, String> {
+            let mut value = 0u64;
+            for shift in (0..64).step_by(7) {
+                let byte = *bytes.get(position).ok_or("truncated PMTiles directory")?;
+                position += 1;
+                value |= u64::from(byte & 0x7F) << shift;
+                if byte & 0x80 == 0 {
+                    return Ok(value);
+                }
+            }
+            Err("invalid varint in PMTiles directory".to_string())
+        };
+        let count = next()? as usize;
+        let mut entries = vec![Entry { tile_id: 0, offset: 0, length: 0, run_length: 0 }; count.min(bytes.len())];
+        if entries.len() != count {
+            return Err("truncated PMTiles directory".to_string());
+        }
+        let mut tile_id = 0;
+        for entry in entries.iter_mut() {
+            tile_id += next()?;
+            entry.tile_id = tile_id;
+        }
+        for entry in entries.iter_mut() {
+            entry.run_length = next()?;
+        }
+        for entry in entries.iter_mut() {
+            entry.length = next()?;
+        }
+        // an offset of 0 means right after the previous entry, others are stored plus one
+        for i in 0..count {
+            let value = next()?;
+            entries[i].offset = match (value, i) {
+                (0, i) if i > 0 => entries[i - 1].offset + entries[i - 1].length,
+                (value, _) => value.saturating_sub(1),
+            };
+        }
+        Ok(entries)
+    }
+
+    fn tile(&mut self, z: u8, x: u32, y: u32) -> Result<Option<Vec<u8>>, String> {
+        let tile_id = tile_id(z, x, y);
+        let mut entries = self.root.clone();
+        // the root and at most three levels of leaf directories
+        for _ in 0..4 {
+            let Some(entry) = find(&entries, tile_id) else {
+                return Ok(None);
+            };
+            if entry.run_length > 0 {
+                return self.read(self.data_offset + entry.offset, entry.length, self.tile_gzip).map(Some);
+            }
+            if !self.leaves.contains_key(&entry.offset) {
+                let leaf = self.directory(self.leaf_offset + entry.offset, entry.length)?;
+                if self.leaves.len() >= MAX_LEAF_DIRECTORIES {
+                    self.leaves.clear();
+                }
+                self.leaves.insert(entry.offset, leaf);
+            }
+            entries = self.leaves[&entry.offset].clone();
+        }
+        Err("PMTiles directories are nested too deep".to_string())
+    }
+
+    fn info(&self) -> Result<TileSetInfo, String> {
+        Ok(TileSetInfo {
+            format: self.format.to_string(),
+            min_zoom: self.min_zoom,
+            max_zoom: self.max_zoom,
+            bounds: Some(self.bounds),
+            attribution: self.attribution.clone(),
+        })
+    }
+}
+
+// entry holding a tile id: the last one starting at or before it, if its run covers it (leaves cover any id after)
+fn find(entries: &[Entry], tile_id: u64) -> Option<Entry> {
+    let entry = *entries.get(entries.partition_point(|e| e.tile_id <= tile_id).checked_sub(1)?)?;
+    (entry.run_length == 0 || tile_id < entry.tile_id + entry.run_length).then_some(entry)
+}
+
+// PMTiles tile id: the tiles of the lower zoom levels, then the position on a Hilbert curve
+fn tile_id(z: u8, x: u32, y: u32) -> u64 {
+    let below: u64 = (0..z).map(|level| 1u64 << (2 * level)).sum();
+    let (mut x, mut y) = (u64::from(x), u64::from(y));
+    let mut d = 0;
+    let mut s = (1u64 << z) / 2;
+    while s > 0 {
+        let rx = u64::from(x & s > 0);
+        let ry = u64::from(y & s > 0);
+        d += s * s * ((3 * rx) ^ ry);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - (x & (s - 1));
+                y = s - 1 - (y & (s - 1));
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    below + d
+}
+
+// zoom, x and y of a tile request, y may have an extension (e.g. 12.png)
+fn parse_tile(z: u8, x: u32, y: &str) -> Result<(u8, u32, u32), AppError> {
+    let y: u32 = y
+        .split('.')
+        .next()
+        .and_then(|y| y.parse().ok())
+        .ok_or_else(|| AppError::BadRequest(format!("invalid tile row {}", y)))?;
+    if z > MAX_ZOOM || x >= 1 << z || y >= 1 << z {
+        return Err(AppError::BadRequest(format!("no tile {}/{}/{}", z, x, y)));
+    }
+    Ok((z, x, y))
+}
+
+// format, zoom levels and bounds of the tile set, null when there is none so maps just go without
+pub async fn tile_info(State(state): State<SharedState>) -> Result<Json<Option<TileSetInfo>>, AppError> {
+    let info = task::spawn_blocking(move || state.tiles.with_source(&state.config.tiles.file, |source| source.info()))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|r| r)
+        .map_err(|e| AppError::Parse("the tile set".to_string(), e))?;
+    Ok(Json(info))
+}
+
+// one tile in the XYZ scheme; tiles outside of the tile set are a plain 404, maps ask for them all the time
+pub async fn tile(
+    State(state): State<SharedState>,
+    Path((z, x, y)): Path<(u8, u32, String)>,
+) -> Result<Response, AppError> {
+    let (z, x, y) = parse_tile(z, x, &y)?;
+    let tiles = state.clone();
+    let tile = task::spawn_blocking(move || {
+        tiles.tiles.with_source(&tiles.config.tiles.file, |source| {
+            let format = source.format().to_string();
+            Ok(source.tile(z, x, y)?.map(|tile| (tile, format)))
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|r| r)
+    .map_err(|e| AppError::Parse("the tile set".to_string(), e))?;
+    match tile {
+        None => Err(AppError::NoData(format!(
+            "no tile set at {}, see tiles.file and the seed-tiles command",
+            state.config.tiles.file.display()
+        ))),
+        Some(None) => Ok(StatusCode::NOT_FOUND.into_response()),
+        Some(Some((tile, format))) => {
+            let format = sniff_format(&tile).unwrap_or(&format);
+            Ok(([(header::CONTENT_TYPE, content_type(format)), (header::CACHE_CONTROL, CACHE_CONTROL)], tile).into_response())
+        }
+    }
+}
+
+// tile column and row of a position at a zoom level
+fn tile_of(z: u8, lon: f64, lat: f64) -> (u32, u32) {
+    let n = f64::from(1u32 << z);
+    let lat = lat.to_radians();
+    let x = ((lon + 180.0) / 360.0 * n).floor();
+    let y = ((1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / std::f64::consts::PI) / 2.0 * n).floor();
+    (x.clamp(0.0, n - 1.0) as u32, y.clamp(0.0, n - 1.0) as u32)
+}
+
+// tiles of one zoom level: the zoom, then the first and last column and row
+type TileRange = (u8, (u32, u32), (u32, u32));
+
+// tiles of a bounding box for every zoom level
+fn seed_ranges(bbox: [f64; 4], min_zoom: u8, max_zoom: u8) -> Vec<TileRange> {
+    let [west, south, east, north] = bbox;
+    (min_zoom..=max_zoom)
+        .map(|z| {
+            let (x0, y0) = tile_of(z, west, north);
+            let (x1, y1) = tile_of(z, east, south);
+            (z, (x0, x1), (y0, y1))
+        })
+        .collect()
+}
+
+const MBTILES_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS metadata (name TEXT, value TEXT);
+    CREATE UNIQUE INDEX IF NOT EXISTS metadata_name ON metadata (name);
+    CREATE TABLE IF NOT EXISTS tiles (zoom_level INTEGER, tile_column INTEGER, tile_row INTEGER, tile_data BLOB);
+    CREATE UNIQUE INDEX IF NOT EXISTS tile_index ON tiles (zoom_level, tile_column, tile_row);
+";
+
+// a tile of the source, None when it does not have it (e.g. outside of its coverage)
+async fn download(client: &Client, url: &str) -> Result<Option<Vec<u8>>, String> {
+    let mut last_error = String::new();
+    for attempt in 0..SEED_ATTEMPTS {
+        if attempt > 0 {
+            tokio::time::sleep(Duration::from_secs(1 << attempt)).await;
+        }
+        let response = match client.get(url).timeout(SEED_TIMEOUT).send().await {
+            Ok(response) => response,
+            Err(e) => {
+                last_error = e.to_string();
+                continue;
+            }
+        };
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            last_error = format!("HTTP {}", status);
+            continue;
+        }
+        match response.bytes().await {
+            Ok(bytes) if sniff_format(&bytes).is_some() => return Ok(Some(bytes.to_vec())),
+            Ok(_) => return Err("not a png, jpg or webp image".to_string()),
+            Err(e) => last_error = e.to_string(),
+        }
+    }
+    Err(last_error)
+}
+
+// downloads the tiles of a bounding box (west, south, east, north) from tiles.source_url into the MBTiles of
+// tiles.file, refresh downloads the tiles already there again
+pub async fn seed(config: &TileConfig, bbox: [f64; 4], min_zoom: u8, max_zoom: u8, max_tiles: u64, refresh: bool) -> Result<(), String> {
+    if config.source_url.is_empty() {
+        return Err("set tiles.source_url to the tile server to download from".to_string());
+    }
+    if config.file.extension().is_some_and(|e| e.eq_ignore_ascii_case("pmtiles")) {
+        return Err("PMTiles archives cannot be seeded, point tiles.file to an .mbtiles file".to_string());
+    }
+    let [west, south, east, north] = bbox;
+    if !(west < east && south < north && west >= -180.0 && east <= 180.0 && south >= -85.0511 && north <= 85.0511) {
+        return Err("the bounding box must be west,south,east,north in degrees, within the web map".to_string());
+    }
+    if min_zoom > max_zoom || max_zoom > MAX_ZOOM {
+        return Err(format!("zoom levels must be between 0 and {}, the minimum first", MAX_ZOOM));
+    }
+    let ranges = seed_ranges(bbox, min_zoom, max_zoom);
+    let total: u64 = ranges.iter().map(|(_, (x0, x1), (y0, y1))| u64::from(x1 - x0 + 1) * u64::from(y1 - y0 + 1)).sum();
+    if total > max_tiles {
+        return Err(format!("the bounding box has {} tiles up to zoom {} (limit {}), use a smaller box, a lower zoom or --max-tiles", total, max_zoom, max_tiles));
+    }
+
+    let connection = Connection::open(&config.file).map_err(|e| format!("{}: {}", config.file.display(), e))?;
+    connection.execute_batch(MBTILES_SCHEMA).map_err(|e| e.to_string())?;
+    let client = Client::builder()
+        .user_agent(concat!("brig-backend/", env!("CARGO_PKG_VERSION"), " (offline map seeding)"))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let delay = Duration::from_millis(config.seed_delay_ms);
+    println!("seeding {} tiles of zoom {} to {} into {}", total, min_zoom, max_zoom, config.file.display());
+
+    let (mut done, mut downloaded, mut skipped, mut unavailable, mut failed) = (0u64, 0u64, 0u64, 0u64, 0u64);
+    let mut format = None;
+    for (z, (x0, x1), (y0, y1)) in ranges {
+        for x in x0..=x1 {
+            for y in y0..=y1 {
+                done += 1;
+                let row = tms_row(z, y);
+                let exists = !refresh
+                    && connection
+                        .query_row(
+                            "SELECT 1 FROM tiles WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+                            (z, x, row),
+                            |_| Ok(()),
+                        )
+                        .optional()
+                        .map_err(|e| e.to_string())?
+                        .is_some();
+                if exists {
+                    skipped += 1;
+                    continue;
+                }
+                let url = config.source_url.replace("{z}", &z.to_string()).replace("{x}", &x.to_string()).replace("{y}", &y.to_string());
+                match download(&client, &url).await {
+                    Ok(None) => unavailable += 1,
+                    Ok(Some(tile)) => {
+                        format = format.or(sniff_format(&tile));
+                        connection
+                            .execute("INSERT OR REPLACE INTO tiles VALUES (?1, ?2, ?3, ?4)", (z, x, row, tile))
+                            .map_err(|e| e.to_string())?;
+                        downloaded += 1;
+                    }
+                    Err(e) => {
+                        eprintln!("tile {}/{}/{}: {}", z, x, y, e);
+                        failed += 1;
+                    }
+                }
+                if done % 100 == 0 {
+                    println!("{} of {} tiles", done, total);
+                }
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+
+    // metadata covering this seed and the previous ones
+    let metadata = mbtiles_metadata(&connection).map_err(|e| e.to_string())?;
+    let zoom = |name: &str| metadata.get(name).and_then(|z| z.parse::<u8>().ok());
+    let bounds = match metadata.get("bounds").and_then(|b| parse_bounds(b)) {
+        Some([w, s, e, n]) => [w.min(west), s.min(south), e.max(east), n.max(north)],
+        None => bbox,
+    };
+    let format = format.map(str::to_string).or_else(|| metadata.get("format").cloned()).unwrap_or_else(|| "png".to_string());
+    let values = [
+        ("name", "brig offline map".to_string()),
+        ("type", "baselayer".to_string()),
+        ("version", "1".to_string()),
+        ("format", format),
+        ("minzoom", zoom("minzoom").map_or(min_zoom, |z| z.min(min_zoom)).to_string()),
+        ("maxzoom", zoom("maxzoom").map_or(max_zoom, |z| z.max(max_zoom)).to_string()),
+        ("bounds", bounds.map(|b| b.to_string()).join(",")),
+        ("attribution", config.attribution.clone()),
+    ];
+    for (name, value) in values {
+        connection
+            .execute("INSERT OR REPLACE INTO metadata (name, value) VALUES (?1, ?2)", (name, value))
+            .map_err(|e| e.to_string())?;
+    }
+    println!(
+        "{} tiles downloaded, {} already there, {} not available from the source, {} failed",
+        downloaded, skipped, unavailable, failed
+    );
+    if failed > 0 {
+        return Err(format!("{} tiles could not be downloaded, run the command again to retry them", failed));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    // tile ids from the examples of the PMTiles spec and its reference implementations
+    #[test]
+    fn tile_ids_follow_the_spec() {
+        assert_eq!(tile_id(0, 0, 0), 0);
+        assert_eq!(tile_id(1, 0, 0), 1);
+        assert_eq!(tile_id(1, 0, 1), 2);
+        assert_eq!(tile_id(1, 1, 1), 3);
+        assert_eq!(tile_id(1, 1, 0), 4);
+        assert_eq!(tile_id(2, 0, 0), 5);
+        assert_eq!(tile_id(3, 0, 0), 21);
+        assert_eq!(tile_id(12, 3423, 1763), 19078479);
+    }
+
+    #[test]
+    fn tile_ids_are_unique_within_and_across_zoom_levels() {
+        let mut ids: Vec<u64> = (0..=4u8).flat_map(|z| (0..1u32 << z).flat_map(move |x| (0..1u32 << z).map(move |y| tile_id(z, x, y)))).collect();
+        ids.sort_unstable();
+        assert_eq!(ids, (0..ids.len() as u64).collect::<Vec<_>>());
+    }
+
+    fn varint(out: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            out.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    // a directory as the spec writes it, offsets right after the previous entry as 0
+    fn encode_directory(entries: &[Entry]) -> Vec<u8> {
+        let mut out = Vec::new();
+        varint(&mut out, entries.len() as u64);
+        let mut last = 0;
+        for entry in entries {
+            varint(&mut out, entry.tile_id - last);
+            last = entry.tile_id;
+        }
+        for entry in entries {
+            varint(&mut out, entry.run_length);
+        }
+        for entry in entries {
+            varint(&mut out, entry.length);
+        }
+        for (i, entry) in entries.iter().enumerate() {
+            let follows = i > 0 && entry.offset == entries[i - 1].offset + entries[i - 1].length;
+            varint(&mut out, if follows { 0 } else { entry.offset + 1 });
+        }
+        out
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn entry(tile_id: u64, offset: u64, length: u64, run_length: u64) -> Entry {
+        Entry { tile_id, offset, length, run_length }
+    }
+
+    // z0, a run of two z1 tiles sharing their data and one more z1 tile in the root, one z2 tile in a leaf
+    fn write_archive(path: &FsPath) {
+        let data = b"t0t12t4t5";
+        let leaf = gzip(&encode_directory(&[entry(5, 7, 2, 1)]));
+        let root = gzip(&encode_directory(&[entry(0, 0, 2, 1), entry(1, 2, 3, 2), entry(4, 5, 2, 1), entry(5, 0, leaf.len() as u64, 0)]));
+        let metadata = gzip(br#"{"attribution": "OpenStreetMap contributors"}"#);
+        let root_offset = 127u64;
+        let metadata_offset = root_offset + root.len() as u64;
+        let leaf_offset = metadata_offset + metadata.len() as u64;
+        let data_offset = leaf_offset + leaf.len() as u64;
+        let mut header = [0u8; 127];
+        header[..7].copy_from_slice(b"PMTiles");
+        header[7] = 3;
+        for (at, value) in [
+            (8, root_offset),
+            (16, root.len() as u64),
+            (24, metadata_offset),
+            (32, metadata.len() as u64),
+            (40, leaf_offset),
+            (48, leaf.len() as u64),
+            (56, data_offset),
+            (64, data.len() as u64),
+        ] {
+            header[at..at + 8].copy_from_slice(&value.to_le_bytes());
+        }
+        // gzip directories, uncompressed PNG tiles, zoom 0 to 2
+        header[97..102].copy_from_slice(&[2, 1, 2, 0, 2]);
+        for (at, degrees) in [(102, -10.5), (106, 40.0), (110, 20.25), (114, 60.0)] {
+            header[at..at + 4].copy_from_slice(&((degrees * 1e7) as i32).to_le_bytes());
+        }
+        let mut file = File::create(path).unwrap();
+        for part in [&header[..], &root, &metadata, &leaf, data] {
+            file.write_all(part).unwrap();
+        }
+    }
+
+    #[test]
+    fn pmtiles_directories_round_trip() {
+        let path = std::env::temp_dir().join(format!("brig-tiles-test-{}.pmtiles", std::process::id()));
+        write_archive(&path);
+        let opened = PmTiles::open(&path);
+        let _ = std::fs::remove_file(&path);
+        let mut pmtiles = opened.unwrap();
+
+        let root: Vec<_> = pmtiles.root.iter().map(|e| (e.tile_id, e.offset, e.run_length)).collect();
+        assert_eq!(root, [(0, 0, 1), (1, 2, 2), (4, 5, 1), (5, 0, 0)]);
+        let info = pmtiles.info().unwrap();
+        assert_eq!((info.format.as_str(), info.min_zoom, info.max_zoom), ("png", 0, 2));
+        assert_eq!(info.bounds, Some([-10.5, 40.0, 20.25, 60.0]));
+        assert_eq!(info.attribution.as_deref(), Some("OpenStreetMap contributors"));
+
+        assert_eq!(pmtiles.tile(0, 0, 0).unwrap().as_deref(), Some(&b"t0"[..]));
+        assert_eq!(pmtiles.tile(1, 0, 0).unwrap().as_deref(), Some(&b"t12"[..]));
+        assert_eq!(pmtiles.tile(1, 0, 1).unwrap().as_deref(), Some(&b"t12"[..]));
+        assert_eq!(pmtiles.tile(1, 1, 1).unwrap(), None);
+        assert_eq!(pmtiles.tile(1, 1, 0).unwrap().as_deref(), Some(&b"t4"[..]));
+        // through the leaf directory
+        assert_eq!(pmtiles.tile(2, 0, 0).unwrap().as_deref(), Some(&b"t5"[..]));
+        assert_eq!(pmtiles.tile(2, 1, 0).unwrap(), None);
+        assert_eq!(pmtiles.leaves.len(), 1);
+    }
+
+    #[test]
+    fn find_respects_run_lengths() {
+        let entries = [entry(0, 0, 2, 1), entry(1, 2, 3, 2), entry(10, 0, 9, 0)];
+        assert_eq!(find(&entries, 2).map(|e| e.tile_id), Some(1));
+        assert!(find(&entries, 3).is_none());
+        // a leaf directory covers every id after it
+        assert_eq!(find(&entries, 1000).map(|e| e.tile_id), Some(10));
+    }
+}
@@ -0,0 +1,678 @@
+use serde::{Deserialize, Serialize};
+
+/*
+Request and response types of the backend HTTP API, shared by the backend and the (wasm) frontend
+so that both stop compiling when the contract changes.
+Only depends on serde, keep it that way so it builds for every target.
+*/
+
+// paths of the backend endpoints
+pub mod endpoints {
+    pub const DATA: &str = "/api/data";
+    pub const LATEST_DATA: &str = "/api/latest_data";
+    pub const LIVE: &str = "/api/live";
+    pub const STATUS: &str = "/api/status";
+    // registry of the configured cameras, a Vec<CameraInfo>
+    pub const CAMERAS: &str = "/api/cameras";
+    // SD space of every camera
+    pub const CAMERA_STATUS: &str = "/api/camera_status";
+    // endpoints of one camera, fill in the id with for_camera
+    pub const CAMERA_HEALTH: &str = "/api/cameras/:camera_id/status";
+    pub const CAMERA_FOLDERS: &str = "/api/cameras/:camera_id/folders";
+    pub const IMAGE_DATA: &str = "/api/cameras/:camera_id/image_data";
+    pub const LAST_CAPTURE: &str = "/api/cameras/:camera_id/last_capture";
+    // two steps: prepare returns a ReformatPlan, confirm must echo its token
+    pub const REFORMAT_PREPARE: &str = "/api/cameras/:camera_id/reformat/prepare";
+    pub const REFORMAT_CONFIRM: &str = "/api/cameras/:camera_id/reformat/confirm";
+    pub const DOWNLOAD_DATA: &str = "/api/download_data";
+    // CTD and GPS values of every capture in a range, CSV or JSON (Vec<CaptureRecord>)
+    pub const CAPTURE_METADATA: &str = "/api/capture_metadata";
+    // geotags of the captures in a range for photogrammetry tools (GeotagParams), and geotagged copies of the
+    // offloaded band files (operators, a GeotagWriteResult)
+    pub const GEOTAGS: &str = "/api/geotags";
+    pub const GEOTAGS_WRITE: &str = "/api/geotags/write";
+    pub const LOGIN: &str = "/api/auth/login";
+    pub const LOGOUT: &str = "/api/auth/logout";
+    pub const SESSION: &str = "/api/auth/session";
+    // copies of camera captures to the archive of the Pi: OFFLOAD lists the last job of every camera
+    pub const OFFLOAD: &str = "/api/offload";
+    pub const OFFLOAD_START: &str = "/api/cameras/:camera_id/offload/start";
+    pub const OFFLOAD_CANCEL: &str = "/api/cameras/:camera_id/offload/cancel";
+    // images built from the bands of one capture, :capture_id as in CaptureId
+    pub const CAPTURE_THUMBNAIL: &str = "/api/captures/:capture_id/thumbnail";
+    pub const CAPTURE_COMPOSITE: &str = "/api/captures/:capture_id/composite";
+    pub const CAPTURE_INDEX: &str = "/api/captures/:capture_id/index";
+    pub const CAPTURE_INDEX_IMAGE: &str = "/api/captures/:capture_id/index/image";
+    // exposure, irradiance and reflectance of every band, a CalibrationInfo
+    pub const CAPTURE_CALIBRATION: &str = "/api/captures/:capture_id/calibration";
+    // captures of a camera in a time range, a page at a time (CapturePage)
+    pub const CAMERA_CAPTURES: &str = "/api/cameras/:camera_id/captures";
+    // newest capture of a camera, as a CaptureInfo
+    pub const LATEST_CAPTURE: &str = "/api/cameras/:camera_id/latest_capture";
+    // offline map tiles of the backend: TILES describes the tile set (Option<TileSetInfo>), TILE is one tile, see tile
+    pub const TILES: &str = "/api/tiles";
+    pub const TILE: &str = "/api/tiles/:z/:x/:y";
+    // start/stop of a systemd service, see service_action
+    pub const SERVICE_ACTION: &str = "/api/:service/:action";
+
+    pub fn service_action(service: &str, action: &str) -> String {
+        format!("/api/{}/{}", service, action)
+    }
+
+    // path of a map tile, x and y in the XYZ scheme of web maps (y from the north)
+    pub fn tile(z: u8, x: u32, y: u32) -> String {
+        format!("/api/tiles/{}/{}/{}", z, x, y)
+    }
+
+    // path of a per camera endpoint, e.g. for_camera(LAST_CAPTURE, "cam1")
+    pub fn for_camera(path: &str, camera: &str) -> String {
+        path.replace(":camera_id", camera)
+    }
+
+    // path of a per capture endpoint, e.g. for_capture(CAPTURE_COMPOSITE, "cam1-SET0001-000-0042")
+    pub fn for_capture(path: &str, capture: &str) -> String {
+        path.replace(":capture_id", capture)
+    }
+}
+
+// name of the server-sent event carrying an RTDataPoint (or null) on /api/live
+pub const LIVE_EVENT: &str = "rtdata";
+
+// one aggregated sample returned by /api/data
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DataPoint {
+    pub time: String,
+    pub measurement: String,
+    pub field: String,
+    pub value: f64,
+    // milliseconds since epoch
+    pub epochtime: i64,
+}
+
+// query parameters of /api/data, every one is optional
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct DataParams {
+    // RFC3339 timestamp or duration relative to now (e.g. -24h), defaults to -24h
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+    // same format as start, defaults to now
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<String>,
+    // comma separated, defaults to idronaut_data
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub measurements: Option<String>,
+    // comma separated, defaults to every field of the selected measurements
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<String>,
+    // aggregation window, defaults to 1m
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window: Option<String>,
+    // mean, min, max, first, last or median, defaults to last
+    #[serde(rename = "fn", skip_serializing_if = "Option::is_none")]
+    pub aggregate: Option<String>,
+}
+
+// last sample of one real time field
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RTValue {
+    pub value: f64,
+    // RFC3339 timestamp of the sample
+    pub time: String,
+    // age of the sample when it was polled
+    pub age_ms: i64,
+    // older than the backend's stale threshold
+    pub stale: bool,
+    // measurement the sample comes from (idronaut_data, gps_data2)
+    pub measurement: String,
+}
+
+// latest real time values, a field is None when it has no recent sample
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct RTDataPoint {
+    pub ph: Option<RTValue>,
+    pub conductivity: Option<RTValue>,
+    pub salinity: Option<RTValue>,
+    pub temperature: Option<RTValue>,
+    pub pressure: Option<RTValue>,
+    pub oxygen_perc: Option<RTValue>,
+    pub oxygen_ppm: Option<RTValue>,
+    pub cog: Option<RTValue>,
+    pub sog: Option<RTValue>,
+    pub latitude: Option<RTValue>,
+    pub longitude: Option<RTValue>,
+    pub depth: Option<RTValue>,
+}
+
+// state of the acquisition services, returned by /api/status
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ServiceStatus {
+    pub idronaut: bool,
+    pub camera_capture: bool,
+}
+
+// query parameters of /api/cameras/:camera_id/image_data
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ImageDataParams {
+    // YYYY-MM-DD
+    pub date: String,
+    pub set: String,
+    pub folder: String,
+    pub img_num: String,
+}
+
+// CTD and GPS values closest to a capture, returned by /api/cameras/:camera_id/image_data
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ImageDataPoint {
+    pub date: String,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+    pub cog: Option<f64>,
+    pub sog: Option<f64>,
+    pub conductivity: Option<f64>,
+    pub depth: Option<f64>,
+    pub oxygen_percentage: Option<f64>,
+    pub oxygen_ppm: Option<f64>,
+    pub ph: Option<f64>,
+    pub pressure: Option<f64>,
+    pub salinity: Option<f64>,
+    pub temperature: Option<f64>,
+}
+
+// query parameters of /api/download_data, datetime-local values (YYYY-MM-DDTHH:MM, UTC)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CsvDataParams {
+    pub start: String,
+    pub end: String,
+}
+
+// query parameters of /api/capture_metadata, every one is optional
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct CaptureMetadataParams {
+    // RFC3339 timestamps or durations relative to now (e.g. -24h), default -24h and now
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<String>,
+    // YYYY-MM-DD, the whole (UTC) day instead of start and stop
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    // only the captures of one camera, or of one set (e.g. SET0003)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub camera: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub set: Option<String>,
+    // csv (default) or json
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    // CTD and GPS samples further than this from a capture are not used, default 1000
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tolerance_ms: Option<u64>,
+}
+
+// query parameters of /api/geotags and body of /api/geotags/write, the captures are selected like CaptureMetadataParams
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct GeotagParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub camera: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub set: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tolerance_ms: Option<u64>,
+    // pix4d (default) or agisoft for a CSV with one row per band file, xmp for a zip of XMP sidecars
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    // height of the cameras above the water line, used as the altitude of every capture (default 0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub altitude_m: Option<f64>,
+}
+
+// outcome of /api/geotags/write
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GeotagWriteResult {
+    // directory of the geotagged copies on the Pi
+    pub dir: String,
+    pub captures: u64,
+    // captures without a GPS sample within the tolerance, not written
+    pub captures_without_position: u64,
+    pub files_written: u64,
+    // band files that are not in the offload archive
+    pub files_missing: u64,
+    pub files_failed: u64,
+    // the first failures, files_failed counts them all
+    pub errors: Vec<String>,
+}
+
+// a capture with the CTD and GPS samples nearest to it, one row of /api/capture_metadata
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CaptureRecord {
+    // CaptureId
+    pub capture: String,
+    pub camera: String,
+    // path of band 1 on the SD card
+    pub file: String,
+    // RFC3339 with milliseconds
+    pub time: String,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+    pub cog: Option<f64>,
+    pub sog: Option<f64>,
+    pub depth: Option<f64>,
+    pub conductivity: Option<f64>,
+    pub oxygen_percentage: Option<f64>,
+    pub oxygen_ppm: Option<f64>,
+    pub ph: Option<f64>,
+    pub pressure: Option<f64>,
+    pub salinity: Option<f64>,
+    pub temperature: Option<f64>,
+}
+
+// one spectral band of a camera
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BandInfo {
+    // 1 based, the N of the IMG_XXXX_N.tif file of the band
+    pub band: u8,
+    pub name: String,
+    // center wavelength and bandwidth (FWHM)
+    pub wavelength_nm: f64,
+    pub bandwidth_nm: f64,
+}
+
+// a configured camera, returned by /api/cameras
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CameraInfo {
+    // id used in the endpoints and in the micasense_data "camera" tag, e.g. cam1
+    pub id: String,
+    pub name: String,
+    pub model: String,
+    pub bands: Vec<BandInfo>,
+}
+
+// outcome of a request to one camera when several are polled at once
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CameraReach {
+    Ok,
+    // no answer within cameras.<id>.timeout_ms
+    Timeout,
+    // connection refused or dropped, e.g. the camera is off
+    Unreachable,
+    // the camera answered, but with an error or something unreadable
+    Error,
+}
+
+// SD space of a camera in GB, returned for every camera by /api/camera_status;
+// the sizes are only set when state is Ok, message explains the other states
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CameraSpace {
+    pub camera: String,
+    pub state: CameraReach,
+    pub free_gb: Option<f64>,
+    pub total_gb: Option<f64>,
+    pub message: Option<String>,
+}
+
+// one satellite tracked by the GPS of a RedEdge, azimuth and elevation in degrees, cno in dB-Hz
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SvInfo {
+    pub azimuth: Option<f64>,
+    pub channel: Option<i32>,
+    pub cno: Option<i32>,
+    pub diff_flag: Option<bool>,
+    pub elevation: Option<f64>,
+    pub orbit_info: Option<bool>,
+    pub orbit_is_eph: Option<bool>,
+    pub quality: Option<i32>,
+    pub sv_healthy: Option<bool>,
+    pub svid: Option<i32>,
+    // used in the position fix
+    pub used_flag: Option<bool>,
+}
+
+// status page of a RedEdge camera as returned by its /status, passed through by /api/cameras/:camera_id/status;
+// every field is optional since firmware versions differ in what they report
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RedEdgeStatus {
+    pub sd_gb_free: Option<f64>,
+    pub sd_gb_total: Option<f64>,
+    pub sd_gb_type: Option<String>,
+    pub sd_warn: Option<bool>,
+    pub sd_status: Option<String>,
+    pub bus_volts: Option<f64>,
+    pub gps_used_sats: Option<i32>,
+    pub gps_vis_sats: Option<i32>,
+    pub gps_warn: Option<bool>,
+    pub gps_lat: Option<f64>,
+    pub gps_lon: Option<f64>,
+    // fix type, e.g. "3D"
+    pub gps_type: Option<String>,
+    pub course: Option<f64>,
+    pub alt_agl: Option<f64>,
+    pub alt_msl: Option<f64>,
+    // position accuracy in meters
+    pub p_acc: Option<f64>,
+    pub utc_time: Option<String>,
+    pub vel_2d: Option<f64>,
+    pub sv_info: Option<Vec<SvInfo>>,
+    pub auto_cap_active: Option<bool>,
+    pub dls_status: Option<String>,
+    pub gps_time: Option<String>,
+    pub utc_time_valid: Option<bool>,
+    pub time_source: Option<String>,
+}
+
+// query parameters of /api/cameras/:camera_id/folders
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CameraFoldersParams {
+    // YYYY-MM-DD
+    pub date: String,
+}
+
+// what a reformat would destroy, returned by the prepare step together with the token to confirm it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ReformatPlan {
+    pub camera: String,
+    pub token: String,
+    // seconds left to confirm
+    pub expires_in: u64,
+    pub sd_gb_free: Option<f64>,
+    pub sd_gb_total: Option<f64>,
+    // captures recorded in Influx for the camera since its last reformat (RFC3339, None if never reformatted)
+    pub captures: u64,
+    pub since: Option<String>,
+    // captures among them with a band missing from the offload archive, and the first few of their paths
+    pub not_archived: u64,
+    pub not_archived_examples: Vec<String>,
+}
+
+// body of the confirm step of a reformat
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ReformatConfirm {
+    pub token: String,
+    // erase even though the plan reported captures missing from the archive
+    #[serde(default)]
+    pub override_backup: bool,
+}
+
+// answer of the camera to a reformat, returned as is by the confirm step
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ReformatResponse {
+    pub message: String,
+    pub reformat_status: String,
+}
+
+// query parameters of /api/cameras/:camera_id/last_capture and /api/captures/:capture_id/thumbnail
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CaptureParams {
+    // 1 to the number of bands of the camera
+    pub band: String,
+}
+
+/*
+Identifier of a capture in the API, "<camera>-<set>-<folder>-<number>", e.g. cam1-SET0001-000-0042 for the files
+/files/SET0001/000/IMG_0042_<band>.tif of cam1 logged in micasense_data. Camera ids may contain '-', the other parts
+are split off from the right.
+*/
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CaptureId {
+    pub camera: String,
+    // SETXXXX
+    pub set: String,
+    // XXX
+    pub folder: String,
+    // XXXX
+    pub number: String,
+}
+
+impl CaptureId {
+    pub fn parse(id: &str) -> Option<CaptureId> {
+        let mut parts = id.rsplitn(4, '-');
+        let (number, folder, set, camera) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        let digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+        let valid = !camera.is_empty() && set.strip_prefix("SET").is_some_and(digits) && digits(folder) && digits(number);
+        valid.then(|| CaptureId {
+            camera: camera.to_string(),
+            set: set.to_string(),
+            folder: folder.to_string(),
+            number: number.to_string(),
+        })
+    }
+
+    // from the micasense_data path of any band, e.g. /files/SET0001/000/IMG_0042_1.tif
+    pub fn from_path(camera: &str, path: &str) -> Option<CaptureId> {
+        let mut parts = path.rsplit('/');
+        let (file, folder, set) = (parts.next()?, parts.next()?, parts.next()?);
+        let number = file.strip_prefix("IMG_")?.split('_').next()?;
+        CaptureId::parse(&format!("{}-{}-{}-{}", camera, set, folder, number))
+    }
+
+    // path of a band file on the SD card, as logged in micasense_data for band 1
+    pub fn band_path(&self, band: u8) -> String {
+        format!("/files/{}/{}/IMG_{}_{}.tif", self.set, self.folder, self.number, band)
+    }
+}
+
+impl std::fmt::Display for CaptureId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}-{}-{}", self.camera, self.set, self.folder, self.number)
+    }
+}
+
+// query parameters of /api/captures/:capture_id/composite
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CompositeParams {
+    // comma separated bands for red, green and blue, by number or name (e.g. "5,3,2" or "nir,red,green"),
+    // a single band gives a grayscale image
+    pub bands: String,
+    // percentiles mapped to black and white, every band is stretched on its own (default 2 and 98)
+    pub low: Option<f64>,
+    pub high: Option<f64>,
+    // calibrate the bands to reflectance first (see CalibrationInfo), so their stretch is comparable between captures
+    #[serde(default)]
+    pub reflectance: bool,
+}
+
+// a capture logged in micasense_data
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CaptureInfo {
+    // CaptureId
+    pub id: String,
+    pub camera: String,
+    // RFC3339
+    pub time: String,
+    // path of band 1 on the SD card
+    pub path: String,
+}
+
+// query parameters of /api/cameras/:camera_id/captures
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct CaptureListParams {
+    // RFC3339 timestamps or durations relative to now (e.g. -24h), default -24h and now
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<String>,
+    // first page is 0
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    // default 24, at most 200
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub per_page: Option<u32>,
+}
+
+// one page of the captures of a camera, oldest first
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CapturePage {
+    pub captures: Vec<CaptureInfo>,
+    // captures in the whole range
+    pub total: u64,
+    pub page: u32,
+    pub per_page: u32,
+}
+
+// query parameters of /api/captures/:capture_id/index and .../index/image
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IndexParams {
+    // ndvi, ndwi, ndre or custom
+    pub index: String,
+    // bands of a custom (a-b)/(a+b) index, by number or name like CompositeParams
+    pub a: Option<String>,
+    pub b: Option<String>,
+    // compute the index on reflectance instead of raw sensor values
+    #[serde(default)]
+    pub reflectance: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Percentile {
+    pub percentile: f64,
+    pub value: f64,
+}
+
+// colour of the colour map at an index value, "#rrggbb"
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LegendStop {
+    pub value: f64,
+    pub color: String,
+}
+
+// summary of an index over the pixels of a capture, returned by /api/captures/:capture_id/index
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IndexStats {
+    pub capture: String,
+    // e.g. "NDVI"
+    pub index: String,
+    // e.g. "(NIR - Red) / (NIR + Red)"
+    pub formula: String,
+    pub a_band: u8,
+    pub b_band: u8,
+    // computed on reflectance rather than raw values
+    pub reflectance: bool,
+    // pixels where a + b > 0, the others are transparent in the image
+    pub valid_pixels: u64,
+    pub total_pixels: u64,
+    pub mean: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub percentiles: Vec<Percentile>,
+    // colour map of the image, from -1 to 1
+    pub legend: Vec<LegendStop>,
+}
+
+// radiometric calibration of one band of a capture
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BandCalibration {
+    pub band: u8,
+    pub exposure_s: f64,
+    // ISO / 100
+    pub gain: f64,
+    pub black_level: f64,
+    // W/m^2/nm, None when there is neither a panel nor DLS data
+    pub irradiance: Option<f64>,
+    // panel, dls_horizontal or dls_spectral
+    pub irradiance_source: Option<String>,
+    // over the whole band
+    pub mean_reflectance: Option<f64>,
+}
+
+// returned by /api/captures/:capture_id/calibration
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CalibrationInfo {
+    pub capture: String,
+    pub bands: Vec<BandCalibration>,
+}
+
+// body of /api/cameras/:camera_id/offload/start
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OffloadRequest {
+    // RFC3339 timestamps or durations relative to now (e.g. -24h), selecting captures by their micasense_data time
+    pub start: String,
+    pub stop: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OffloadState {
+    Running,
+    // every file is in the archive
+    Done,
+    // stopped by an operator, the files copied so far are kept
+    Cancelled,
+    // finished, but some files could not be downloaded or verified
+    Failed,
+}
+
+// progress of the last offload job of a camera, returned by /api/offload (one per camera), start and cancel
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OffloadStatus {
+    pub camera: String,
+    pub state: OffloadState,
+    pub user: String,
+    // RFC3339, the range of the request and when the job started
+    pub start: String,
+    pub stop: String,
+    pub started: String,
+    pub captures_total: u64,
+    pub captures_done: u64,
+    // band files copied by this job, and those already in the archive from an earlier one
+    pub files_downloaded: u64,
+    pub files_skipped: u64,
+    pub files_failed: u64,
+    pub bytes_downloaded: u64,
+    // first failures, "<path>: <error>"
+    pub errors: Vec<String>,
+}
+
+// JSON body of every error response of the backend
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ErrorBody {
+    // stable machine readable code, e.g. "influx_unreachable", "camera_timeout", "no_data"
+    pub code: String,
+    // human readable explanation, fit to be shown in the UI
+    pub message: String,
+    // underlying error, if any (Influx, camera or parser message)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cause: Option<String>,
+}
+
+// what a user may do: viewers read data, operators can also start/stop services and reformat cameras
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Operator,
+}
+
+// body of /api/auth/login
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+// logged in user, returned by /api/auth/login and (or null) by /api/auth/session
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SessionInfo {
+    pub username: String,
+    pub role: Role,
+    // seconds until the session expires
+    pub expires_in: u64,
+}
+
+// tile set served on /api/tiles, read from its MBTiles metadata or PMTiles header
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TileSetInfo {
+    // png, jpg or webp
+    pub format: String,
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+    // west, south, east, north in degrees, when the tile set has them
+    pub bounds: Option<[f64; 4]>,
+    // to show with the map, as required by most tile providers
+    pub attribution: Option<String>,
+}
@@ -0,0 +1,357 @@
+use brig_types::{
+    endpoints, CameraFoldersParams, CameraInfo, CaptureInfo, CaptureListParams, CaptureMetadataParams, CapturePage, CaptureRecord, ErrorBody, GeotagParams, GeotagWriteResult, IndexParams, IndexStats, LoginRequest, SessionInfo, CameraSpace, CaptureParams, CsvDataParams, DataParams, DataPoint,
+    ImageDataParams, ImageDataPoint, RedEdgeStatus, OffloadRequest, OffloadStatus, ReformatConfirm, ReformatPlan, ReformatResponse, ServiceStatus, TileSetInfo,
+};
+use gloo::timers::future::TimeoutFuture;
+use leptos::web_sys;
+use reqwest::{Client, RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::util;
+
+/*
+Typed client of the backend API, every request of the UI goes through here.
+At startup the frontend reads /config.json next to index.html, its "api_base" (e.g. "http://192.168.2.9:3000")
+is prepended to every API path. If the file is missing or api_base is empty, the API is expected on the same origin
+the UI is served from (the nginx container proxies /api to the backend).
+Requests share one client and one timeout and carry the session cookie (also when the API is on another origin),
+reads are retried on network errors and gateway failures, and every failure that reaches the caller is also shown
+as an error toast.
+*/
+
+static API_BASE: OnceLock<String> = OnceLock::new();
+
+thread_local! {
+    static CLIENT: RefCell<Option<Client>> = const { RefCell::new(None) };
+}
+
+// per attempt, the backend answers in well under a second unless a camera or Influx hangs
+const TIMEOUT: Duration = Duration::from_secs(10);
+// a download of a long range can take a while to build on the Pi
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+// attempts of a read request, with RETRY_DELAY_MS, 2*RETRY_DELAY_MS, ... in between
+const ATTEMPTS: u32 = 3;
+const RETRY_DELAY_MS: u32 = 500;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiError {
+    // backend not reachable, connection dropped or no answer within the timeout
+    Network(String),
+    // backend answered with a non-success status, with the message of its ErrorBody (or the raw body)
+    Http(u16, String),
+    // answer did not match the expected type
+    Decode(String),
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Network(e) => write!(f, "Backend not reachable: {}", e),
+            ApiError::Http(status, body) if body.is_empty() => write!(f, "Server error: HTTP {}", status),
+            ApiError::Http(status, body) => write!(f, "Server error: HTTP {} ({})", status, body),
+            ApiError::Decode(e) => write!(f, "Unexpected answer from the backend: {}", e),
+        }
+    }
+}
+
+impl ApiError {
+    // worth another attempt: the request may not have reached the backend, or a proxy gave up on it
+    fn is_transient(&self) -> bool {
+        matches!(self, ApiError::Network(_) | ApiError::Http(502..=504, _))
+    }
+}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            ApiError::Network("request timed out".to_string())
+        } else if e.is_decode() {
+            ApiError::Decode(e.to_string())
+        } else {
+            ApiError::Network(e.to_string())
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct RuntimeConfig {
+    #[serde(default)]
+    api_base: String,
+}
+
+// origin the page was loaded from, e.g. http://192.168.2.9:9080
+fn page_origin() -> String {
+    web_sys::window()
+        .and_then(|w| w.location().origin().ok())
+        .unwrap_or_default()
+}
+
+// reads /config.json and fixes the API base for the rest of the session, must run before mounting the app
+pub async fn init() {
+    let origin = page_origin();
+    let config = match client().get(format!("{}/config.json", origin)).timeout(TIMEOUT).send().await {
+        Ok(response) if response.status().is_success() => response.json::<RuntimeConfig>().await.unwrap_or_default(),
+        _ => RuntimeConfig::default(),
+    };
+    let base = config.api_base.trim().trim_end_matches('/').to_string();
+    let base = if base.is_empty() { origin } else { base };
+    log::info!("API base: {}", base);
+    let _ = API_BASE.set(base);
+}
+
+// base address of the backend, same origin until init has run
+pub fn base() -> &'static str {
+    API_BASE.get_or_init(page_origin)
+}
+
+// full url of an API path, e.g. url(endpoints::STATUS)
+pub fn url(path: &str) -> String {
+    format!("{}{}", base(), path)
+}
+
+fn client() -> Client {
+    CLIENT.with(|c| c.borrow_mut().get_or_insert_with(Client::new).clone())
+}
+
+// sends a request built by make, retrying transient failures when retry is set,
+// non-success statuses are turned into ApiError::Http and every final error is toasted
+async fn send(make: impl Fn(&Client) -> RequestBuilder, timeout: Duration, retry: bool) -> Result<Response, ApiError> {
+    let client = client();
+    let attempts = if retry { ATTEMPTS } else { 1 };
+    let mut attempt = 1;
+    loop {
+        let result = match make(&client).timeout(timeout).fetch_credentials_include().send().await {
+            Ok(response) if response.status().is_success() => Ok(response),
+            Ok(response) => {
+                let status = response.status().as_u16();
+                let body = response.text().await.unwrap_or_default();
+                let message = match serde_json::from_str::<ErrorBody>(&body) {
+                    Ok(error) => error.message,
+                    Err(_) => body.trim().to_string(),
+                };
+                Err(ApiError::Http(status, message))
+            }
+            Err(e) => Err(ApiError::from(e)),
+        };
+        match result {
+            Err(e) if attempt < attempts && e.is_transient() => {
+                log::warn!("attempt {} failed, retrying: {}", attempt, e);
+                TimeoutFuture::new(RETRY_DELAY_MS * attempt).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(report(e)),
+            Ok(response) => return Ok(response),
+        }
+    }
+}
+
+// logs and toasts an error on its way to the caller
+fn report(e: ApiError) -> ApiError {
+    log::error!("{}", e);
+    util::toast_error(e.to_string());
+    e
+}
+
+async fn decode<T: DeserializeOwned>(response: Response) -> Result<T, ApiError> {
+    response.json::<T>().await.map_err(|e| report(ApiError::Decode(e.to_string())))
+}
+
+async fn get_json<T: DeserializeOwned, Q: Serialize>(path: &str, query: &Q) -> Result<T, ApiError> {
+    let url = url(path);
+    let response = send(|c| c.get(&url).query(query), TIMEOUT, true).await?;
+    decode(response).await
+}
+
+async fn get_bytes<Q: Serialize>(path: &str, query: &Q, timeout: Duration) -> Result<Vec<u8>, ApiError> {
+    let url = url(path);
+    let response = send(|c| c.get(&url).query(query), timeout, true).await?;
+    response.bytes().await.map(|b| b.to_vec()).map_err(|e| report(ApiError::from(e)))
+}
+
+// starts a session, the backend sets the cookie
+pub async fn login(request: &LoginRequest) -> Result<SessionInfo, ApiError> {
+    let url = url(endpoints::LOGIN);
+    let response = send(|c| c.post(&url).json(request), TIMEOUT, false).await?;
+    decode(response).await
+}
+
+pub async fn logout() -> Result<(), ApiError> {
+    let url = url(endpoints::LOGOUT);
+    send(|c| c.post(&url), TIMEOUT, false).await.map(|_| ())
+}
+
+// logged in user, None when browsing anonymously
+pub async fn session() -> Result<Option<SessionInfo>, ApiError> {
+    get_json(endpoints::SESSION, &()).await
+}
+
+// sensor data for the charts, sorted by time
+pub async fn data(params: &DataParams) -> Result<Vec<DataPoint>, ApiError> {
+    let mut points: Vec<DataPoint> = get_json(endpoints::DATA, params).await?;
+    points.sort_by_key(|p| p.epochtime);
+    Ok(points)
+}
+
+// raw Influx CSV of a time range
+pub async fn csv_data(params: &CsvDataParams) -> Result<Vec<u8>, ApiError> {
+    get_bytes(endpoints::DOWNLOAD_DATA, params, DOWNLOAD_TIMEOUT).await
+}
+
+// every capture of a range with its CTD and GPS values, as CSV or JSON
+pub async fn capture_metadata(params: &CaptureMetadataParams) -> Result<Vec<u8>, ApiError> {
+    get_bytes(endpoints::CAPTURE_METADATA, params, DOWNLOAD_TIMEOUT).await
+}
+
+// captures of a range with their positions, for the map
+pub async fn capture_records(params: &CaptureMetadataParams) -> Result<Vec<CaptureRecord>, ApiError> {
+    let params = CaptureMetadataParams { format: Some("json".to_string()), ..params.clone() };
+    get_json(endpoints::CAPTURE_METADATA, &params).await
+}
+
+// offline map tiles of the backend, None when it has none
+pub async fn tile_set() -> Result<Option<TileSetInfo>, ApiError> {
+    get_json(endpoints::TILES, &()).await
+}
+
+// url of a map tile, for an svg image
+pub fn tile_url(z: u8, x: u32, y: u32) -> String {
+    url(&endpoints::tile(z, x, y))
+}
+
+// geotags of the captures of a range, as a Pix4D or Agisoft CSV or a zip of XMP sidecars
+pub async fn geotags(params: &GeotagParams) -> Result<Vec<u8>, ApiError> {
+    get_bytes(endpoints::GEOTAGS, params, DOWNLOAD_TIMEOUT).await
+}
+
+// writes geotagged copies of the archived band files of a range, never retried
+pub async fn geotags_write(params: &GeotagParams) -> Result<GeotagWriteResult, ApiError> {
+    let url = url(endpoints::GEOTAGS_WRITE);
+    let response = send(|c| c.post(&url).json(params), DOWNLOAD_TIMEOUT, false).await?;
+    decode(response).await
+}
+
+// running state of the ASV services
+pub async fn status() -> Result<ServiceStatus, ApiError> {
+    get_json(endpoints::STATUS, &()).await
+}
+
+// starts or stops a service, never retried
+pub async fn service_action(service: &str, action: &str) -> Result<(), ApiError> {
+    let url = url(&endpoints::service_action(service, action));
+    send(|c| c.post(&url), TIMEOUT, false).await.map(|_| ())
+}
+
+// cameras configured on the backend, with their bands
+pub async fn cameras() -> Result<Vec<CameraInfo>, ApiError> {
+    get_json(endpoints::CAMERAS, &()).await
+}
+
+// SD card space of every camera
+pub async fn camera_status() -> Result<Vec<CameraSpace>, ApiError> {
+    get_json(endpoints::CAMERA_STATUS, &()).await
+}
+
+// full status of one camera
+pub async fn camera_health(camera: &str) -> Result<RedEdgeStatus, ApiError> {
+    get_json(&endpoints::for_camera(endpoints::CAMERA_HEALTH, camera), &()).await
+}
+
+// "set/folder" entries captured by a camera on a date
+pub async fn camera_folders(camera: &str, params: &CameraFoldersParams) -> Result<Vec<String>, ApiError> {
+    get_json(&endpoints::for_camera(endpoints::CAMERA_FOLDERS, camera), params).await
+}
+
+// CTD and GPS values at the time of a capture
+pub async fn image_data(camera: &str, params: &ImageDataParams) -> Result<ImageDataPoint, ApiError> {
+    get_json(&endpoints::for_camera(endpoints::IMAGE_DATA, camera), params).await
+}
+
+// asks what a reformat of a camera would erase, and for the token to confirm it
+pub async fn reformat_prepare(camera: &str) -> Result<ReformatPlan, ApiError> {
+    let url = url(&endpoints::for_camera(endpoints::REFORMAT_PREPARE, camera));
+    let response = send(|c| c.post(&url), TIMEOUT, false).await?;
+    decode(response).await
+}
+
+// erases the SD card of a camera, never retried
+pub async fn reformat_confirm(camera: &str, params: &ReformatConfirm) -> Result<ReformatResponse, ApiError> {
+    let url = url(&endpoints::for_camera(endpoints::REFORMAT_CONFIRM, camera));
+    let response = send(|c| c.post(&url).json(params), TIMEOUT, false).await?;
+    decode(response).await
+}
+
+// last offload job of every camera
+pub async fn offloads() -> Result<Vec<OffloadStatus>, ApiError> {
+    get_json(endpoints::OFFLOAD, &()).await
+}
+
+// starts copying the captures of a range to the archive of the Pi
+pub async fn offload_start(camera: &str, request: &OffloadRequest) -> Result<OffloadStatus, ApiError> {
+    let url = url(&endpoints::for_camera(endpoints::OFFLOAD_START, camera));
+    let response = send(|c| c.post(&url).json(request), TIMEOUT, false).await?;
+    decode(response).await
+}
+
+pub async fn offload_cancel(camera: &str) -> Result<OffloadStatus, ApiError> {
+    let url = url(&endpoints::for_camera(endpoints::OFFLOAD_CANCEL, camera));
+    let response = send(|c| c.post(&url), TIMEOUT, false).await?;
+    decode(response).await
+}
+
+// jpeg of the latest capture of a camera band
+pub async fn last_capture(camera: &str, params: &CaptureParams) -> Result<Vec<u8>, ApiError> {
+    get_bytes(&endpoints::for_camera(endpoints::LAST_CAPTURE, camera), params, TIMEOUT).await
+}
+
+// same image as last_capture, as a url for <img> tags
+pub fn last_capture_url(camera: &str, band: u8) -> String {
+    format!("{}?band={}", url(&endpoints::for_camera(endpoints::LAST_CAPTURE, camera)), band)
+}
+
+// one page of the captures of a camera in a range, oldest first
+pub async fn camera_captures(camera: &str, params: &CaptureListParams) -> Result<CapturePage, ApiError> {
+    get_json(&endpoints::for_camera(endpoints::CAMERA_CAPTURES, camera), params).await
+}
+
+// cached thumbnail of one band of a capture, as a url for <img> tags
+pub fn capture_thumbnail_url(capture: &str, band: u8) -> String {
+    format!("{}?band={}", url(&endpoints::for_capture(endpoints::CAPTURE_THUMBNAIL, capture)), band)
+}
+
+// newest capture of a camera in the last week
+pub async fn latest_capture(camera: &str) -> Result<CaptureInfo, ApiError> {
+    get_json(&endpoints::for_camera(endpoints::LATEST_CAPTURE, camera), &()).await
+}
+
+// summary statistics of a spectral index of a capture
+pub async fn capture_index(capture: &str, params: &IndexParams) -> Result<IndexStats, ApiError> {
+    get_json(&endpoints::for_capture(endpoints::CAPTURE_INDEX, capture), params).await
+}
+
+// colour mapped PNG of the same index, as a url for <img> tags
+pub fn capture_index_image_url(capture: &str, params: &IndexParams) -> String {
+    let mut query = format!("index={}", encode(&params.index));
+    for (name, band) in [("a", &params.a), ("b", &params.b)] {
+        if let Some(band) = band {
+            query.push_str(&format!("&{}={}", name, encode(band)));
+        }
+    }
+    if params.reflectance {
+        query.push_str("&reflectance=true");
+    }
+    format!("{}?{}", url(&endpoints::for_capture(endpoints::CAPTURE_INDEX_IMAGE, capture)), query)
+}
+
+fn encode(value: &str) -> String {
+    String::from(js_sys::encode_uri_component(value))
+}
+
+// server-sent events stream of real time data, see brig_types::LIVE_EVENT
+pub fn live_url() -> String {
+    url(endpoints::LIVE)
+}
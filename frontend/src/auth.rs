@@ -0,0 +1,124 @@
+use brig_types::{LoginRequest, Role, SessionInfo};
+use leptos::*;
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+use leptos_router::components::A;
+use leptos_router::hooks::use_navigate;
+
+use crate::api;
+
+/*
+Login state of the UI.
+App provides the current session as context, loaded from the backend on startup; components read it with
+use_session and disable the buttons the backend would refuse anyway (services, reformat) for non operators.
+*/
+
+#[derive(Clone, Copy)]
+pub struct SessionContext(pub RwSignal<Option<SessionInfo>>);
+
+// creates the session context and asks the backend who we are (the cookie survives page reloads)
+pub fn provide_session() {
+    let session = RwSignal::new(None::<SessionInfo>);
+    provide_context(SessionContext(session));
+    spawn_local(async move {
+        if let Ok(current) = api::session().await {
+            session.set(current);
+        }
+    });
+}
+
+pub fn use_session() -> RwSignal<Option<SessionInfo>> {
+    expect_context::<SessionContext>().0
+}
+
+// true while an operator is logged in
+pub fn is_operator() -> Signal<bool> {
+    let session = use_session();
+    Signal::derive(move || session.get().is_some_and(|s| s.role >= Role::Operator))
+}
+
+// tooltip for buttons disabled by is_operator
+pub const OPERATOR_ONLY: &str = "Log in as an operator to use this";
+
+/*
+Login form, sends the user back to the home page once logged in.
+*/
+#[component]
+pub fn Login() -> impl IntoView {
+    let session = use_session();
+    let username = RwSignal::new(String::new());
+    let password = RwSignal::new(String::new());
+    let status_message = RwSignal::new(String::new());
+    let navigate = use_navigate();
+
+    let submit = move |ev: ev::SubmitEvent| {
+        ev.prevent_default();
+        let request = LoginRequest { username: username.get(), password: password.get() };
+        status_message.set("Logging in...".to_string());
+        let navigate = navigate.clone();
+        spawn_local(async move {
+            match api::login(&request).await {
+                Ok(info) => {
+                    status_message.set(String::new());
+                    password.set(String::new());
+                    session.set(Some(info));
+                    navigate("/", Default::default());
+                }
+                Err(e) => status_message.set(e.to_string()),
+            }
+        });
+    };
+
+    view! {
+        <div class="component-container login">
+            <h2>"Login"</h2>
+            <form on:submit=submit>
+                <div class="form-group">
+                    <label>
+                        "Username:"
+                        <input type="text" autocomplete="username"
+                            prop:value=move || username.get()
+                            on:input=move |e| username.set(event_target_value(&e)) />
+                    </label>
+                </div>
+                <div class="form-group">
+                    <label>
+                        "Password:"
+                        <input type="password" autocomplete="current-password"
+                            prop:value=move || password.get()
+                            on:input=move |e| password.set(event_target_value(&e)) />
+                    </label>
+                </div>
+                <div class="form-group">
+                    <button type="submit">"Login"</button>
+                    <p class="status-message">{move || status_message.get()}</p>
+                </div>
+            </form>
+        </div>
+    }
+}
+
+// user name, role and login/logout link, shown in the header
+#[component]
+pub fn SessionBadge() -> impl IntoView {
+    let session = use_session();
+    let logout = move |_| {
+        spawn_local(async move {
+            if api::logout().await.is_ok() {
+                session.set(None);
+            }
+        });
+    };
+    view! {
+        {move || match session.get() {
+            Some(info) => view! {
+                <p class="session-badge">
+                    {format!("{} ({})", info.username, if info.role >= Role::Operator { "operator" } else { "viewer" })}
+                    " "
+                    <button class="logout-button" on:click=logout>"Logout"</button>
+                </p>
+            }.into_any(),
+            None => view! { <p><A href="/Login/">"Login"</A></p> }.into_any(),
+        }}
+    }
+}
@@ -1,29 +1,70 @@
-use base64::{Engine as _, engine::general_purpose};
 use leptos::*;
-use reqwest::Client;
 use leptos::prelude::*;
-use serde::{Deserialize, Serialize};
+use brig_types::{BandInfo, CameraInfo, CameraReach, CameraSpace, CaptureParams, IndexParams, IndexStats, RedEdgeStatus, ReformatConfirm, ReformatPlan, SvInfo};
+use futures::future::{AbortHandle, Abortable};
+use gloo::timers::future::TimeoutFuture;
 use leptos::task::spawn_local;
+use crate::api;
 
 /*
 Camera utilities and info visualization
  */
 
+/*
+Camera registry of the backend (/api/cameras).
+App provides it as context, loaded once on startup; every camera selector, panel and band label is built from it.
+*/
+#[derive(Clone, Copy)]
+pub struct CamerasContext(pub RwSignal<Vec<CameraInfo>>);
+
+pub fn provide_cameras() {
+    let cameras = RwSignal::new(Vec::<CameraInfo>::new());
+    provide_context(CamerasContext(cameras));
+    spawn_local(async move {
+        if let Ok(list) = api::cameras().await {
+            cameras.set(list);
+        }
+    });
+}
+
+pub fn use_cameras() -> RwSignal<Vec<CameraInfo>> {
+    expect_context::<CamerasContext>().0
+}
+
+// display name of a camera id, the id itself when the camera is not (yet) known
+pub fn camera_name(cameras: &[CameraInfo], id: &str) -> String {
+    cameras.iter().find(|c| c.id == id).map(|c| c.name.clone()).unwrap_or_else(|| id.to_string())
+}
+
+// e.g. "Blue (475±32 nm)"
+pub fn band_label(band: &BandInfo) -> String {
+    format!("{} ({}±{} nm)", band.name, band.wavelength_nm, band.bandwidth_nm)
+}
+
+// <select> of the configured cameras bound to camera, empty until one is chosen
+#[component]
+pub fn CameraSelect(camera: RwSignal<String>) -> impl IntoView {
+    let cameras = use_cameras();
+    view! {
+        <select on:change=move |ev| camera.set(event_target_value(&ev))>
+            <option value="" selected=move || camera.get().is_empty()>"-- Choose a Camera --"</option>
+            {move || cameras.get().into_iter().map(|c| {
+                let id = c.id.clone();
+                view! { <option value=c.id selected=move || camera.get() == id>{c.name}</option> }
+            }).collect_view()}
+        </select>
+    }
+}
+
 pub fn camera_page() -> impl IntoView {
     view! {
         <CameraStatus/> // Status of the cameras (space available)
+        <CameraHealth/> // Power, GPS and warnings of each camera
         <Reformat/> // Window for reformatting SD cards
         <ImageFetch/> // Fetcher of latest capture
+        <IndexProducts/> // NDVI, NDWI, NDRE of a capture
     }
 }
-#[derive(Serialize, Clone, Deserialize, Debug)]
-struct CameraSpace {
-    cam1_free: f64,
-    cam1_total: f64,
-    cam2_free: f64,
-    cam2_total: f64,
-}
-
 
 /* 
 This component handles the status of the micasense cameras,
@@ -32,9 +73,9 @@ it simply uses the RedEdge APIs to fetch the total and the free space and shows
 #[component]
 pub fn CameraStatus() -> impl IntoView {
     let status_message = RwSignal::new(String::new());
-    let camera_data = RwSignal::new(None::<CameraSpace>);
+    let camera_data = RwSignal::new(None::<Vec<CameraSpace>>);
+    let cameras = use_cameras();
     let fetched = RwSignal::new(false); // for running once
-    let client = Client::new();
 
     // Effect for the asynchronous HTTP API call
     Effect::new(move |_| {
@@ -45,26 +86,13 @@ pub fn CameraStatus() -> impl IntoView {
 
         status_message.set("Loading camera data...".to_string());
 
-        let cl = client.clone();
         spawn_local(async move {
-            let url = "http://192.168.2.9:3000/api/camera_status";
-            match cl.get(url).send().await {
-                Ok(res) => {
-                    if res.status().is_success() {
-                        match res.json::<CameraSpace>().await {
-                            Ok(data) => {
-                                status_message.set("Camera data loaded successfully.".to_string());
-                                camera_data.set(Some(data));
-                            }
-                            Err(e) => status_message.set(
-                                "Failed to get camera data from server: ".to_string() + &e.to_string(),
-                            ),
-                        }
-                    } else {
-                        status_message.set(format!("Server error: {}", res.status()));
-                    }
+            match api::camera_status().await {
+                Ok(data) => {
+                    status_message.set("Camera data loaded successfully.".to_string());
+                    camera_data.set(Some(data));
                 }
-                Err(_) => status_message.set("Failed to connect to server.".to_string()),
+                Err(e) => status_message.set(e.to_string()),
             }
         });
     });
@@ -77,8 +105,17 @@ pub fn CameraStatus() -> impl IntoView {
                 move || camera_data.get().map(|data| view! {
                     <div class="image-meta">
                         <h2><strong>"Space Available"</strong></h2>
-                        <p><strong>"Camera 1: "</strong>{f64::trunc(data.cam1_free  * 100.0) / 100.0} " out of " {f64::trunc(data.cam1_total  * 100.0) / 100.0} " GB"</p>
-                        <p><strong>"Camera 2: "</strong>{f64::trunc(data.cam2_free  * 100.0) / 100.0} " out of " {f64::trunc(data.cam2_total  * 100.0) / 100.0} " GB"</p>
+                        {data.into_iter().map(|space| {
+                            let name = camera_name(&cameras.get(), &space.camera);
+                            let gb = |v: Option<f64>| v.map(|v| (f64::trunc(v * 100.0) / 100.0).to_string()).unwrap_or_else(|| "?".to_string());
+                            let (class, space_text) = match space.state {
+                                CameraReach::Ok => ("", format!("{} out of {} GB", gb(space.free_gb), gb(space.total_gb))),
+                                CameraReach::Timeout => ("health-warning", "did not answer in time".to_string()),
+                                CameraReach::Unreachable => ("health-warning", "not reachable".to_string()),
+                                CameraReach::Error => ("health-warning", space.message.unwrap_or_else(|| "error".to_string())),
+                            };
+                            view! { <p class=class><strong>{format!("{}: ", name)}</strong>{space_text}</p> }
+                        }).collect_view()}
                     </div>
                 })
             }
@@ -86,9 +123,202 @@ pub fn CameraStatus() -> impl IntoView {
     }
 }
 
+/*
+Health of the cameras, from the full RedEdge status: supply voltage, GPS fix and satellites, DLS and capture state.
+Every panel polls its camera while the page is open and keeps the last answer when a poll fails.
+*/
+#[component]
+pub fn CameraHealth() -> impl IntoView {
+    let cameras = use_cameras();
+    view! {
+        <div class="camera-container">
+            <h2>"Camera Health"</h2>
+            <div class="health-panels">
+                <For each=move || cameras.get() key=|c| c.id.clone() let:camera>
+                    <CameraHealthPanel camera=camera.id name=camera.name />
+                </For>
+            </div>
+        </div>
+    }
+}
+
+// how often the health panels are refreshed
+const HEALTH_POLL_MS: u32 = 5000;
+
+#[component]
+fn CameraHealthPanel(camera: String, name: String) -> impl IntoView {
+    let health = RwSignal::new(None::<RedEdgeStatus>);
+    let error = RwSignal::new(None::<String>);
+
+    let (abort_handle, abort_registration) = AbortHandle::new_pair();
+    spawn_local(async move {
+        let poll = async move {
+            loop {
+                match api::camera_health(&camera).await {
+                    Ok(status) => {
+                        health.set(Some(status));
+                        error.set(None);
+                    }
+                    Err(e) => error.set(Some(e.to_string())),
+                }
+                TimeoutFuture::new(HEALTH_POLL_MS).await;
+            }
+        };
+        let _ = Abortable::new(poll, abort_registration).await;
+    });
+    on_cleanup(move || abort_handle.abort());
+
+    view! {
+        <div class="health-panel">
+            <h3>{name}</h3>
+            {move || error.get().map(|e| view! { <p class="health-warning">{e}</p> })}
+            {move || match health.get() {
+                Some(status) => health_view(status).into_any(),
+                None => view! { <p>"Loading..."</p> }.into_any(),
+            }}
+        </div>
+    }
+}
+
+// one optional value with its unit, "N/A" when the camera did not report it
+fn show<T: std::fmt::Display>(value: Option<T>, unit: &str) -> String {
+    value.map(|v| format!("{}{}", v, unit)).unwrap_or_else(|| "N/A".to_string())
+}
+
+fn yes_no(value: Option<bool>) -> &'static str {
+    match value {
+        Some(true) => "yes",
+        Some(false) => "no",
+        None => "N/A",
+    }
+}
+
+// problems reported by the camera, worth a look before a survey
+fn health_warnings(status: &RedEdgeStatus) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if status.sd_warn == Some(true) {
+        warnings.push(format!("SD card: {}", status.sd_status.as_deref().unwrap_or("warning")));
+    }
+    if status.gps_warn == Some(true) {
+        warnings.push("GPS warning, the fix may be missing or poor".to_string());
+    }
+    if status.utc_time_valid == Some(false) {
+        warnings.push("UTC time not valid, capture timestamps are unreliable".to_string());
+    }
+    if status.dls_status.as_deref().is_some_and(|s| !s.eq_ignore_ascii_case("ok")) {
+        warnings.push(format!("DLS: {}", status.dls_status.as_deref().unwrap_or_default()));
+    }
+    warnings
+}
+
+fn health_view(status: RedEdgeStatus) -> impl IntoView {
+    let warnings = health_warnings(&status);
+    let satellites = status.sv_info.clone().unwrap_or_default();
+    view! {
+        <div class="health-details">
+            <div class="image-meta">
+                <p><strong>"Bus voltage: "</strong>{show(status.bus_volts.map(|v| format!("{:.2}", v)), " V")}</p>
+                <p><strong>"GPS fix: "</strong>{show(status.gps_type.clone(), "")}</p>
+                <p><strong>"Satellites used: "</strong>
+                    {format!("{} of {} visible", show(status.gps_used_sats, ""), show(status.gps_vis_sats, ""))}</p>
+                <p><strong>"Position accuracy: "</strong>{show(status.p_acc.map(|v| format!("{:.1}", v)), " m")}</p>
+                <p><strong>"Position: "</strong>
+                    {format!("{}, {}", show(status.gps_lat.map(|v| format!("{:.6}", v)), ""), show(status.gps_lon.map(|v| format!("{:.6}", v)), ""))}</p>
+                <p><strong>"Time source: "</strong>{show(status.time_source.clone(), "")}
+                    {format!(" (UTC valid: {})", yes_no(status.utc_time_valid))}</p>
+                <p><strong>"DLS: "</strong>{show(status.dls_status.clone(), "")}</p>
+                <p><strong>"Auto capture: "</strong>{yes_no(status.auto_cap_active)}</p>
+                <p><strong>"SD card: "</strong>
+                    {format!("{} of {} GB free ({})",
+                        show(status.sd_gb_free.map(|v| format!("{:.2}", v)), ""),
+                        show(status.sd_gb_total.map(|v| format!("{:.2}", v)), ""),
+                        show(status.sd_status.clone(), ""))}</p>
+            </div>
+            <SkyPlot satellites=satellites />
+            <ul class="health-warnings">
+                {warnings.into_iter().map(|w| view! { <li class="health-warning">{w}</li> }).collect_view()}
+            </ul>
+        </div>
+    }
+}
+
+// size of the sky plot, the horizon is drawn at SKY_RADIUS around the center
+const SKY_SIZE: f64 = 220.0;
+const SKY_RADIUS: f64 = 100.0;
+
+// position of a satellite on the sky plot: north up, zenith in the center, horizon on the outer ring
+fn sky_position(azimuth: f64, elevation: f64) -> (f64, f64) {
+    let center = SKY_SIZE / 2.0;
+    let r = SKY_RADIUS * (90.0 - elevation.clamp(0.0, 90.0)) / 90.0;
+    let az = azimuth.to_radians();
+    (center + r * az.sin(), center - r * az.cos())
+}
+
+/*
+Polar plot of the satellites tracked by a camera, green when used in the fix, with their id and signal strength
+as tooltip. Rings are at 0, 30 and 60 degrees of elevation.
+*/
+#[component]
+fn SkyPlot(satellites: Vec<SvInfo>) -> impl IntoView {
+    let center = SKY_SIZE / 2.0;
+    let rings = [0.0, 30.0, 60.0].map(|elevation| SKY_RADIUS * (90.0 - elevation) / 90.0);
+    let labels = [("N", 0.0), ("E", 90.0), ("S", 180.0), ("W", 270.0)].map(|(label, azimuth)| {
+        let (x, y) = sky_position(azimuth, -8.0);
+        view! { <text x=x y=y class="sky-label">{label}</text> }
+    });
+    let points = satellites
+        .into_iter()
+        .filter_map(|sv| {
+            let (x, y) = sky_position(sv.azimuth?, sv.elevation?);
+            let class = if sv.used_flag == Some(true) { "sky-sat sky-used" } else { "sky-sat" };
+            let tooltip = format!("SV {}: {} dB-Hz", show(sv.svid, ""), show(sv.cno, ""));
+            Some(view! {
+                <circle cx=x cy=y r="5" class=class>
+                    <title>{tooltip}</title>
+                </circle>
+            })
+        })
+        .collect_view();
+    view! {
+        <svg class="sky-plot" width=SKY_SIZE height=SKY_SIZE viewBox=format!("0 0 {} {}", SKY_SIZE, SKY_SIZE)>
+            {rings.map(|r| view! { <circle cx=center cy=center r=r class="sky-ring" /> })}
+            {labels}
+            {points}
+        </svg>
+    }
+}
+
+// confirmation text of a prepared reformat
+fn reformat_summary(plan: &ReformatPlan, name: &str) -> String {
+    let gb = |v: Option<f64>| v.map(|v| format!("{:.2}", v)).unwrap_or_else(|| "?".to_string());
+    let backup = if plan.not_archived == 0 {
+        "All of them are in the offload archive.".to_string()
+    } else {
+        format!(
+            "WARNING: {} of them are not in the offload archive (e.g. {}), confirming erases them anyway.",
+            plan.not_archived,
+            plan.not_archived_examples.join(", "),
+        )
+    };
+    format!(
+        "Are you sure you want to reformat {}? {} of {} GB are free, {} captures were recorded since {}. {} \
+        This action cannot be undone and must be confirmed within {} seconds.",
+        name,
+        gb(plan.sd_gb_free),
+        gb(plan.sd_gb_total),
+        plan.captures,
+        plan.since.as_deref().unwrap_or("the first record"),
+        backup,
+        plan.expires_in,
+    )
+}
+
 /* 
 This component handles the reformatting of the micasense cameras,
 using the RedEdge HTTP APIs.
+Reformatting takes two steps: the backend first reports what would be erased and returns a token,
+which the confirmation has to send back.
+Captures missing from the offload archive are listed in the confirmation, confirming overrides the backup check.
 */
 #[component]
 pub fn Reformat() -> impl IntoView {    
@@ -99,84 +329,86 @@ pub fn Reformat() -> impl IntoView {
     let show_result_popup = RwSignal::new(false);
     let show_confirmation_popup = RwSignal::new(false);
     
-    // Create a signal to store which camera is being reformatted
-    let target_camera = RwSignal::new(String::new());
+    // Reformat prepared by the backend, waiting for confirmation
+    let plan = RwSignal::new(None::<ReformatPlan>);
+    let cameras = use_cameras();
+
+    // only operators may reformat
+    let operator = crate::auth::is_operator();
+    let locked = move || !operator.get();
+    let title = move || locked().then_some(crate::auth::OPERATOR_ONLY);
     
-    // Function to handle the HTTP request
-    let fetch_data = move |camera: &str| {
-        // Define the URL to fetch data from with camera parameter
-        let url = format!("http://192.168.2.9:3000/api/reformat?camera={}", camera);
+    // Function to handle the HTTP request, echoing the token of the prepared reformat
+    let fetch_data = move |prepared: ReformatPlan| {
+        let camera = prepared.camera;
+        let params = ReformatConfirm {
+            token: prepared.token,
+            // the warning was part of the confirmation
+            override_backup: prepared.not_archived > 0,
+        };
         
         spawn_local(async move {
-            // Use fetch API to make a GET request
-            match reqwest::get(url).await {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        match response.text().await {
-                            Ok(text) => {
-                                // Set the result and show the result popup
-                                result.set(Some(text));
-                                show_result_popup.set(true);
-                            }
-                            Err(err) => {
-                                result.set(Some(format!("Error parsing response: {}", err)));
-                                show_result_popup.set(true);
-                            }
-                        }
-                    } else {
-                        result.set(Some(format!("Error: HTTP status {}", response.status())));
-                        show_result_popup.set(true);
-                    }
+            let text = match api::reformat_confirm(&camera, &params).await {
+                Ok(res) => format!("{} ({})", res.message, res.reformat_status),
+                Err(err) => err.to_string(),
+            };
+            // Set the result and show the result popup
+            result.set(Some(text));
+            show_result_popup.set(true);
+        });
+    };
+    
+    // Function to ask the backend what would be erased, then show confirmation popup
+    let request_confirmation = move |camera: String| {
+        spawn_local(async move {
+            match api::reformat_prepare(&camera).await {
+                Ok(prepared) => {
+                    plan.set(Some(prepared));
+                    show_confirmation_popup.set(true);
                 }
                 Err(err) => {
-                    result.set(Some(format!("Request failed: {}", err)));
+                    result.set(Some(err.to_string()));
                     show_result_popup.set(true);
                 }
             }
         });
     };
     
-    // Function to show confirmation popup
-    let request_confirmation = move |camera: &str| {
-        target_camera.set(camera.to_string());
-        show_confirmation_popup.set(true);
-    };
-    
     // Function to handle confirmation
     let on_confirm = move |_| {
-        let camera = target_camera.get();
         show_confirmation_popup.set(false);
-        fetch_data(&camera);
+        if let Some(prepared) = plan.get() {
+            plan.set(None);
+            fetch_data(prepared);
+        }
     };
     
     // Function to handle cancellation
     let on_cancel = move |_| {
         show_confirmation_popup.set(false);
+        plan.set(None);
     };
     
     view! {
         <div class="camera-container">
             <h2>"Reformat Cameras"</h2>
             <div class="button-container">
-                <button
-                    on:click=move |_| request_confirmation("cam1")
-                    class="fetch-button"
-                >
-                    "Reformat Camera 1"
-                </button>
-                <button
-                    on:click=move |_| request_confirmation("cam2")
-                    class="fetch-button"
-                >
-                    "Reformat Camera 2"
-                </button>
+                <For each=move || cameras.get() key=|c| c.id.clone() let:camera>
+                    <button
+                        on:click=move |_| request_confirmation(camera.id.clone())
+                        class="fetch-button"
+                        disabled=locked
+                        title=title
+                    >
+                        {format!("Reformat {}", camera.name)}
+                    </button>
+                </For>
             </div>
             
             // Confirmation popup
             <crate::util::ConfirmationPopup
                 show_popup=show_confirmation_popup
-                message=Signal::derive(move || format!("Are you sure you want to reformat {}? This action cannot be undone.", 
-                    if target_camera.get() == "cam1" { "Camera 1" } else { "Camera 2" }))
+                message=Signal::derive(move || plan.get().map(|p| reformat_summary(&p, &camera_name(&cameras.get(), &p.camera))).unwrap_or_default())
                 on_confirm=Callback::new(on_confirm)
                 on_cancel=Callback::new(on_cancel)
             />
@@ -191,72 +423,188 @@ pub fn Reformat() -> impl IntoView {
 }
 
 /* 
-This component fetches the images of the latest capture.
-It does this by simply calling get_last_capture on the backend, which handles basically everything.
+This component fetches the images of the latest capture of a camera, one per band.
+It does this by simply calling last_capture on the backend, which handles basically everything.
 */
 #[component]
 pub fn ImageFetch() -> impl IntoView {
-    let last_capture_image = RwSignal::new(None::<String>);
+    let cameras = use_cameras();
+    let camera = RwSignal::new(String::new());
+    // camera whose capture is shown
+    let shown = RwSignal::new(None::<CameraInfo>);
     let status_message = RwSignal::new(String::new());
-    let client: RwSignal<Client> = RwSignal::new(Client::new());
     let fetch_last_capture_image = move |_| {
+        let Some(selected) = cameras.get().into_iter().find(|c| c.id == camera.get()) else {
+            status_message.set("Please select a camera first.".to_string());
+            return;
+        };
         status_message.set("Fetching last capture image...".to_string());
-        let cl = client.get_untracked().clone();
         spawn_local(async move {
-            let url = "http://192.168.2.9:3000/api/get_last_capture?cam=cam1&band=1";
-            match cl.get(url).send().await {
-                Ok(res) => {
-                    if res.status().is_success() {
-                        match res.bytes().await {
-                            Ok(bytes) => {
-                                // Encode the image bytes to base64
-                                let base64_str = general_purpose::STANDARD.encode(&bytes);
-                                let data_url = format!("data:image/jpeg;base64,{}", base64_str);
-                                last_capture_image.set(Some(data_url));
-                                status_message.set("Image fetched successfully.".to_string());
-                            },
-                            Err(_) => status_message.set("Failed to read image bytes.".to_string()),
-                        }
-                    } else {
-                        status_message.set(format!("Server error: {}", res.status()));
-                    }
+            // band 1 first, so a camera without captures is reported once instead of as broken images
+            let params = CaptureParams { band: "1".to_string() };
+            match api::last_capture(&selected.id, &params).await {
+                Ok(_) => {
+                    shown.set(Some(selected));
+                    status_message.set("Image fetched successfully.".to_string());
                 },
-                Err(_) => status_message.set("Failed to connect to server.".to_string())
+                Err(e) => status_message.set(e.to_string()),
             }
         });
     };
     view!{
         <div class="camera-container">
-            <button on:click=fetch_last_capture_image class="fetch-button">
-                "Fetch Last Capture Image"
-            </button>
-            {move || last_capture_image.get().map(|_data_url| view! {
+            <div class="form-group">
+                <label>"Camera:"</label>
+                <CameraSelect camera=camera />
+                <button on:click=fetch_last_capture_image class="fetch-button">
+                    "Fetch Last Capture Image"
+                </button>
+            </div>
+            <p>{move || status_message.get()}</p>
+            {move || shown.get().map(|camera| view! {
                 <div>
-                    <div>
-                        <p><strong>"Last Capture Preview:"</strong></p>
-                        <p>"Red Band 1 (475±32)" </p>
-                        <img src="http://192.168.2.9:3000/api/get_last_capture?cam=cam1&band=1" alt="Last Capture" style="max-width: 500px;" />
-                        <p>"Red Band 2 (560±27)" </p>
-                        <img src="http://192.168.2.9:3000/api/get_last_capture?cam=cam1&band=2" alt="Last Capture" style="max-width: 500px;" />
-                        <p>"Red Band 3 (668±14)" </p>
-                        <img src="http://192.168.2.9:3000/api/get_last_capture?cam=cam1&band=3" alt="Last Capture" style="max-width: 500px;" />
-                        <p>"Red Band 4 (717±12)" </p>
-                        <img src="http://192.168.2.9:3000/api/get_last_capture?cam=cam1&band=4" alt="Last Capture" style="max-width: 500px;" />
-                        <p>"Red Band 5 (842±57)" </p>
-                        <img src="http://192.168.2.9:3000/api/get_last_capture?cam=cam1&band=5" alt="Last Capture" style="max-width: 500px;" />
-                    </div>
-                    <div>
-                        <p><strong>"Last Capture Preview:"</strong></p>
-                        <p>"Blue Band 1 (444±28)" </p>
-                        <img src="http://192.168.2.9:3000/api/get_last_capture?cam=cam2&band=1" alt="Last Capture" style="max-width: 500px;" />
-                        <p>"Blue Band 2 (531±14)" </p>
-                        <img src="http://192.168.2.9:3000/api/get_last_capture?cam=cam2&band=2" alt="Last Capture" style="max-width: 500px;" />
-                        <p>"Blue Band 3 (650±16)" </p>
-                        <img src="http://192.168.2.9:3000/api/get_last_capture?cam=cam2&band=3" alt="Last Capture" style="max-width: 500px;" />
-                        <p>"Blue Band 4 (705±10)" </p>
-                        <img src="http://192.168.2.9:3000/api/get_last_capture?cam=cam2&band=4" alt="Last Capture" style="max-width: 500px;" />
-                        <p>"Blue Band 5 (740±18)" </p>
-                        <img src="http://192.168.2.9:3000/api/get_last_capture?cam=cam2&band=5" alt="Last Capture" style="max-width: 500px;" />
+                    <p><strong>{format!("Last Capture Preview of {} ({}):", camera.name, camera.model)}</strong></p>
+                    {camera.bands.iter().map(|band| view! {
+                        <p>{format!("Band {}: {}", band.band, band_label(band))}</p>
+                        <img src=api::last_capture_url(&camera.id, band.band) alt="Last Capture" style="max-width: 500px;" />
+                    }).collect_view()}
+                </div>
+            })}
+        </div>
+    }
+}
+
+/*
+This component shows a spectral index (NDVI, NDWI, NDRE or any (a-b)/(a+b) pair of bands) of one capture,
+as the colour mapped image of the backend next to its statistics.
+The capture is given by id, "Latest Capture" fills in the newest one of the selected camera.
+*/
+#[component]
+pub fn IndexProducts() -> impl IntoView {
+    let cameras = use_cameras();
+    let camera = RwSignal::new(String::new());
+    let capture = RwSignal::new(String::new());
+    let index = RwSignal::new("ndvi".to_string());
+    let band_a = RwSignal::new("1".to_string());
+    let band_b = RwSignal::new("2".to_string());
+    let reflectance = RwSignal::new(false);
+    let stats = RwSignal::new(None::<IndexStats>);
+    let image_url = RwSignal::new(None::<String>);
+    let status_message = RwSignal::new(String::new());
+
+    // bands of the camera of the selected capture, for the custom index
+    let bands = move || {
+        let id = capture.get();
+        cameras.get().into_iter().find(|c| id.starts_with(&format!("{}-", c.id))).map(|c| c.bands).unwrap_or_default()
+    };
+
+    let fetch_latest = move |_| {
+        let selected = camera.get();
+        if selected.is_empty() {
+            status_message.set("Please select a camera first.".to_string());
+            return;
+        }
+        spawn_local(async move {
+            match api::latest_capture(&selected).await {
+                Ok(info) => {
+                    status_message.set(format!("Latest capture taken at {}.", info.time));
+                    capture.set(info.id);
+                }
+                Err(e) => status_message.set(e.to_string()),
+            }
+        });
+    };
+
+    let compute = move |_| {
+        let id = capture.get();
+        if id.is_empty() {
+            status_message.set("Please enter a capture first.".to_string());
+            return;
+        }
+        let custom = index.get() == "custom";
+        let params = IndexParams {
+            index: index.get(),
+            a: custom.then(|| band_a.get()),
+            b: custom.then(|| band_b.get()),
+            reflectance: reflectance.get(),
+        };
+        status_message.set("Computing index...".to_string());
+        spawn_local(async move {
+            match api::capture_index(&id, &params).await {
+                Ok(result) => {
+                    image_url.set(Some(api::capture_index_image_url(&id, &params)));
+                    stats.set(Some(result));
+                    status_message.set(String::new());
+                }
+                Err(e) => status_message.set(e.to_string()),
+            }
+        });
+    };
+
+    let band_select = move |band: RwSignal<String>| view! {
+        <select on:change=move |ev| band.set(event_target_value(&ev))>
+            {move || bands().into_iter().map(|b| {
+                let value = b.band.to_string();
+                let selected = value.clone();
+                view! { <option value=value selected=move || band.get() == selected>{band_label(&b)}</option> }
+            }).collect_view()}
+        </select>
+    };
+
+    view! {
+        <div class="camera-container">
+            <h2>"Spectral Indices"</h2>
+            <div class="form-group">
+                <label>"Camera:"</label>
+                <CameraSelect camera=camera />
+                <button on:click=fetch_latest class="fetch-button">"Latest Capture"</button>
+            </div>
+            <div class="form-group">
+                <label>"Capture:"</label>
+                <input type="text" placeholder="cam1-SET0001-000-0001"
+                    prop:value=move || capture.get()
+                    on:input=move |ev| capture.set(event_target_value(&ev)) />
+            </div>
+            <div class="form-group">
+                <label>"Index:"</label>
+                <select on:change=move |ev| index.set(event_target_value(&ev))>
+                    <option value="ndvi">"NDVI (vegetation)"</option>
+                    <option value="ndwi">"NDWI (water)"</option>
+                    <option value="ndre">"NDRE (red edge)"</option>
+                    <option value="custom">"Custom (a-b)/(a+b)"</option>
+                </select>
+            </div>
+            <Show when=move || index.get() == "custom">
+                <div class="form-group">
+                    <label>"a:"</label>
+                    {band_select(band_a)}
+                    <label>"b:"</label>
+                    {band_select(band_b)}
+                </div>
+            </Show>
+            <div class="form-group">
+                <label>
+                    <input type="checkbox"
+                        prop:checked=move || reflectance.get()
+                        on:change=move |ev| reflectance.set(event_target_checked(&ev)) />
+                    " Reflectance (DLS or calibration panel)"
+                </label>
+            </div>
+            <button on:click=compute class="fetch-button">"Compute"</button>
+            <p>{move || status_message.get()}</p>
+            {move || stats.get().map(|s| view! {
+                <div class="index-result">
+                    <img src=image_url.get().unwrap_or_default() alt=s.index.clone() class="index-image" />
+                    <div class="image-meta">
+                        <p><strong>{format!("{}: ", s.index)}</strong>{s.formula.clone()}</p>
+                        <p><strong>"Values: "</strong>{if s.reflectance { "reflectance" } else { "raw" }}</p>
+                        <p><strong>"Mean: "</strong>{show(s.mean.map(|v| format!("{:.3}", v)), "")}</p>
+                        <p><strong>"Range: "</strong>
+                            {format!("{} to {}", show(s.min.map(|v| format!("{:.3}", v)), ""), show(s.max.map(|v| format!("{:.3}", v)), ""))}</p>
+                        {s.percentiles.iter().map(|p| view! {
+                            <p><strong>{format!("P{}: ", p.percentile)}</strong>{format!("{:.3}", p.value)}</p>
+                        }).collect_view()}
+                        <p><strong>"Valid pixels: "</strong>{format!("{} of {}", s.valid_pixels, s.total_pixels)}</p>
                     </div>
                 </div>
             })}
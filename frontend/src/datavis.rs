@@ -1,59 +1,17 @@
 use web_sys::console;
 use leptos_chartistry::*;
 use leptos::*;
-use reqwest::Client;
 use wasm_bindgen::JsCast;
-use serde::Deserialize;
+use brig_types::{CameraFoldersParams, CaptureMetadataParams, CsvDataParams, GeotagParams, DataParams, DataPoint, ImageDataParams, ImageDataPoint};
+use crate::api;
+use crate::auth::{is_operator, OPERATOR_ONLY};
+use crate::camera::CameraSelect;
 use web_sys::{Blob, BlobPropertyBag, Url, HtmlAnchorElement};
 use leptos::prelude::*;
-use const_format::concatcp;  
 use tracing::info;
 use chrono::{DateTime, Utc};
 use leptos::task::spawn_local;
 
-const BASEURL: &'static str = "http://192.168.2.9:3000";
-
-#[derive(Debug, Clone, serde::Deserialize)]
-struct ImageDataPoint{
-    date: String,
-    lat: Option<f64>,
-    lon: Option<f64>,
-    cog: Option<f64>,
-    sog: Option<f64>,
-    conductivity: Option<f64>,
-    depth: Option<f64>,
-    oxygen_percentage: Option<f64>,
-    oxygen_ppm: Option<f64>,
-    ph: Option<f64>,
-    pressure: Option<f64>,
-    salinity: Option<f64>,
-    temperature: Option<f64>,
-}
-
-#[derive(Deserialize, Clone, Debug)]
-pub struct DataPoint {
-    time: String,
-    value: f64,
-    field: String,
-    epochtime: i64,
-}
-
-#[derive(Deserialize, Clone, Debug)]
-pub struct RTDataPoint {
-    pub ph: Option<f64>,
-    pub conductivity: Option<f64>,
-    pub salinity: Option<f64>,
-    pub temperature: Option<f64>,
-    pub pressure: Option<f64>,
-    pub oxygen_perc: Option<f64>,
-    pub oxygen_ppm: Option<f64>,
-    pub cog: Option<f64>,
-    pub sog: Option<f64>,
-    pub latitude: Option<f64>,
-    pub longitude: Option<f64>,
-    pub depth: Option<f64>,
-}
-
 pub fn data_page() -> impl IntoView {
     view! {
         <CsvDownload/>
@@ -69,53 +27,87 @@ pub fn CsvDownload() -> impl IntoView {
     let start_time = RwSignal::new(String::new());
     let end_time = RwSignal::new(String::new());
 
+    let export_format = RwSignal::new("csv".to_string());
+    let export_camera = RwSignal::new(String::new());
+    let altitude = RwSignal::new(String::new());
+    let geotag_message = RwSignal::new(String::new());
+
+    let operator = is_operator();
+    let locked = move || !operator.get();
+    let title = move || locked().then_some(OPERATOR_ONLY);
+
     let download_csv = move || {
         let start = start_time.get();
         let end = end_time.get();
 
         spawn_local(async move {
-            let url = format!(
-                "http://192.168.2.9:3000/api/download_data?start={}&end={}",
-                &start,
-                &end,
-            );
-
-            match reqwest::get(&url).await {
-                Ok(resp) if resp.status().is_success() => {
-                    if let Ok(bytes) = resp.bytes().await {
-                        let array = js_sys::Uint8Array::from(bytes.as_ref());
-                        let blob_parts = js_sys::Array::new();
-                        blob_parts.push(&array.buffer());
-
-                        let options = BlobPropertyBag::new();
-                        options.set_type("text/csv");
-
-                        let blob = Blob::new_with_buffer_source_sequence_and_options(
-                            &blob_parts,
-                            &options,
-                        )
-                        .unwrap();
-
-                        let url = Url::create_object_url_with_blob(&blob).unwrap();
-                        let document = web_sys::window().unwrap().document().unwrap();
-                        let a = document
-                            .create_element("a")
-                            .unwrap()
-                            .dyn_into::<HtmlAnchorElement>()
-                            .unwrap();
-                        a.set_href(&url);
-                        a.set_download("data.csv");
-                        a.click();
-
-                        Url::revoke_object_url(&url).ok();
+            let params = CsvDataParams { start, end };
+
+            // failures are already reported by the api module
+            if let Ok(bytes) = api::csv_data(&params).await {
+                save_file(&bytes, "text/csv", "data.csv");
+            }
+        });
+    };
+
+    // captures of the range for the geotag endpoints, an empty altitude is the water line
+    let geotag_params = move || {
+        let utc = |value: String| (!value.is_empty()).then(|| format!("{}:00Z", value));
+        let camera = export_camera.get();
+        GeotagParams {
+            start: utc(start_time.get()),
+            stop: utc(end_time.get()),
+            camera: (!camera.is_empty()).then_some(camera),
+            format: Some(export_format.get()),
+            altitude_m: altitude.get().trim().parse().ok(),
+            ..Default::default()
+        }
+    };
+
+    // one row per capture of the range with the nearest CTD and GPS values, or the geotags of its band files
+    let download_metadata = move || {
+        let format = export_format.get();
+        if ["pix4d", "agisoft", "xmp"].contains(&format.as_str()) {
+            let params = geotag_params();
+            spawn_local(async move {
+                if let Ok(bytes) = api::geotags(&params).await {
+                    match format.as_str() {
+                        "xmp" => save_file(&bytes, "application/zip", "geotags.zip"),
+                        _ => save_file(&bytes, "text/csv", &format!("geotags_{}.csv", format)),
                     }
                 }
-                Ok(resp) => {
-                    log::error!("Download failed: {}", resp.status());
-                }
-                Err(err) => {
-                    log::error!("Request failed: {:?}", err);
-                }
+            });
+            return;
+        }
+        let (start, end, format, camera) = (start_time.get(), end_time.get(), export_format.get(), export_camera.get());
+        let utc = |value: String| (!value.is_empty()).then(|| format!("{}:00Z", value));
+        let params = CaptureMetadataParams {
+            start: utc(start),
+            stop: utc(end),
+            camera: (!camera.is_empty()).then_some(camera),
+            format: Some(format.clone()),
+            ..Default::default()
+        };
+        spawn_local(async move {
+            if let Ok(bytes) = api::capture_metadata(&params).await {
+                let mime = if format == "json" { "application/json" } else { "text/csv" };
+                save_file(&bytes, mime, &format!("captures.{}", format));
+            }
+        });
+    };
+
+    // geotagged copies of the archived band files, next to the archive on the Pi
+    let write_geotags = move || {
+        let params = GeotagParams { format: None, ..geotag_params() };
+        geotag_message.set("Writing geotagged copies...".to_string());
+        spawn_local(async move {
+            match api::geotags_write(&params).await {
+                Ok(result) => geotag_message.set(format!(
+                    "{} files written to {}, {} not offloaded, {} failed; {} of {} captures had no GPS position.",
+                    result.files_written, result.dir, result.files_missing, result.files_failed,
+                    result.captures_without_position, result.captures
+                )),
+                Err(e) => geotag_message.set(e.to_string()),
             }
         });
     };
@@ -157,18 +149,73 @@ pub fn CsvDownload() -> impl IntoView {
                     "Download CSV"
                 </button>
             </div>
+            <h3>"Capture metadata"</h3>
+            <p>"One row per capture of the range with the GPS and CTD values nearest to it (within 1 s), for Pix4D or Agisoft. All cameras when none is selected."</p>
+            <p>"The geotag formats have one entry per band file, with the boat position and the altitude below. File names repeat between sets, so export one set at a time."</p>
+            <div class="form-group">
+                <label>"Camera:"</label>
+                <CameraSelect camera=export_camera />
+                <select on:change=move |ev| export_format.set(event_target_value(&ev))>
+                    <option value="csv">"CSV"</option>
+                    <option value="json">"JSON"</option>
+                    <option value="pix4d">"Pix4D geotags"</option>
+                    <option value="agisoft">"Agisoft geotags"</option>
+                    <option value="xmp">"XMP sidecars"</option>
+                </select>
+                <label>
+                    "Altitude (m):"
+                    <input type="number" step="0.1" placeholder="0"
+                        on:input=move |e| altitude.set(event_target_value(&e)) />
+                </label>
+            </div>
+            <div class="form-group">
+                <button on:click=move |_| download_metadata()>
+                    "Download Capture Metadata"
+                </button>
+                <button on:click=move |_| write_geotags() disabled=locked title=title>
+                    "Write Geotagged Copies"
+                </button>
+            </div>
+            <p class="status-message">{move || geotag_message.get()}</p>
         </div>
     }
 }
 
+// hands bytes to the browser as a file download
+fn save_file(bytes: &[u8], mime: &str, name: &str) {
+    let array = js_sys::Uint8Array::from(bytes);
+    let blob_parts = js_sys::Array::new();
+    blob_parts.push(&array.buffer());
+
+    let options = BlobPropertyBag::new();
+    options.set_type(mime);
+
+    let blob = Blob::new_with_buffer_source_sequence_and_options(
+        &blob_parts,
+        &options,
+    )
+    .unwrap();
+
+    let url = Url::create_object_url_with_blob(&blob).unwrap();
+    let document = web_sys::window().unwrap().document().unwrap();
+    let a = document
+        .create_element("a")
+        .unwrap()
+        .dyn_into::<HtmlAnchorElement>()
+        .unwrap();
+    a.set_href(&url);
+    a.set_download(name);
+    a.click();
+
+    Url::revoke_object_url(&url).ok();
+}
+
 /* 
 This component couples a micasense image with the CTD and GPS data.
 */
 #[component]
 pub fn ImageData() -> impl IntoView {
 
-    let client: RwSignal<Client> = RwSignal::new(Client::new());
-    
     // Selection signals for first load
     let selected_camera = RwSignal::new(String::new());
     let selected_date = RwSignal::new(String::new());
@@ -210,39 +257,27 @@ pub fn ImageData() -> impl IntoView {
         selected_set.set(String::new());
         selected_folder.set(String::new());
         
-        // Get a clone of the client before moving into spawn_local
-        let cl = client.get_untracked().clone(); 
-
         spawn_local(async move {
-            let url = format!("http://192.168.2.9:3000/api/camera_folders?camera={}&date={}", camera, date);
+            let params = CameraFoldersParams { date };
             
-            match cl.get(&url).send().await {
-                Ok(res) => {
-                    if res.status().is_success() {
-                        match res.json::<Vec<String>>().await {
-                            Ok(folder_list) => {
-                                // Process folder list into sets and folders
-                                let mut map = std::collections::HashMap::new();
-                                for entry in folder_list.iter() {
-                                    let mut parts = entry.as_str().splitn(2, '/');
-                                    if let (Some(set), Some(folder)) = (parts.next(), parts.next()) {
-                                        map.entry(set.to_owned())
-                                            .or_insert_with(Vec::new)
-                                            .push(folder.to_owned());
-                                    }
-                                }
-                                
-                                folders_map.set(map.clone());
-                                sets.set(map.keys().cloned().collect());
-                                status_message.set("Folders loaded successfully.".to_string());
-                            },
-                            Err(_) => status_message.set("Failed to parse folder data from server.".to_string())
+            match api::camera_folders(&camera, &params).await {
+                Ok(folder_list) => {
+                    // Process folder list into sets and folders
+                    let mut map = std::collections::HashMap::new();
+                    for entry in folder_list.iter() {
+                        let mut parts = entry.as_str().splitn(2, '/');
+                        if let (Some(set), Some(folder)) = (parts.next(), parts.next()) {
+                            map.entry(set.to_owned())
+                                .or_insert_with(Vec::new)
+                                .push(folder.to_owned());
                         }
-                    } else {
-                        status_message.set(format!("Server error: {}", res.status()));
                     }
+                    
+                    folders_map.set(map.clone());
+                    sets.set(map.keys().cloned().collect());
+                    status_message.set("Folders loaded successfully.".to_string());
                 },
-                Err(_) => status_message.set("Failed to connect to server.".to_string())
+                Err(e) => status_message.set(e.to_string())
             }
             
             is_loading_folders.set(false);
@@ -263,28 +298,15 @@ pub fn ImageData() -> impl IntoView {
 
         status_message.set("Loading image data...".to_string());
         
-        // Get a clone of the client before moving into spawn_local
-        let cl = client.get_untracked().clone();
-        
         spawn_local(async move {
-            let url = format!("http://192.168.2.9:3000/api/image_data?camera={}&date={}&set={}&folder={}&img_num={}", 
-                camera, date, set, folder, img);
+            let params = ImageDataParams { date, set, folder, img_num: img };
             
-            match cl.get(&url).send().await {
-                Ok(res) => {
-                    if res.status().is_success() {
-                        match res.json::<ImageDataPoint>().await {
-                            Ok(data) => {
-                                status_message.set("Image data loaded successfully.".to_string());
-                                image_data.set(Some(data));
-                            },
-                            Err(e) => status_message.set("Failed to parse image data from server. ".to_string() + &e.to_string())
-                        }
-                    } else {
-                        status_message.set(format!("Server error: {}", res.status()));
-                    }
+            match api::image_data(&camera, &params).await {
+                Ok(data) => {
+                    status_message.set("Image data loaded successfully.".to_string());
+                    image_data.set(Some(data));
                 },
-                Err(_) => status_message.set("Failed to connect to server.".to_string())
+                Err(e) => status_message.set(e.to_string())
             }
         });
     };
@@ -295,7 +317,7 @@ pub fn ImageData() -> impl IntoView {
             <details>
                 <summary>Instructions</summary>
 
-                <p>First select the camera, consult camera documentation for the bands of each one.</p>
+                <p>First select the camera, the Cameras page lists the bands of each one.</p>
                 <p>Select the date of the day the capture was taken, the system will then give a selection of sets and folders that were used that day.</p>
                 <p>The set refers to a startup sequence of the cameras, each time the cameras are powered one, a new set is made.</p>
                 <p>Folders contains up to 200 photos, so IMG_0000 to IMG_0199 will be in folder 000, IMG_0200 to IMG_0399 on folder 001 and so on.</p>
@@ -303,28 +325,7 @@ pub fn ImageData() -> impl IntoView {
             </details>
             <div class="form-group">
                 <label>"Select Camera:"</label>
-                <select
-                    on:change=move |ev| selected_camera.set(event_target_value(&ev))
-                >
-                    <option 
-                        value="" 
-                        selected=move || selected_camera.get().is_empty()
-                    >
-                        "-- Choose a Camera --"
-                    </option>
-                    <option 
-                        value="cam1" 
-                        selected=move || selected_camera.get() == "cam1"
-                    >
-                        "RedEdge-MX Red"
-                    </option>
-                    <option 
-                        value="cam2" 
-                        selected=move || selected_camera.get() == "cam2"
-                    >
-                        "RedEdge-MX Blue"
-                    </option>
-                </select>
+                <CameraSelect camera=selected_camera />
             </div>
 
             <div class="form-group">
@@ -441,51 +442,51 @@ pub fn ImageData() -> impl IntoView {
                     }}
                 </p>
 
-                {move || image_data.get().map(|data| view! {
-                    <div class="image-meta">
-                        <p class="timestamp"><strong>"Timestamp: "</strong>{data.date.clone()}</p>
-                        
-                        <p class="section-header"><strong>GPS data</strong></p>
-                        
-                        <div class="data-grid">
-                            <p><strong>"Latitude: "</strong>{data.lat}"°"</p>
-                            <p><strong>"Longitude: "</strong>{data.lon}"°"</p>
-                            <p><strong>"Cog: "</strong>{data.cog}"°"</p>
-                            <p><strong>"Sog: "</strong>{data.sog}" m/s"</p>
-                            <p><strong>"Depth: "</strong>{data.depth}" m"</p>
-                        </div>
-                        
-                        <p class="section-header"><strong>CTD data</strong></p>
-                        
-                        <div class="data-grid">
-                            <p><strong>"Conductivity: "</strong>{data.conductivity}" mS/cm"</p>
-                            <p><strong>"Oxygen Percentage: "</strong>{data.oxygen_percentage}</p>
-                            <p><strong>"Oxygen PPM: "</strong>{data.oxygen_ppm}" mg/l"</p>
-                            <p><strong>"pH: "</strong>{data.ph}</p>
-                            <p><strong>"Pressure: "</strong>{data.pressure}" dbar"</p>
-                            <p><strong>"Salinity: "</strong>{data.salinity}</p>
-                            <p><strong>"Temperature: "</strong>{data.temperature}" °C"</p>
-                        </div>
-                    </div>
-                })}
+                {move || image_data.get().map(image_meta)}
             </div>
         </div>
     }
 }
 
-// queries backend for sensor data, atm only asks for idronaut data
-async fn load_data(client: Client) -> Vec<DataPoint> {
-    info!("Loading data...");
-    let mut res = vec![];
-    match client.get(concatcp!(BASEURL, "/api/data")).send().await {
-        Ok(response) => match response.json::<Vec<DataPoint>>().await {
-            Ok(data) => res = data,
-            Err(_) => res = vec![],
-        },
-        Err(_) => res = vec![],
+// CTD and GPS values of a capture, as shown by the file browser and the gallery
+pub fn image_meta(data: ImageDataPoint) -> impl IntoView {
+    view! {
+        <div class="image-meta">
+            <p class="timestamp"><strong>"Timestamp: "</strong>{data.date.clone()}</p>
+            
+            <p class="section-header"><strong>GPS data</strong></p>
+            
+            <div class="data-grid">
+                <p><strong>"Latitude: "</strong>{data.lat}"°"</p>
+                <p><strong>"Longitude: "</strong>{data.lon}"°"</p>
+                <p><strong>"Cog: "</strong>{data.cog}"°"</p>
+                <p><strong>"Sog: "</strong>{data.sog}" m/s"</p>
+                <p><strong>"Depth: "</strong>{data.depth}" m"</p>
+            </div>
+            
+            <p class="section-header"><strong>CTD data</strong></p>
+            
+            <div class="data-grid">
+                <p><strong>"Conductivity: "</strong>{data.conductivity}" mS/cm"</p>
+                <p><strong>"Oxygen Percentage: "</strong>{data.oxygen_percentage}</p>
+                <p><strong>"Oxygen PPM: "</strong>{data.oxygen_ppm}" mg/l"</p>
+                <p><strong>"pH: "</strong>{data.ph}</p>
+                <p><strong>"Pressure: "</strong>{data.pressure}" dbar"</p>
+                <p><strong>"Salinity: "</strong>{data.salinity}</p>
+                <p><strong>"Temperature: "</strong>{data.temperature}" °C"</p>
+            </div>
+        </div>
     }
+}
+
+// fields shown on the charts page
+const CHART_FIELDS: &str = "conductivity,oxygen_percentage,oxygen_ppm,ph,pressure,temperature,salinity,depth,sog";
+
+// queries backend for sensor data, an empty list when the request failed
+async fn load_data(params: DataParams) -> Vec<DataPoint> {
+    info!("Loading data...");
+    let res = api::data(&params).await.unwrap_or_default();
     info!("Data received: {:?}", res);
-    res.sort_by_key(|k| k.epochtime);
     //console::log_1(&format!("Ordered vector: {:?}", res).into());
     res
 }
@@ -494,14 +495,38 @@ async fn load_data(client: Client) -> Vec<DataPoint> {
 #[component]
 pub fn Charts() -> impl IntoView {
 
-    let client = Client::new();
+    // range and aggregation picked by the user, empty start/end means the last 24 hours
+    let start_time = RwSignal::new(String::new());
+    let end_time = RwSignal::new(String::new());
+    let window = RwSignal::new(String::from("1m"));
+    let aggregate = RwSignal::new(String::from("last"));
+    let params = RwSignal::new(DataParams {
+        measurements: Some("idronaut_data,gps_data2".to_string()),
+        fields: Some(CHART_FIELDS.to_string()),
+        ..Default::default()
+    });
+
     let data = LocalResource::new(move || {
-        let client = client.clone();
+        let params = params.get();
         async move { 
-            load_data(client).await
+            load_data(params).await
         }
     });
 
+    // datetime-local values are sent as UTC, like the CSV download
+    let apply_range = move |_| {
+        let start = start_time.get();
+        let end = end_time.get();
+        params.set(DataParams {
+            start: (!start.is_empty()).then(|| format!("{}:00Z", start)),
+            stop: (!end.is_empty()).then(|| format!("{}:00Z", end)),
+            measurements: Some("idronaut_data,gps_data2".to_string()),
+            fields: Some(CHART_FIELDS.to_string()),
+            window: Some(window.get()),
+            aggregate: Some(aggregate.get()),
+        });
+    };
+
     //let chart_data = RwSignal::new(vec![]);
     //let chart_data2 = RwSignal::new(vec![]);
     let temperature_chart_data = RwSignal::new(vec![]);
@@ -511,6 +536,8 @@ pub fn Charts() -> impl IntoView {
     let salinity_chart_data = RwSignal::new(vec![]);
     let conductivity_chart_data = RwSignal::new(vec![]);
     let ph_chart_data = RwSignal::new(vec![]);
+    let depth_chart_data = RwSignal::new(vec![]);
+    let sog_chart_data = RwSignal::new(vec![]);
 
     // Effect to update chart data
     Effect::new(move || {
@@ -522,6 +549,8 @@ pub fn Charts() -> impl IntoView {
             salinity_chart_data.set(points.iter().filter(|&p| p.field == "salinity").cloned().collect());
             conductivity_chart_data.set(points.iter().filter(|&p| p.field == "conductivity").cloned().collect());
             ph_chart_data.set(points.iter().filter(|&p| p.field == "ph").cloned().collect());
+            depth_chart_data.set(points.iter().filter(|&p| p.field == "depth").cloned().collect());
+            sog_chart_data.set(points.iter().filter(|&p| p.field == "sog").cloned().collect());
             console::log_1(&format!("Ordered vector: {:?}", ph_chart_data.get()).into());
             //chart_data2.set((*points).clone()); // Example: using the same data for now
         }
@@ -561,7 +590,60 @@ pub fn Charts() -> impl IntoView {
         p.time.parse::<DateTime<Utc>>().unwrap()})
     .line(Line::new(|p: &DataPoint| p.value).with_interpolation(Interpolation::Linear).with_name("pH"));
 
+    // depth
+    let depth_series = Series::new(|p: &DataPoint| {
+        p.time.parse::<DateTime<Utc>>().unwrap()})
+    .line(Line::new(|p: &DataPoint| p.value).with_interpolation(Interpolation::Linear).with_name("Depth"));
+
+    // speed over ground
+    let sog_series = Series::new(|p: &DataPoint| {
+        p.time.parse::<DateTime<Utc>>().unwrap()})
+    .line(Line::new(|p: &DataPoint| p.value).with_interpolation(Interpolation::Linear).with_name("SOG"));
+
     view! {
+        <div class="component-container chart-range">
+            <h2>"Range"</h2>
+            <p>"Leave start and end empty for the last 24 hours."</p>
+            <div class="form-row">
+                <div class="form-group">
+                    <label>
+                        "Start:"
+                        <input type="datetime-local"
+                            on:input=move |e| start_time.set(event_target_value(&e)) />
+                    </label>
+                </div>
+                <div class="form-group">
+                    <label>
+                        "End:"
+                        <input type="datetime-local"
+                            on:input=move |e| end_time.set(event_target_value(&e)) />
+                    </label>
+                </div>
+                <div class="form-group">
+                    <label>"Window:"</label>
+                    <select on:change=move |ev| window.set(event_target_value(&ev))>
+                        <option value="10s">"10 s"</option>
+                        <option value="1m" selected=true>"1 min"</option>
+                        <option value="5m">"5 min"</option>
+                        <option value="15m">"15 min"</option>
+                        <option value="1h">"1 h"</option>
+                    </select>
+                </div>
+                <div class="form-group">
+                    <label>"Aggregate:"</label>
+                    <select on:change=move |ev| aggregate.set(event_target_value(&ev))>
+                        <option value="last" selected=true>"Last"</option>
+                        <option value="mean">"Mean"</option>
+                        <option value="median">"Median"</option>
+                        <option value="min">"Min"</option>
+                        <option value="max">"Max"</option>
+                    </select>
+                </div>
+                <div class="form-group">
+                    <button on:click=apply_range>"Load"</button>
+                </div>
+            </div>
+        </div>
         <h2>"CTD Data"</h2>
         <div class="charts-grid">
             <Suspense fallback=move || view! { <p>"Loading..."</p> }>
@@ -574,6 +656,13 @@ pub fn Charts() -> impl IntoView {
                 <SensorChart title="pH".to_string() data=ph_chart_data series=ph_series.clone() />
             </Suspense>
         </div>
+        <h2>"GPS Data"</h2>
+        <div class="charts-grid">
+            <Suspense fallback=move || view! { <p>"Loading..."</p> }>
+                <SensorChart title="Depth".to_string() data=depth_chart_data series=depth_series />
+                <SensorChart title="SOG".to_string() data=sog_chart_data series=sog_series />
+            </Suspense>
+        </div>
     }
 }
 
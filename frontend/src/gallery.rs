@@ -0,0 +1,193 @@
+use brig_types::{BandInfo, CaptureId, CaptureInfo, CaptureListParams, CapturePage, ImageDataParams, ImageDataPoint};
+use leptos::*;
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+
+use crate::api;
+use crate::camera::{band_label, use_cameras, CameraSelect};
+use crate::datavis::image_meta;
+
+/*
+This component browses the captures of a camera as thumbnails instead of by typing image numbers.
+The user picks a camera and a time range (the last 24 hours when left empty), the captures logged in
+micasense_data come a page at a time, oldest first. Clicking one shows every band of it with the CTD and GPS
+values recorded at the time of the capture; the previous and next buttons walk through the captures of the page.
+*/
+
+// band shown in the grid until another one is picked
+const GRID_BAND: u8 = 1;
+
+#[component]
+pub fn Gallery() -> impl IntoView {
+    let cameras = use_cameras();
+    let camera = RwSignal::new(String::new());
+    let start_time = RwSignal::new(String::new());
+    let end_time = RwSignal::new(String::new());
+    let grid_band = RwSignal::new(GRID_BAND);
+    let page = RwSignal::new(None::<CapturePage>);
+    let selected = RwSignal::new(None::<CaptureInfo>);
+    let selected_data = RwSignal::new(None::<ImageDataPoint>);
+    let status_message = RwSignal::new(String::new());
+
+    // datetime-local values are sent as UTC, like the CSV download
+    let load_page = move |number: u32| {
+        let selected_camera = camera.get_untracked();
+        if selected_camera.is_empty() {
+            status_message.set("Please select a camera first.".to_string());
+            return;
+        }
+        let utc = |value: String| (!value.is_empty()).then(|| format!("{}:00Z", value));
+        let params = CaptureListParams {
+            start: utc(start_time.get_untracked()),
+            stop: utc(end_time.get_untracked()),
+            page: Some(number),
+            per_page: None,
+        };
+        status_message.set("Loading captures...".to_string());
+        spawn_local(async move {
+            match api::camera_captures(&selected_camera, &params).await {
+                Ok(result) if result.total == 0 => {
+                    status_message.set("No captures in that range.".to_string());
+                    page.set(None);
+                }
+                Ok(result) => {
+                    status_message.set(format!("{} captures in that range.", result.total));
+                    page.set(Some(result));
+                }
+                Err(e) => status_message.set(e.to_string()),
+            }
+        });
+    };
+
+    // shows a capture with the CTD and GPS values of its time
+    let select = move |capture: CaptureInfo| {
+        selected_data.set(None);
+        selected.set(Some(capture.clone()));
+        let Some(id) = CaptureId::parse(&capture.id) else {
+            return;
+        };
+        let params = ImageDataParams {
+            date: capture.time.chars().take(10).collect(),
+            set: id.set,
+            folder: id.folder,
+            img_num: id.number,
+        };
+        spawn_local(async move {
+            match api::image_data(&id.camera, &params).await {
+                Ok(data) => selected_data.set(Some(data)),
+                Err(e) => status_message.set(e.to_string()),
+            }
+        });
+    };
+
+    // capture before (-1) or after (+1) the selected one on the current page
+    let step = move |offset: isize| {
+        let (Some(current), Some(result)) = (selected.get_untracked(), page.get_untracked()) else {
+            return;
+        };
+        let position = result.captures.iter().position(|c| c.id == current.id);
+        if let Some(next) = position.and_then(|p| p.checked_add_signed(offset)).and_then(|p| result.captures.get(p)) {
+            select(next.clone());
+        }
+    };
+
+    let pages = move || page.get().map(|p| p.total.div_ceil(p.per_page as u64).max(1)).unwrap_or(0);
+    let current_page = move || page.get().map(|p| p.page).unwrap_or(0);
+
+    // bands of the camera of the selected capture
+    let selected_bands = move || {
+        let capture = selected.get()?;
+        let camera = cameras.get().into_iter().find(|c| c.id == capture.camera)?;
+        Some((capture, camera.bands))
+    };
+
+    // bands of the selected camera, for the grid
+    let grid_bands = move || {
+        let id = camera.get();
+        cameras.get().into_iter().find(|c| c.id == id).map(|c| c.bands).unwrap_or_default()
+    };
+
+    view! {
+        <div class="component-container gallery">
+            <h2>"Capture Gallery"</h2>
+            <div class="form-group">
+                <label>"Camera:"</label>
+                <CameraSelect camera=camera />
+            </div>
+            <div class="form-group">
+                <label>
+                    "Start:"
+                    <input type="datetime-local"
+                        on:input=move |e| start_time.set(event_target_value(&e)) />
+                </label>
+                <label>
+                    "End:"
+                    <input type="datetime-local"
+                        on:input=move |e| end_time.set(event_target_value(&e)) />
+                </label>
+            </div>
+            <div class="form-group">
+                <label>"Band:"</label>
+                <select on:change=move |ev| grid_band.set(event_target_value(&ev).parse().unwrap_or(GRID_BAND))>
+                    {move || grid_bands().into_iter().map(|b| {
+                        let band = b.band;
+                        view! { <option value=band.to_string() selected=move || grid_band.get() == band>{band_label(&b)}</option> }
+                    }).collect_view()}
+                </select>
+                <button on:click=move |_| load_page(0) class="fetch-button">"Load Captures"</button>
+            </div>
+            <p class="status-message">{move || status_message.get()}</p>
+
+            {move || selected_bands().map(|(capture, bands)| view! {
+                <div class="gallery-detail">
+                    <h3>{format!("{} at {}", capture.id, capture.time)}</h3>
+                    <div class="form-group">
+                        <button on:click=move |_| step(-1)>"Previous"</button>
+                        <button on:click=move |_| step(1)>"Next"</button>
+                        <button on:click=move |_| selected.set(None)>"Close"</button>
+                    </div>
+                    <CaptureBands capture_id=capture.id.clone() bands=bands />
+                    {move || selected_data.get().map(image_meta)}
+                </div>
+            })}
+
+            <div class="gallery-grid">
+                {move || page.get().map(|result| {
+                    let band = grid_band.get();
+                    result.captures.into_iter().map(|capture| {
+                        let (id, time) = (capture.id.clone(), capture.time.clone());
+                        view! {
+                            <button class="gallery-item" on:click=move |_| select(capture.clone())>
+                                <img src=api::capture_thumbnail_url(&id, band) alt=id.clone() loading="lazy" />
+                                <span>{time}</span>
+                            </button>
+                        }
+                    }).collect_view()
+                })}
+            </div>
+
+            <Show when=move || { pages() > 1 }>
+                <div class="form-group">
+                    <button on:click=move |_| load_page(current_page() - 1) disabled=move || current_page() == 0>"Previous Page"</button>
+                    <span>{move || format!(" Page {} of {} ", current_page() + 1, pages())}</span>
+                    <button on:click=move |_| load_page(current_page() + 1) disabled=move || current_page() as u64 + 1 >= pages()>"Next Page"</button>
+                </div>
+            </Show>
+        </div>
+    }
+}
+
+// thumbnails of every band of a capture, also shown by the map
+#[component]
+pub fn CaptureBands(capture_id: String, bands: Vec<BandInfo>) -> impl IntoView {
+    view! {
+        <div class="gallery-bands">
+            {bands.iter().map(|band| view! {
+                <figure>
+                    <img src=api::capture_thumbnail_url(&capture_id, band.band) alt=band_label(band) />
+                    <figcaption>{format!("Band {}: {}", band.band, band_label(band))}</figcaption>
+                </figure>
+            }).collect_view()}
+        </div>
+    }
+}
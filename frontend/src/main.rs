@@ -1,38 +1,43 @@
-use datavis::RTDataPoint;
+use brig_types::{RTDataPoint, RTValue, LIVE_EVENT};
 use leptos::*;
 use leptos_router::components::{Route, Router, Routes, A};
 use leptos_router::path;
-use reqwest::Client;
 use gloo::timers::callback::Interval;
+use gloo::timers::future::TimeoutFuture;
+use gloo::net::eventsource::{futures::EventSource, State as EventSourceState};
+use futures::future::{AbortHandle, Abortable};
+use futures::StreamExt;
 use leptos::suspense::Suspense;
 use leptos::prelude::*;
 use leptos::task::spawn_local;
 use web_sys::console;
 use tracing::info;
-use const_format::concatcp;  
 
+mod api;
+mod auth;
 mod camera;
 mod datavis;
+mod gallery;
+mod map;
+mod offload;
 mod util;
 
-#[derive(serde::Deserialize, Clone, Debug)]
-struct ServiceStatus {
-    idronaut: bool,
-    camera_capture: bool,
-}
-
-const BASEURL: &'static str = "http://192.168.2.9:3000";
-
 //#[tokio::main]
 fn main() {
     wasm_logger::init(wasm_logger::Config::default());
     log::info!("Some info");
-    leptos::mount::mount_to_body(App);
+    // the backend address has to be known before the first request goes out
+    spawn_local(async {
+        api::init().await;
+        leptos::mount::mount_to_body(App);
+    });
 }
 
 // main component
 #[component]
 fn App() -> impl IntoView {
+    auth::provide_session();
+    camera::provide_cameras();
     view! {
         <Router>
             <SiteHeader />
@@ -43,39 +48,26 @@ fn App() -> impl IntoView {
                     <Route path=path!("/Status/") view=Status/>
                     <Route path=path!("/Cameras/") view=camera::camera_page/>
                     <Route path=path!("/Data/") view=datavis::data_page/>
+                    <Route path=path!("/Gallery/") view=gallery::Gallery/>
+                    <Route path=path!("/Map/") view=map::Map/>
+                    <Route path=path!("/Offload/") view=offload::Offload/>
+                    <Route path=path!("/Login/") view=auth::Login/>
                 </Routes>
             </main>
+            <util::Toaster />
         </Router>
     }
 }
 
 // post for service calls
-async fn service_request(client: Client, name: &str, action: &str, result: &RwSignal<Option<String>>, popup: &RwSignal<bool>) -> () {
-    let addr = format!("/api/{}/{}", name, action).to_owned();
-    let mut url : String = "".to_owned();
-    url.push_str(BASEURL);
-    url.push_str(&addr);
-    match client.post(url).send().await {
-        Ok(response) => {
-            if response.status().is_success() {
-                let res = format!("{} {} request sent successfully", action, name);
-                info!(res);
-                result.set(Some(res));
-                popup.set(true);
-            } else {
-                let res = format!("Failed to {} {}", action, name);
-                info!(res);
-                result.set(Some(res));
-                popup.set(true);
-            }
-        }
-        Err(err) => {
-            let res = format!("Error sending request: {:?}", err);
-            info!(res);
-            result.set(Some(res));
-            popup.set(true);
-            }
-    }
+async fn service_request(name: &str, action: &str, result: &RwSignal<Option<String>>, popup: &RwSignal<bool>) -> () {
+    let res = match api::service_action(name, action).await {
+        Ok(()) => format!("{} {} request sent successfully", action, name),
+        Err(err) => format!("Failed to {} {}: {}", action, name, err),
+    };
+    info!(res);
+    result.set(Some(res));
+    popup.set(true);
 }
 
 #[component]
@@ -87,243 +79,97 @@ fn Home() -> impl IntoView {
 
 }
 
+// keeps a server-sent events connection to the live endpoint open and writes every update to data,
+// reconnecting when the backend closes the connection
+async fn follow_live_data(data: RwSignal<Option<RTDataPoint>>) {
+    loop {
+        if let Ok(mut source) = EventSource::new(&api::live_url()) {
+            if let Ok(mut events) = source.subscribe(LIVE_EVENT) {
+                while let Some(event) = events.next().await {
+                    match event {
+                        Ok((_, msg)) => {
+                            let latest = msg.data().as_string()
+                                .and_then(|d| serde_json::from_str::<Option<RTDataPoint>>(&d).ok())
+                                .flatten();
+                            data.set(latest);
+                        }
+                        Err(_) => {
+                            data.set(None);
+                            // the browser retries on its own unless the connection is closed for good
+                            if source.state() == EventSourceState::Closed {
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        TimeoutFuture::new(3000).await;
+    }
+}
+
 #[component]
 fn RTData() -> impl IntoView{
-    let tick = RwSignal::new(0);
-
-    // signal interval
-    Interval::new(5000, move || {
-        tick.update(|t| *t += 1);
-    })
-    .forget();
+    let data = RwSignal::new(None::<RTDataPoint>);
 
-    let data = LocalResource::new(move || {
-        let client = Client::new();
-        let _ = tick.get();
-        async move { 
-            match client.get(concatcp!(BASEURL, "/api/latest_data")).send().await {
-                Ok(response) => match response.json::<datavis::RTDataPoint>().await {
-                    Ok(data) => data,
-                    Err(_) => RTDataPoint{ph: None, temperature: None, conductivity: None, salinity: None, pressure: None, oxygen_perc: None, oxygen_ppm: None, cog: None, sog: None, latitude: None, longitude: None, depth: None},
-                },
-                Err(_) => RTDataPoint{ph: None, temperature: None, conductivity: None, salinity: None, pressure: None, oxygen_perc: None, oxygen_ppm: None, cog: None, sog: None, latitude: None, longitude: None, depth: None},
-            }
-        }
+    // live updates, the connection is dropped when the component is unmounted
+    let (abort_handle, abort_registration) = AbortHandle::new_pair();
+    spawn_local(async move {
+        let _ = Abortable::new(follow_live_data(data), abort_registration).await;
     });
+    on_cleanup(move || abort_handle.abort());
+
+    // label, unit and field of every tile, in display order
+    let tiles: [(&'static str, &'static str, fn(&RTDataPoint) -> Option<RTValue>); 12] = [
+        ("Latitude", "", |d| d.latitude.clone()),
+        ("Longitude", "", |d| d.longitude.clone()),
+        ("Depth", " m", |d| d.depth.clone()),
+        ("Temperature", " °C", |d| d.temperature.clone()),
+        ("Pressure", " dbar", |d| d.pressure.clone()),
+        ("Conductivity", " mS/cm", |d| d.conductivity.clone()),
+        ("Salinity", "", |d| d.salinity.clone()),
+        ("pH", "", |d| d.ph.clone()),
+        ("Oxygen Dissolved %", "", |d| d.oxygen_perc.clone()),
+        ("Oxygen Dissolved (ppm)", " mg/l", |d| d.oxygen_ppm.clone()),
+        ("COG", "°", |d| d.cog.clone()),
+        ("SOG", " m/s", |d| d.sog.clone()),
+    ];
+
     view!{
         <h2>"Real Time Data"</h2>
         <div class="numbers-grid">
-            <Suspense fallback=move || view! {
-                <div class="chart-container-small">
-                    <div class="number-label">"Latitude"</div>
-                    <div class="number-display">"Loading..."</div>
-                </div>
-                <div class="chart-container-small">
-                    <div class="number-label">"Longitude"</div>
-                    <div class="number-display">"Loading..."</div>
-                </div>
-                <div class="chart-container-small">
-                    <div class="number-label">"Depth"</div>
-                    <div class="number-display">"Loading..."</div>
-                </div>
-                <div class="chart-container-small">
-                    <div class="number-label">"Temperature"</div>
-                    <div class="number-display">"Loading..."</div>
-                </div>
-                <div class="chart-container-small">
-                    <div class="number-label">"Pressure"</div>
-                    <div class="number-display">"Loading..."</div>
-                </div>
-                <div class="chart-container-small">
-                    <div class="number-label">"Conductivity"</div>
-                    <div class="number-display">"Loading..."</div>
-                </div>
-                <div class="chart-container-small">
-                    <div class="number-label">"Salinity"</div>
-                    <div class="number-display">"Loading..."</div>
-                </div>
-                <div class="chart-container-small">
-                    <div class="number-label">"pH"</div>
-                    <div class="number-display">"Loading..."</div>
-                </div>
-                <div class="chart-container-small">
-                    <div class="number-label">"Oxygen Dissolved %"</div>
-                    <div class="number-display">"Loading..."</div>
-                </div>
-                <div class="chart-container-small">
-                    <div class="number-label">"Oxygen Dissolved (ppm)"</div>
-                    <div class="number-display">"Loading..."</div>
-                </div>
-                <div class="chart-container-small">
-                    <div class="number-label">"COG"</div>
-                    <div class="number-display">"Loading"</div>
-                </div>
-                <div class="chart-container-small">
-                    <div class="number-label">"SOG"</div>
-                    <div class="number-display">"Loading"</div>
-                </div>
-            }>
-            {move || {
-                if let Some(dt) = data.get() {
-                    if let (Some(ph), Some(temperature), Some(pressure), Some(conductivity), Some(salinity), Some(oxygen_perc), Some(oxygen_ppm),
-                            Some(cog), Some(sog), Some(latitude), Some(longitude), Some(depth)) = (dt.ph, dt.temperature, dt.pressure, dt.conductivity, dt.salinity, dt.oxygen_perc, dt.oxygen_ppm, dt.cog, dt.sog, dt.latitude, dt.longitude, dt.depth)
-                        {
-                            view! {
-                                <div class="chart-container-small">
-                                    <div class="number-label">"Latitude"</div>
-                                    <div class="number-display">{latitude.to_string()}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"Longitude"</div>
-                                    <div class="number-display">{longitude.to_string()}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"Depth"</div>
-                                    <div class="number-display">{depth.to_string()+ " m"}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"Temperature"</div>
-                                    <div class="number-display">{temperature.to_string()+ " °C"}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"Pressure"</div>
-                                    <div class="number-display">{pressure.to_string() + " dbar"}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"Conductivity"</div>
-                                    <div class="number-display">{conductivity.to_string()+ " mS/cm"}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"Salinity"</div>
-                                    <div class="number-display">{salinity.to_string()}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"pH"</div>
-                                    <div class="number-display">{ph.to_string()}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"Oxygen Dissolved %"</div>
-                                    <div class="number-display">{oxygen_perc.to_string()}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"Oxygen Dissolved (ppm)"</div>
-                                    <div class="number-display">{oxygen_ppm.to_string() + " mg/l"}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"COG"</div>
-                                    <div class="number-display">{cog.to_string() + "°"}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"SOG"</div>
-                                    <div class="number-display">{sog.to_string() + " m/s"}</div>
-                                </div>
-                            }
-                        } else {
-                            view! {
-                                <div class="chart-container-small">
-                                    <div class="number-label">"Latitude"</div>
-                                    <div class="number-display">{"N/A".to_string()}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"Longitude"</div>
-                                    <div class="number-display">{"N/A".to_string()}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"Depth"</div>
-                                    <div class="number-display">{"N/A".to_string()}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"Temperature"</div>
-                                    <div class="number-display">{"N/A".to_string()}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"Pressure"</div>
-                                    <div class="number-display">{"N/A".to_string()}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"Conductivity"</div>
-                                    <div class="number-display">{"N/A".to_string()}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"Salinity"</div>
-                                    <div class="number-display">{"N/A".to_string()}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"pH"</div>
-                                    <div class="number-display">{"N/A".to_string()}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"Oxygen Dissolved %"</div>
-                                    <div class="number-display">{"N/A".to_string()}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"Oxygen Dissolved (ppm)"</div>
-                                    <div class="number-display">{"N/A".to_string()}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"COG"</div>
-                                    <div class="number-display">{"N/A".to_string()}</div>
-                                </div>
-                                <div class="chart-container-small">
-                                    <div class="number-label">"SOG"</div>
-                                    <div class="number-display">{"N/A".to_string()}</div>
-                                </div>
-                            }
-                        }
-                    } else {
-                        view! {
-                            <div class="chart-container-small">
-                                <div class="number-label">"Latitude"</div>
-                                <div class="number-display">{"N/A".to_string()}</div>
-                            </div>
-                            <div class="chart-container-small">
-                                <div class="number-label">"Longitude"</div>
-                                <div class="number-display">{"N/A".to_string()}</div>
-                            </div>
-                            <div class="chart-container-small">
-                                <div class="number-label">"Depth"</div>
-                                <div class="number-display">{"N/A".to_string()}</div>
-                            </div>
-                            <div class="chart-container-small">
-                                <div class="number-label">"Temperature"</div>
-                                <div class="number-display">{"N/A".to_string()}</div>
-                            </div>
-                            <div class="chart-container-small">
-                                <div class="number-label">"Pressure"</div>
-                                <div class="number-display">{"N/A".to_string()}</div>
-                            </div>
-                            <div class="chart-container-small">
-                                <div class="number-label">"Conductivity"</div>
-                                <div class="number-display">{"N/A".to_string()}</div>
-                            </div>
-                            <div class="chart-container-small">
-                                <div class="number-label">"Salinity"</div>
-                                <div class="number-display">{"N/A".to_string()}</div>
-                            </div>
-                            <div class="chart-container-small">
-                                <div class="number-label">"pH"</div>
-                                <div class="number-display">{"N/A".to_string()}</div>
-                            </div>
-                            <div class="chart-container-small">
-                                <div class="number-label">"Oxygen Dissolved %"</div>
-                                <div class="number-display">{"N/A".to_string()}</div>
-                            </div>
-                            <div class="chart-container-small">
-                                <div class="number-label">"Oxygen Dissolved (ppm)"</div>
-                                <div class="number-display">{"N/A".to_string()}</div>
-                            </div>
-                            <div class="chart-container-small">
-                                <div class="number-label">"COG"</div>
-                                <div class="number-display">{"N/A".to_string()}</div>
-                            </div>
-                            <div class="chart-container-small">
-                                <div class="number-label">"SOG"</div>
-                                <div class="number-display">{"N/A".to_string()}</div>
-                            </div>
-                        }
-                    }
-                }}
-            </Suspense>
+            {tiles.into_iter().map(|(label, unit, field)| {
+                let value = Signal::derive(move || data.get().and_then(|d| field(&d)));
+                view! { <RTTile label=label unit=unit value=value /> }
+            }).collect_view()}
+        </div>
+    }
+}
+
+// one real time value with its freshness: ok, stale (sensor stopped reporting) or missing (no sample at all)
+#[component]
+fn RTTile(label: &'static str, unit: &'static str, #[prop(into)] value: Signal<Option<RTValue>>) -> impl IntoView {
+    let class = move || match value.get() {
+        Some(v) if v.stale => "chart-container-small tile-stale",
+        Some(_) => "chart-container-small tile-ok",
+        None => "chart-container-small tile-missing",
+    };
+    view! {
+        <div class=class>
+            <div class="number-label">{label}</div>
+            {move || match value.get() {
+                Some(v) => view! {
+                    <div class="number-display">{format!("{}{}", v.value, unit)}</div>
+                    <div class="number-age" title=v.time.clone()>
+                        {if v.stale { "stale, " } else { "" }}
+                        {format!("{} s ago ({})", v.age_ms / 1000, v.measurement)}
+                    </div>
+                }.into_any(),
+                None => view! {
+                    <div class="number-display">"N/A"</div>
+                    <div class="number-age">"missing"</div>
+                }.into_any(),
+            }}
         </div>
     }
 }
@@ -331,31 +177,26 @@ fn RTData() -> impl IntoView{
 // component displaying buttons for asv services
 #[component]
 fn Status() -> impl IntoView {
-    let client = Client::new();
-    let client1= Client::new();
-    let client2= Client::new();
-    let client3= Client::new();
-
     let result = RwSignal::new(None::<String>);
     let show_popup= RwSignal::new(false);
+    // only operators may start and stop services
+    let operator = auth::is_operator();
+    let locked = move || !operator.get();
+    let title = move || locked().then_some(auth::OPERATOR_ONLY);
 
     view! {
         <h2>"Service Control"</h2>
         <ServiceMonitor />
         <div class="status-container">
             <div class="status-buttons">
-                <button class="start" on:click=move |_| spawn_local({
-                    let value = client.clone();
-                    async move { service_request(value, "IDRONAUT", "start", &result, &show_popup).await}})>"Start CTD gathering"</button>
-                <button class="start" on:click=move |_| spawn_local({
-                    let value = client2.clone();
-                    async move {service_request(value, "camera_capture", "start", &result, &show_popup).await}})>"Start Camera Capture"</button>
-                <button class="stop" on:click=move |_| spawn_local({
-                    let value = client1.clone();
-                    async move {service_request(value, "IDRONAUT", "stop", &result, &show_popup).await}})>"Stop CTD gathering"</button>
-                <button class="stop" on:click=move |_| spawn_local({
-                    let value = client3.clone();
-                    async move {service_request(value, "camera_capture", "stop", &result, &show_popup).await}})>"Stop Camera Capture"</button>
+                <button class="start" disabled=locked title=title on:click=move |_| spawn_local(async move {
+                    service_request("IDRONAUT", "start", &result, &show_popup).await })>"Start CTD gathering"</button>
+                <button class="start" disabled=locked title=title on:click=move |_| spawn_local(async move {
+                    service_request("camera_capture", "start", &result, &show_popup).await })>"Start Camera Capture"</button>
+                <button class="stop" disabled=locked title=title on:click=move |_| spawn_local(async move {
+                    service_request("IDRONAUT", "stop", &result, &show_popup).await })>"Stop CTD gathering"</button>
+                <button class="stop" disabled=locked title=title on:click=move |_| spawn_local(async move {
+                    service_request("camera_capture", "stop", &result, &show_popup).await })>"Stop Camera Capture"</button>
             </div>
             <util::PopUp 
                 show_popup=show_popup
@@ -377,30 +218,13 @@ fn ServiceMonitor() -> impl IntoView {
     })
     .forget();
 
-    let url = concatcp!(BASEURL, "/api/status");
-
-    // dynamic resource
+    // dynamic resource, None while the backend is not reachable
     let status_resource = LocalResource::new(move || {
         let _ = tick.get();
-        let value = url;
         async move {
-            let client = Client::new();
-            match client.get(value).send().await {
-                Ok(response) => match response.json::<ServiceStatus>().await {
-                    Ok(status) => {
-                        console::log_1(&format!("Fetched status: {:?}", status).into());
-                        status
-                    }
-                    Err(err) => {
-                        console::error_1(&format!("Status parse error: {:?}", err).into());
-                        ServiceStatus { idronaut: false, camera_capture: false }
-                    }
-                },
-                Err(err) => {
-                    console::error_1(&format!("Status request error: {:?}", err).into());
-                    ServiceStatus { idronaut: false, camera_capture: false }
-                }
-            }
+            let status = api::status().await.ok();
+            console::log_1(&format!("Fetched status: {:?}", status).into());
+            status
         }
     });
 
@@ -416,7 +240,7 @@ fn ServiceMonitor() -> impl IntoView {
                                 </div>
                             </div> }>
                 {move || {
-                    if let Some(status) = status_resource.get() {
+                    if let Some(Some(status)) = status_resource.get().map(|s| s.take()) {
                         view! {
                             <div class="status-indicators">
                                 <div>
@@ -474,8 +298,12 @@ fn SiteHeader() -> impl IntoView {
             <nav>
                 <p><A href="/Charts/">"Charts"</A></p>
                 <p><A href="/Data/">"Data"</A></p>
+                <p><A href="/Gallery/">"Gallery"</A></p>
+                <p><A href="/Map/">"Map"</A></p>
                 <p><A href="/Status/">"Status"</A></p>
                 <p><A href="/Cameras/">"Cameras"</A></p>
+                <p><A href="/Offload/">"Offload"</A></p>
+                <auth::SessionBadge />
             </nav>
         </header>
     }
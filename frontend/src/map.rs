@@ -0,0 +1,406 @@
+use brig_types::{CaptureMetadataParams, CaptureRecord, DataParams, DataPoint, RTDataPoint, TileSetInfo};
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, TimeDelta, Utc};
+use futures::future::{AbortHandle, Abortable};
+use leptos::*;
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+
+use crate::api;
+use crate::camera::{use_cameras, CameraSelect};
+use crate::gallery::CaptureBands;
+
+/*
+This component draws the boat on a map: its live position and heading (cog) from the real time stream, its track
+over a time range from gps_data2 and a marker for every capture of that range, which opens the bands and the CTD
+and GPS values of the capture. Everything is drawn as SVG in Web Mercator coordinates, over the offline tiles of the
+backend when it has some (see tiles.rs of the backend), so the page needs nothing from the internet; dragging pans
+the view and the wheel zooms it.
+*/
+
+// size of the map in SVG units
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 500.0;
+// pixels of the whole world at zoom 0, as for web map tiles
+const TILE_SIZE: f64 = 256.0;
+const MIN_ZOOM: f64 = 2.0;
+const MAX_ZOOM: f64 = 20.0;
+// tiles drawn at once, beyond that the view is left without them
+const MAX_TILES: u32 = 64;
+// points of the track per field, the window of the query grows with the range
+const TRACK_POINTS: i64 = 2000;
+
+// centre of the view in Web Mercator coordinates (0 to 1 over the world, y growing southwards) and its zoom level
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MapView {
+    pub x: f64,
+    pub y: f64,
+    pub zoom: f64,
+}
+
+impl MapView {
+    fn scale(&self) -> f64 {
+        TILE_SIZE * self.zoom.exp2()
+    }
+
+    // SVG coordinates of a point in Web Mercator coordinates
+    pub fn to_screen(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        ((x - self.x) * self.scale() + WIDTH / 2.0, (y - self.y) * self.scale() + HEIGHT / 2.0)
+    }
+
+    // view showing every point, zoomed in to a street level when there is only one
+    fn fit(points: &[(f64, f64)]) -> Option<Self> {
+        let (first, rest) = points.split_first()?;
+        let (mut min, mut max) = (*first, *first);
+        for &(x, y) in rest {
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+        }
+        let (dx, dy) = (max.0 - min.0, max.1 - min.1);
+        let zoom = if dx <= 0.0 && dy <= 0.0 {
+            16.0
+        } else {
+            // a margin of 10% on every side
+            ((WIDTH / dx).min(HEIGHT / dy) * 0.8 / TILE_SIZE).log2()
+        };
+        Some(MapView { x: (min.0 + max.0) / 2.0, y: (min.1 + max.1) / 2.0, zoom: zoom.clamp(MIN_ZOOM, MAX_ZOOM) })
+    }
+}
+
+impl Default for MapView {
+    fn default() -> Self {
+        MapView { x: 0.5, y: 0.5, zoom: MIN_ZOOM }
+    }
+}
+
+// Web Mercator coordinates of a position
+pub fn project(lat: f64, lon: f64) -> (f64, f64) {
+    let lat = lat.clamp(-85.0511, 85.0511).to_radians();
+    ((lon + 180.0) / 360.0, (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0)
+}
+
+// tiles of the tile set covering the view, at the zoom level of the view within the levels of the tile set
+fn visible_tiles(v: &MapView, tiles: &TileSetInfo) -> Vec<(u8, u32, u32)> {
+    let z = (v.zoom.round().max(0.0) as u8).clamp(tiles.min_zoom, tiles.max_zoom);
+    let n = 1u32 << z;
+    let (half_width, half_height) = (WIDTH / 2.0 / v.scale(), HEIGHT / 2.0 / v.scale());
+    let mut west_north = (v.x - half_width, v.y - half_height);
+    let mut east_south = (v.x + half_width, v.y + half_height);
+    if let Some([west, south, east, north]) = tiles.bounds {
+        let (bounds_west_north, bounds_east_south) = (project(north, west), project(south, east));
+        west_north = (west_north.0.max(bounds_west_north.0), west_north.1.max(bounds_west_north.1));
+        east_south = (east_south.0.min(bounds_east_south.0), east_south.1.min(bounds_east_south.1));
+    }
+    let tile = |value: f64| (value * n as f64).floor().clamp(0.0, (n - 1) as f64) as u32;
+    if west_north.0 > east_south.0 || west_north.1 > east_south.1 || east_south.0 < 0.0 || east_south.1 < 0.0 {
+        return Vec::new();
+    }
+    let (x0, x1, y0, y1) = (tile(west_north.0), tile(east_south.0), tile(west_north.1), tile(east_south.1));
+    if (x1 - x0 + 1) * (y1 - y0 + 1) > MAX_TILES {
+        return Vec::new();
+    }
+    (x0..=x1).flat_map(|x| (y0..=y1).map(move |y| (z, x, y))).collect()
+}
+
+// datetime-local value as a UTC time, empty values take the default
+fn input_time(value: &str, default: DateTime<Utc>) -> DateTime<Utc> {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M").map(|t| t.and_utc()).unwrap_or(default)
+}
+
+// positions of the track, pairing the latitude and longitude samples of the same window
+fn track_points(points: Vec<DataPoint>) -> Vec<(f64, f64)> {
+    let mut positions: BTreeMap<i64, (Option<f64>, Option<f64>)> = BTreeMap::new();
+    for point in points {
+        let position = positions.entry(point.epochtime).or_default();
+        match point.field.as_str() {
+            "latitude" => position.0 = Some(point.value),
+            "longitude" => position.1 = Some(point.value),
+            _ => {}
+        }
+    }
+    positions
+        .into_values()
+        .filter_map(|(lat, lon)| Some(project(lat?, lon?)))
+        .collect()
+}
+
+#[component]
+pub fn Map() -> impl IntoView {
+    let cameras = use_cameras();
+    let camera = RwSignal::new(String::new());
+    let start_time = RwSignal::new(String::new());
+    let end_time = RwSignal::new(String::new());
+    let track = RwSignal::new(Vec::<(f64, f64)>::new());
+    let captures = RwSignal::new(Vec::<CaptureRecord>::new());
+    let selected = RwSignal::new(None::<CaptureRecord>);
+    let live = RwSignal::new(None::<RTDataPoint>);
+    let tile_set = RwSignal::new(None::<TileSetInfo>);
+    let view = RwSignal::new(MapView::default());
+    let follow = RwSignal::new(false);
+    let status_message = RwSignal::new(String::new());
+    // last pointer position while dragging
+    let drag = RwSignal::new(None::<(i32, i32)>);
+
+    // live position, the connection is dropped when the page is left
+    let (abort_handle, abort_registration) = AbortHandle::new_pair();
+    spawn_local(async move {
+        let _ = Abortable::new(crate::follow_live_data(live), abort_registration).await;
+    });
+    on_cleanup(move || abort_handle.abort());
+
+    // offline tiles, the map is drawn on plain water without them
+    spawn_local(async move {
+        if let Ok(tiles) = api::tile_set().await {
+            tile_set.set(tiles);
+        }
+    });
+
+    // live position and heading of the boat in Web Mercator coordinates
+    let boat = move || {
+        let data = live.get()?;
+        let (lat, lon) = (data.latitude?, data.longitude?);
+        let stale = lat.stale || lon.stale;
+        Some((project(lat.value, lon.value), data.cog.map(|c| c.value), stale))
+    };
+
+    Effect::new(move |_| {
+        if follow.get() {
+            if let Some(((x, y), _, _)) = boat() {
+                view.update(|v| {
+                    v.x = x;
+                    v.y = y;
+                });
+            }
+        }
+    });
+
+    let fit = move || {
+        let mut points = track.get_untracked();
+        points.extend(captures.get_untracked().iter().filter_map(|c| Some(project(c.lat?, c.lon?))));
+        if points.is_empty() {
+            points.extend(untrack(boat).map(|(point, _, _)| point));
+        }
+        if let Some(fitted) = MapView::fit(&points) {
+            follow.set(false);
+            view.set(fitted);
+        }
+    };
+
+    // track and captures of the range, the last 24 hours when left empty; datetime-local values are UTC
+    let load = move || {
+        let now = Utc::now();
+        let start = input_time(&start_time.get_untracked(), now - TimeDelta::days(1));
+        let stop = input_time(&end_time.get_untracked(), now);
+        if start >= stop {
+            status_message.set("The start must be before the end.".to_string());
+            return;
+        }
+        let window = ((stop - start).num_seconds() / TRACK_POINTS).max(1);
+        let track_params = DataParams {
+            start: Some(start.to_rfc3339_opts(SecondsFormat::Secs, true)),
+            stop: Some(stop.to_rfc3339_opts(SecondsFormat::Secs, true)),
+            measurements: Some("gps_data2".to_string()),
+            fields: Some("latitude,longitude".to_string()),
+            window: Some(format!("{}s", window)),
+            aggregate: Some("last".to_string()),
+        };
+        let selected_camera = camera.get_untracked();
+        let capture_params = CaptureMetadataParams {
+            start: Some(start.to_rfc3339_opts(SecondsFormat::Secs, true)),
+            stop: Some(stop.to_rfc3339_opts(SecondsFormat::Secs, true)),
+            camera: (!selected_camera.is_empty()).then_some(selected_camera),
+            ..Default::default()
+        };
+        status_message.set("Loading track and captures...".to_string());
+        selected.set(None);
+        spawn_local(async move {
+            let (points, records) = futures::join!(api::data(&track_params), api::capture_records(&capture_params));
+            let points = points.map(track_points);
+            match (&points, &records) {
+                (Ok(points), Ok(records)) => status_message.set(format!(
+                    "{} track points, {} captures ({} without a position).",
+                    points.len(), records.len(), records.iter().filter(|r| r.lat.is_none() || r.lon.is_none()).count()
+                )),
+                (Err(e), _) | (_, Err(e)) => status_message.set(e.to_string()),
+            }
+            track.set(points.unwrap_or_default());
+            captures.set(records.unwrap_or_default());
+            fit();
+        });
+    };
+
+    let zoom_by = move |delta: f64| {
+        view.update(|v| v.zoom = (v.zoom + delta).clamp(MIN_ZOOM, MAX_ZOOM));
+    };
+
+    // moves the view by a number of SVG units
+    let pan = move |dx: f64, dy: f64| {
+        follow.set(false);
+        view.update(|v| {
+            let scale = v.scale();
+            v.x -= dx / scale;
+            v.y -= dy / scale;
+        });
+    };
+
+    let track_path = move || {
+        let v = view.get();
+        track.get().into_iter().map(|p| {
+            let (x, y) = v.to_screen(p);
+            format!("{:.1},{:.1}", x, y)
+        }).collect::<Vec<_>>().join(" ")
+    };
+
+    // index of the camera of a capture among the configured ones, for its marker colour
+    let camera_index = move |id: &str| cameras.get().iter().position(|c| c.id == id).unwrap_or(0);
+
+    // bands of the camera of the selected capture
+    let selected_bands = move || {
+        let capture = selected.get()?;
+        let camera = cameras.get().into_iter().find(|c| c.id == capture.camera)?;
+        Some((capture, camera.bands))
+    };
+
+    view! {
+        <div class="component-container map">
+            <h2>"Map"</h2>
+            <div class="form-group">
+                <label>"Captures of:"</label>
+                <CameraSelect camera=camera />
+            </div>
+            <div class="form-group">
+                <label>
+                    "Start:"
+                    <input type="datetime-local"
+                        on:input=move |e| start_time.set(event_target_value(&e)) />
+                </label>
+                <label>
+                    "End:"
+                    <input type="datetime-local"
+                        on:input=move |e| end_time.set(event_target_value(&e)) />
+                </label>
+                <button on:click=move |_| load() class="fetch-button">"Load Track"</button>
+            </div>
+            <div class="form-group">
+                <button on:click=move |_| zoom_by(1.0)>"+"</button>
+                <button on:click=move |_| zoom_by(-1.0)>"-"</button>
+                <button on:click=move |_| fit()>"Fit"</button>
+                <label>
+                    <input type="checkbox" prop:checked=move || follow.get()
+                        on:change=move |e| follow.set(event_target_checked(&e)) />
+                    "Follow the boat"
+                </label>
+            </div>
+            <p class="status-message">{move || status_message.get()}</p>
+
+            <svg class="map-view" viewBox=format!("0 0 {} {}", WIDTH, HEIGHT)
+                on:mousedown=move |e| drag.set(Some((e.client_x(), e.client_y())))
+                on:mousemove=move |e| {
+                    if let Some((x, y)) = drag.get_untracked() {
+                        pan((e.client_x() - x) as f64, (e.client_y() - y) as f64);
+                        drag.set(Some((e.client_x(), e.client_y())));
+                    }
+                }
+                on:mouseup=move |_| drag.set(None)
+                on:mouseleave=move |_| drag.set(None)
+                on:wheel=move |e| {
+                    e.prevent_default();
+                    zoom_by(if e.delta_y() < 0.0 { 0.5 } else { -0.5 });
+                }>
+                <rect class="map-water" width=WIDTH height=HEIGHT />
+                {move || tile_set.get().map(|tiles| {
+                    let v = view.get();
+                    visible_tiles(&v, &tiles).into_iter().map(|(z, x, y)| {
+                        let n = (1u32 << z) as f64;
+                        let (left, top) = v.to_screen((x as f64 / n, y as f64 / n));
+                        // a little larger than the tile so no seam shows between them
+                        let size = v.scale() / n + 0.5;
+                        view! {
+                            <image href=api::tile_url(z, x, y) x=left y=top width=size height=size
+                                preserveAspectRatio="none" />
+                        }
+                    }).collect_view()
+                })}
+                <polyline class="map-track" points=track_path />
+                {move || {
+                    let v = view.get();
+                    let current = selected.get().map(|s| s.capture);
+                    captures.get().into_iter().filter_map(|capture| {
+                        let (x, y) = v.to_screen(project(capture.lat?, capture.lon?));
+                        let class = format!(
+                            "map-capture map-camera-{}{}",
+                            camera_index(&capture.camera) % 4,
+                            if current.as_deref() == Some(capture.capture.as_str()) { " map-selected" } else { "" }
+                        );
+                        let label = format!("{} at {}", capture.capture, capture.time);
+                        Some(view! {
+                            <circle class=class cx=x cy=y r="5"
+                                on:mousedown=|e| e.stop_propagation()
+                                on:click=move |_| selected.set(Some(capture.clone()))>
+                                <title>{label}</title>
+                            </circle>
+                        })
+                    }).collect_view()
+                }}
+                {move || boat().map(|(point, cog, stale)| {
+                    let (x, y) = view.get().to_screen(point);
+                    let class = if stale { "map-boat map-stale" } else { "map-boat" };
+                    // a triangle pointing along the course, a circle when it is unknown
+                    match cog {
+                        Some(cog) => view! {
+                            <polygon class=class points="0,-12 8,8 0,3 -8,8"
+                                transform=format!("translate({:.1} {:.1}) rotate({:.1})", x, y, cog) />
+                        }.into_any(),
+                        None => view! { <circle class=class cx=x cy=y r="7" /> }.into_any(),
+                    }
+                })}
+            </svg>
+            <p class="map-scale">{move || {
+                let v = view.get();
+                let attribution = tile_set.get().and_then(|t| t.attribution).map(|a| format!(" | {}", a)).unwrap_or_default();
+                format!("Zoom {:.1}, centre {:.5}, {:.5}{}", v.zoom, latitude(v.y), v.x * 360.0 - 180.0, attribution)
+            }}</p>
+
+            {move || selected_bands().map(|(capture, bands)| view! {
+                <div class="gallery-detail">
+                    <h3>{format!("{} at {}", capture.capture, capture.time)}</h3>
+                    <button on:click=move |_| selected.set(None)>"Close"</button>
+                    <CaptureBands capture_id=capture.capture.clone() bands=bands />
+                    <CaptureValues capture=capture />
+                </div>
+            })}
+        </div>
+    }
+}
+
+// latitude of a Web Mercator y
+fn latitude(y: f64) -> f64 {
+    (PI * (1.0 - 2.0 * y)).sinh().atan().to_degrees()
+}
+
+// GPS and CTD values of a capture, N/A when no sample was close enough
+#[component]
+fn CaptureValues(capture: CaptureRecord) -> impl IntoView {
+    let value = |value: Option<f64>, unit: &str| value.map(|v| format!("{}{}", v, unit)).unwrap_or("N/A".to_string());
+    let rows = [
+        ("File", capture.file.clone()),
+        ("Latitude", value(capture.lat, "")),
+        ("Longitude", value(capture.lon, "")),
+        ("COG", value(capture.cog, "°")),
+        ("SOG", value(capture.sog, " m/s")),
+        ("Depth", value(capture.depth, " m")),
+        ("Temperature", value(capture.temperature, " °C")),
+        ("Pressure", value(capture.pressure, " dbar")),
+        ("Conductivity", value(capture.conductivity, " mS/cm")),
+        ("Salinity", value(capture.salinity, "")),
+        ("pH", value(capture.ph, "")),
+        ("Oxygen Dissolved %", value(capture.oxygen_percentage, "")),
+        ("Oxygen Dissolved (ppm)", value(capture.oxygen_ppm, " mg/l")),
+    ];
+    view! {
+        <table class="map-values">
+            {rows.into_iter().map(|(label, value)| view! { <tr><td>{label}</td><td>{value}</td></tr> }).collect_view()}
+        </table>
+    }
+}
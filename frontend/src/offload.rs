@@ -0,0 +1,182 @@
+use brig_types::{OffloadRequest, OffloadState, OffloadStatus};
+use futures::future::{AbortHandle, Abortable};
+use gloo::timers::future::TimeoutFuture;
+use leptos::*;
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+
+use crate::api;
+use crate::auth::{is_operator, OPERATOR_ONLY};
+use crate::camera::{camera_name, use_cameras, CameraSelect};
+
+/*
+This component copies the captures of a camera from its SD card to the archive on the Pi.
+The operator picks a camera and a time range, the backend downloads and verifies every band of the captures
+logged in that range; starting the same range again only fetches what is still missing.
+The progress of the last job of every camera is polled while the page is open.
+*/
+
+// how often the job list is refreshed
+const POLL_MS: u32 = 2000;
+
+#[component]
+pub fn Offload() -> impl IntoView {
+    let jobs = RwSignal::new(Vec::<OffloadStatus>::new());
+    let camera = RwSignal::new(String::new());
+    let cameras = use_cameras();
+    let start_time = RwSignal::new(String::new());
+    let end_time = RwSignal::new(String::new());
+    let status_message = RwSignal::new(String::new());
+
+    // only operators may start and cancel offloads
+    let operator = is_operator();
+    let locked = move || !operator.get();
+    let title = move || locked().then_some(OPERATOR_ONLY);
+
+    // polling stops when the page is left
+    let (abort_handle, abort_registration) = AbortHandle::new_pair();
+    spawn_local(async move {
+        let poll = async move {
+            loop {
+                if let Ok(list) = api::offloads().await {
+                    jobs.set(list);
+                }
+                TimeoutFuture::new(POLL_MS).await;
+            }
+        };
+        let _ = Abortable::new(poll, abort_registration).await;
+    });
+    on_cleanup(move || abort_handle.abort());
+
+    // datetime-local values are sent as UTC, like the CSV download
+    let start_offload = move |_| {
+        let (selected, start, end) = (camera.get(), start_time.get(), end_time.get());
+        if selected.is_empty() {
+            status_message.set("Select a camera.".to_string());
+            return;
+        }
+        if start.is_empty() || end.is_empty() {
+            status_message.set("Select a start and an end.".to_string());
+            return;
+        }
+        let request = OffloadRequest {
+            start: format!("{}:00Z", start),
+            stop: format!("{}:00Z", end),
+        };
+        spawn_local(async move {
+            match api::offload_start(&selected, &request).await {
+                Ok(job) => {
+                    let name = camera_name(&cameras.get_untracked(), &job.camera);
+                    status_message.set(format!("Copying {} captures of {}.", job.captures_total, name));
+                    jobs.update(|list| replace_job(list, job));
+                }
+                Err(e) => status_message.set(e.to_string()),
+            }
+        });
+    };
+
+    let cancel_offload = move |camera: String| {
+        spawn_local(async move {
+            if let Ok(job) = api::offload_cancel(&camera).await {
+                let name = camera_name(&cameras.get_untracked(), &job.camera);
+                status_message.set(format!("The offload of {} stops after the current capture.", name));
+            }
+        });
+    };
+
+    view! {
+        <div class="component-container offload">
+            <h2>"Offload Captures"</h2>
+            <p>"Copies every band of the captures logged in a range from the camera to the archive on the Pi."</p>
+            <div class="form-group">
+                <label>
+                    "Camera:"
+                    <CameraSelect camera=camera />
+                </label>
+            </div>
+            <div class="form-group">
+                <label>
+                    "Start:"
+                    <input type="datetime-local"
+                        on:input=move |e| start_time.set(event_target_value(&e)) />
+                </label>
+            </div>
+            <div class="form-group">
+                <label>
+                    "End:"
+                    <input type="datetime-local"
+                        on:input=move |e| end_time.set(event_target_value(&e)) />
+                </label>
+            </div>
+            <div class="form-group">
+                <button on:click=start_offload disabled=locked title=title>"Start Offload"</button>
+                <p class="status-message">{move || status_message.get()}</p>
+            </div>
+            <div class="offload-jobs">
+                {move || jobs.get().into_iter().map(|job| view! {
+                    <OffloadJob
+                        name=camera_name(&cameras.get(), &job.camera)
+                        job=job
+                        locked=Signal::derive(locked)
+                        on_cancel=Callback::new(cancel_offload)
+                    />
+                }).collect_view()}
+            </div>
+        </div>
+    }
+}
+
+// puts a job in place of the previous one of its camera
+fn replace_job(list: &mut Vec<OffloadStatus>, job: OffloadStatus) {
+    list.retain(|j| j.camera != job.camera);
+    list.push(job);
+    list.sort_by(|a, b| a.camera.cmp(&b.camera));
+}
+
+// progress of one camera
+#[component]
+fn OffloadJob(job: OffloadStatus, name: String, #[prop(into)] locked: Signal<bool>, on_cancel: Callback<String>) -> impl IntoView {
+    let percent = if job.captures_total == 0 { 100 } else { job.captures_done * 100 / job.captures_total };
+    let state = match job.state {
+        OffloadState::Running => "running",
+        OffloadState::Done => "done",
+        OffloadState::Cancelled => "cancelled",
+        OffloadState::Failed => "failed",
+    };
+    let running = job.state == OffloadState::Running;
+    let camera = job.camera.clone();
+    view! {
+        <div class=format!("offload-job offload-{}", state)>
+            <h3>{format!("{} ({})", name, state)}</h3>
+            <p>{format!("{} to {}, started {} by {}", job.start, job.stop, job.started, job.user)}</p>
+            <progress max="100" value=percent.to_string()></progress>
+            <p>
+                {format!(
+                    "{} of {} captures, {} files copied ({:.1} MB), {} already archived, {} failed",
+                    job.captures_done,
+                    job.captures_total,
+                    job.files_downloaded,
+                    job.bytes_downloaded as f64 / 1e6,
+                    job.files_skipped,
+                    job.files_failed,
+                )}
+            </p>
+            <Show when=move || running>
+                <button
+                    class="cancel-button"
+                    disabled=move || locked.get()
+                    title=move || locked.get().then_some(OPERATOR_ONLY)
+                    on:click={
+                        let camera = camera.clone();
+                        move |_| on_cancel.run(camera.clone())
+                    }
+                >
+                    "Cancel"
+                </button>
+            </Show>
+            <ul class="offload-errors">
+                {job.errors.iter().map(|e| view! { <li>{e.clone()}</li> }).collect_view()}
+            </ul>
+        </div>
+    }
+}
@@ -1,5 +1,8 @@
+use gloo::timers::callback::Timeout;
 use leptos::*;
 use leptos::prelude::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
 
 #[component]
 pub fn PopUp(#[prop(into)] show_popup: RwSignal<bool>,
@@ -71,4 +74,56 @@ pub fn ConfirmationPopup(
             </div>
         </Show>
     }
-}
\ No newline at end of file
+}
+
+// how long an error toast stays on screen
+const TOAST_MS: u32 = 6000;
+
+#[derive(Clone, Debug, PartialEq)]
+struct Toast {
+    id: u64,
+    message: String,
+}
+
+// toasts live outside of any component so that API calls can raise them from spawned tasks
+fn toasts() -> &'static ArcRwSignal<Vec<Toast>> {
+    static TOASTS: OnceLock<ArcRwSignal<Vec<Toast>>> = OnceLock::new();
+    TOASTS.get_or_init(|| ArcRwSignal::new(Vec::new()))
+}
+
+// shows an error message in the corner of the screen for a few seconds,
+// a message already on screen is not repeated (e.g. the status poll while the backend is down)
+pub fn toast_error(message: String) {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    let toasts = toasts();
+    if toasts.with_untracked(|t| t.iter().any(|toast| toast.message == message)) {
+        return;
+    }
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    toasts.update(|t| t.push(Toast { id, message }));
+    Timeout::new(TOAST_MS, move || dismiss(id)).forget();
+}
+
+fn dismiss(id: u64) {
+    toasts().update(|t| t.retain(|toast| toast.id != id));
+}
+
+// renders the pending toasts, mounted once in App
+#[component]
+pub fn Toaster() -> impl IntoView {
+    let toasts = toasts().clone();
+    view! {
+        <div class="toaster">
+            <For
+                each=move || toasts.get()
+                key=|toast| toast.id
+                let:toast
+            >
+                <div class="toast toast-error">
+                    <span>{toast.message.clone()}</span>
+                    <button class="close-button" on:click=move |_| dismiss(toast.id)>"×"</button>
+                </div>
+            </For>
+        </div>
+    }
+}